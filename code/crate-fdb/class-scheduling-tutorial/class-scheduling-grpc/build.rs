@@ -0,0 +1,16 @@
+use std::env;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // Use the vendored `protoc`, so that building does not need
+    // `protoc` to be installed.
+    env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+
+    // The generated `connect` needs `TryInto` from the 2021 prelude, so
+    // we leave it out and connect with an `Endpoint` instead.
+    tonic_prost_build::configure()
+        .build_transport(false)
+        .compile_protos(&["proto/class_scheduling.proto"], &["proto"])?;
+
+    Ok(())
+}
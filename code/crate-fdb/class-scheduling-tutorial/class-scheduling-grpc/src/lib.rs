@@ -0,0 +1,287 @@
+use class_scheduling::seats::{
+    decode_seats_token, encode_seats_token, wait_for_seat_changes, SEAT_CHANGES_TRIMMED,
+};
+use class_scheduling::{
+    available_classes, dropout, error_message, get_class_roster, get_student_schedule, signup,
+    switch_classes, Class, NewClass, OldClass, Student, Timestamp, ALREADY_SIGNED_UP,
+    INCOMPLETE_BUNDLE, KEY_CONVERTION_ERROR, LOTTERY_CLOSED, NOT_SIGNED_UP, NO_REMAINING_SEATS,
+    NO_SUCH_CLASS, PRIORITY_REGISTRATION_ONLY, REGISTRATION_CLOSED, TIME_CONFLICT,
+    TOO_MANY_CLASSES, VALUE_CONVERTION_ERROR,
+};
+
+use fdb::database::FdbDatabase;
+use fdb::error::FdbError;
+
+use tonic::metadata::MetadataValue;
+use tonic::{Code, Request, Response, Status};
+
+use tokio::sync::mpsc;
+
+use tokio_stream::wrappers::ReceiverStream;
+
+pub mod proto {
+    tonic::include_proto!("class_scheduling");
+}
+
+use proto::class_scheduling_server::ClassScheduling;
+use proto::{
+    EnrollmentRequest, EnrollmentResponse, GetRosterRequest, GetRosterResponse, GetScheduleRequest,
+    GetScheduleResponse, ListClassesRequest, ListClassesResponse, SeatChange, SeatChanges,
+    SwitchClassesRequest, WatchSeatChangesRequest,
+};
+
+// gRPC status codes for the errors returned by our transactions. Any
+// other error is `INTERNAL`.
+const ERROR_STATUS_CODES: [(i32, Code); 13] = [
+    (NO_REMAINING_SEATS, Code::ResourceExhausted),
+    (ALREADY_SIGNED_UP, Code::AlreadyExists),
+    (TOO_MANY_CLASSES, Code::FailedPrecondition),
+    (NOT_SIGNED_UP, Code::FailedPrecondition),
+    (TIME_CONFLICT, Code::FailedPrecondition),
+    (INCOMPLETE_BUNDLE, Code::InvalidArgument),
+    (REGISTRATION_CLOSED, Code::PermissionDenied),
+    (PRIORITY_REGISTRATION_ONLY, Code::PermissionDenied),
+    (LOTTERY_CLOSED, Code::PermissionDenied),
+    (NO_SUCH_CLASS, Code::NotFound),
+    (SEAT_CHANGES_TRIMMED, Code::OutOfRange),
+    (KEY_CONVERTION_ERROR, Code::Internal),
+    (VALUE_CONVERTION_ERROR, Code::Internal),
+];
+
+// Metadata key carrying the error code of the transaction.
+pub const SCHEDULING_ERROR_CODE: &str = "scheduling-error-code";
+
+fn into_status(err: FdbError) -> Status {
+    let code = ERROR_STATUS_CODES
+        .iter()
+        .find(|(code, _)| *code == err.code())
+        .map(|(_, status_code)| *status_code)
+        .unwrap_or(Code::Internal);
+
+    let mut status = Status::new(code, error_message(&err));
+
+    status
+        .metadata_mut()
+        .insert(SCHEDULING_ERROR_CODE, MetadataValue::from(err.code()));
+
+    status
+}
+
+pub struct ClassSchedulingService {
+    db: FdbDatabase,
+}
+
+impl ClassSchedulingService {
+    pub fn new(db: FdbDatabase) -> ClassSchedulingService {
+        ClassSchedulingService { db }
+    }
+}
+
+#[tonic::async_trait]
+impl ClassScheduling for ClassSchedulingService {
+    async fn list_classes(
+        &self,
+        _request: Request<ListClassesRequest>,
+    ) -> Result<Response<ListClassesResponse>, Status> {
+        let class_names = self
+            .db
+            .run(|tr| async move { available_classes(&tr).await })
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(ListClassesResponse {
+            class_names: class_names
+                .into_iter()
+                .map(|Class(class_inner)| class_inner)
+                .collect(),
+        }))
+    }
+
+    async fn signup(
+        &self,
+        request: Request<EnrollmentRequest>,
+    ) -> Result<Response<EnrollmentResponse>, Status> {
+        let enrollment_request = request.into_inner();
+
+        let enrollment_request_ref = &enrollment_request;
+
+        self.db
+            .run(|tr| async move {
+                signup(
+                    &tr,
+                    Student(enrollment_request_ref.student.clone()),
+                    Class(enrollment_request_ref.class_name.clone()),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(EnrollmentResponse {}))
+    }
+
+    async fn dropout(
+        &self,
+        request: Request<EnrollmentRequest>,
+    ) -> Result<Response<EnrollmentResponse>, Status> {
+        let enrollment_request = request.into_inner();
+
+        let enrollment_request_ref = &enrollment_request;
+
+        self.db
+            .run(|tr| async move {
+                dropout(
+                    &tr,
+                    Student(enrollment_request_ref.student.clone()),
+                    Class(enrollment_request_ref.class_name.clone()),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(EnrollmentResponse {}))
+    }
+
+    async fn switch_classes(
+        &self,
+        request: Request<SwitchClassesRequest>,
+    ) -> Result<Response<EnrollmentResponse>, Status> {
+        let switch_classes_request = request.into_inner();
+
+        let switch_classes_request_ref = &switch_classes_request;
+
+        self.db
+            .run(|tr| async move {
+                switch_classes(
+                    &tr,
+                    Student(switch_classes_request_ref.student.clone()),
+                    OldClass(Class(switch_classes_request_ref.old_class_name.clone())),
+                    NewClass(Class(switch_classes_request_ref.new_class_name.clone())),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(EnrollmentResponse {}))
+    }
+
+    async fn get_schedule(
+        &self,
+        request: Request<GetScheduleRequest>,
+    ) -> Result<Response<GetScheduleResponse>, Status> {
+        let get_schedule_request = request.into_inner();
+
+        let student_ref = &get_schedule_request.student;
+
+        let class_names = self
+            .db
+            .run(|tr| async move { get_student_schedule(&tr, Student(student_ref.clone())).await })
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(GetScheduleResponse {
+            class_names: class_names
+                .into_iter()
+                .map(|Class(class_inner)| class_inner)
+                .collect(),
+        }))
+    }
+
+    async fn get_roster(
+        &self,
+        request: Request<GetRosterRequest>,
+    ) -> Result<Response<GetRosterResponse>, Status> {
+        let get_roster_request = request.into_inner();
+
+        let class_name_ref = &get_roster_request.class_name;
+
+        let students = self
+            .db
+            .run(|tr| async move { get_class_roster(&tr, Class(class_name_ref.clone())).await })
+            .await
+            .map_err(into_status)?;
+
+        Ok(Response::new(GetRosterResponse {
+            students: students
+                .into_iter()
+                .map(|Student(student_inner)| student_inner)
+                .collect(),
+        }))
+    }
+
+    type WatchSeatChangesStream = ReceiverStream<Result<SeatChanges, Status>>;
+
+    async fn watch_seat_changes(
+        &self,
+        request: Request<WatchSeatChangesRequest>,
+    ) -> Result<Response<Self::WatchSeatChangesStream>, Status> {
+        let watch_seat_changes_request = request.into_inner();
+
+        let class_names = watch_seat_changes_request
+            .class_names
+            .into_iter()
+            .map(Class)
+            .collect::<Vec<Class>>();
+
+        let mut versions = if watch_seat_changes_request.resume_token.is_empty() {
+            None
+        } else {
+            Some(
+                decode_seats_token(&watch_seat_changes_request.resume_token, class_names.len())
+                    .ok_or_else(|| Status::invalid_argument("invalid resume token"))?,
+            )
+        };
+
+        let (seat_changes_sender, seat_changes_recv) = mpsc::channel(16);
+
+        let db = self.db.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let res = tokio::select! {
+                    res = wait_for_seat_changes(&db, &class_names, versions.as_deref()) => res,
+                    // Client has gone away.
+                    _ = seat_changes_sender.closed() => return,
+                };
+
+                let (seat_changes, new_versions) = match res {
+                    Ok(res) => res,
+                    Err(err) => {
+                        let _ = seat_changes_sender.send(Err(into_status(err))).await;
+                        return;
+                    }
+                };
+
+                let seat_changes = seat_changes
+                    .into_iter()
+                    .map(|(Class(class_inner), seats_left)| SeatChange {
+                        class_name: class_inner,
+                        seats_left: seats_left.into(),
+                    })
+                    .collect();
+
+                let resume_token = encode_seats_token(&new_versions);
+
+                if seat_changes_sender
+                    .send(Ok(SeatChanges {
+                        seat_changes,
+                        resume_token,
+                    }))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+
+                versions = Some(new_versions);
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(seat_changes_recv)))
+    }
+}
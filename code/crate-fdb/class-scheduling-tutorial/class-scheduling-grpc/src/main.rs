@@ -0,0 +1,75 @@
+use class_scheduling_grpc::proto::class_scheduling_server::ClassSchedulingServer;
+use class_scheduling_grpc::ClassSchedulingService;
+
+use fdb::database::DatabaseOption;
+
+use clap::Parser;
+
+use tonic::transport::Server;
+
+use tracing::info;
+
+use tokio::runtime::Runtime;
+
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+
+/// gRPC service for class scheduling.
+#[derive(Parser, Debug)]
+#[command(name = "class-scheduling-grpc")]
+struct Cli {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    listen: SocketAddr,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let fdb_cluster_file = env::var("FDB_CLUSTER_FILE").expect("FDB_CLUSTER_FILE not defined!");
+
+    unsafe {
+        fdb::select_api_version(fdb::FDB_API_VERSION as i32);
+        fdb::start_network();
+    }
+
+    let fdb_database = fdb::open_database(fdb_cluster_file)?;
+
+    // 60,000 ms = 1 minute
+    fdb_database.set_option(DatabaseOption::TransactionTimeout(60000))?;
+    fdb_database.set_option(DatabaseOption::TransactionRetryLimit(100))?;
+
+    let rt = Runtime::new()?;
+
+    let cloned_fdb_database = fdb_database.clone();
+
+    rt.block_on(async {
+        let fdb_database = cloned_fdb_database;
+
+        info!(listen = %cli.listen, "serving");
+
+        Server::builder()
+            .add_service(ClassSchedulingServer::new(ClassSchedulingService::new(
+                fdb_database,
+            )))
+            .serve_with_shutdown(cli.listen, async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await?;
+
+        Result::<(), Box<dyn Error>>::Ok(())
+    })?;
+
+    drop(fdb_database);
+
+    unsafe {
+        fdb::stop_network();
+    }
+
+    Ok(())
+}
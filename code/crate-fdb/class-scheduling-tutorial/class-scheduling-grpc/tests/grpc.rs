@@ -0,0 +1,207 @@
+// These tests start `fdbserver`, which needs to be installed. Run them
+// with `cargo test -- --ignored`. Every test sets up the classes with
+// `init`, so each starts a cluster of its own.
+
+use class_scheduling::{init, ALREADY_SIGNED_UP};
+use class_scheduling_grpc::proto::class_scheduling_client::ClassSchedulingClient;
+use class_scheduling_grpc::proto::class_scheduling_server::ClassSchedulingServer;
+use class_scheduling_grpc::proto::{
+    EnrollmentRequest, GetRosterRequest, GetScheduleRequest, SwitchClassesRequest,
+    WatchSeatChangesRequest,
+};
+use class_scheduling_grpc::{ClassSchedulingService, SCHEDULING_ERROR_CODE};
+
+use fdb::database::FdbDatabase;
+
+use test_support::TestCluster;
+
+use tonic::transport::{Channel, Endpoint, Server};
+use tonic::Code;
+
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+
+async fn start_server(db: FdbDatabase) -> ClassSchedulingClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(
+        Server::builder()
+            .add_service(ClassSchedulingServer::new(ClassSchedulingService::new(db)))
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+
+    let channel = Endpoint::from_shared(format!("http://{}", addr))
+        .unwrap()
+        .connect()
+        .await
+        .unwrap();
+
+    ClassSchedulingClient::new(channel)
+}
+
+fn enrollment_request(student: &str, class_name: &str) -> EnrollmentRequest {
+    EnrollmentRequest {
+        student: student.to_string(),
+        class_name: class_name.to_string(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs fdbserver"]
+async fn enrollment() {
+    let cluster = TestCluster::start().unwrap();
+
+    let db = cluster.database();
+
+    init(&db).await.unwrap();
+
+    let mut client = start_server(db).await;
+
+    client
+        .signup(enrollment_request("s1", "10:00 chem 201"))
+        .await
+        .unwrap();
+
+    let status = client
+        .signup(enrollment_request("s1", "10:00 chem 201"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(status.code(), Code::AlreadyExists);
+    assert_eq!(
+        status.metadata().get(SCHEDULING_ERROR_CODE).unwrap(),
+        ALREADY_SIGNED_UP.to_string().as_str()
+    );
+
+    let roster = client
+        .get_roster(GetRosterRequest {
+            class_name: "10:00 chem 201".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(roster.students, vec!["s1".to_string()]);
+
+    client
+        .switch_classes(SwitchClassesRequest {
+            student: "s1".to_string(),
+            old_class_name: "10:00 chem 201".to_string(),
+            new_class_name: "11:00 chem 201".to_string(),
+        })
+        .await
+        .unwrap();
+
+    let schedule = client
+        .get_schedule(GetScheduleRequest {
+            student: "s1".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert_eq!(schedule.class_names, vec!["11:00 chem 201".to_string()]);
+
+    client
+        .dropout(enrollment_request("s1", "11:00 chem 201"))
+        .await
+        .unwrap();
+
+    let schedule = client
+        .get_schedule(GetScheduleRequest {
+            student: "s1".to_string(),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    assert!(schedule.class_names.is_empty());
+}
+
+#[tokio::test]
+#[ignore = "needs fdbserver"]
+async fn seat_changes() {
+    let cluster = TestCluster::start().unwrap();
+
+    let db = cluster.database();
+
+    init(&db).await.unwrap();
+
+    let mut client = start_server(db).await;
+
+    let watch_seat_changes_request = |resume_token: String| WatchSeatChangesRequest {
+        class_names: vec!["10:00 chem 201".to_string(), "11:00 chem 201".to_string()],
+        resume_token,
+    };
+
+    let mut seat_changes_stream = client
+        .watch_seat_changes(watch_seat_changes_request(String::new()))
+        .await
+        .unwrap()
+        .into_inner();
+
+    // The first message has the seats left in every class.
+    let first = seat_changes_stream.message().await.unwrap().unwrap();
+
+    assert_eq!(first.seat_changes.len(), 2);
+    assert!(first.seat_changes.iter().all(|s| s.seats_left == 100));
+
+    client
+        .signup(enrollment_request("s1", "10:00 chem 201"))
+        .await
+        .unwrap();
+
+    let second = seat_changes_stream.message().await.unwrap().unwrap();
+
+    assert_eq!(second.seat_changes.len(), 1);
+    assert_eq!(second.seat_changes[0].class_name, "10:00 chem 201");
+    assert_eq!(second.seat_changes[0].seats_left, 99);
+
+    drop(seat_changes_stream);
+
+    // A change that is undone while the stream is gone.
+    client
+        .signup(enrollment_request("s1", "11:00 chem 201"))
+        .await
+        .unwrap();
+
+    client
+        .dropout(enrollment_request("s1", "11:00 chem 201"))
+        .await
+        .unwrap();
+
+    // Resuming from the second message gets both changes.
+    let mut seat_changes_stream = client
+        .watch_seat_changes(watch_seat_changes_request(second.resume_token.clone()))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let resumed = seat_changes_stream.message().await.unwrap().unwrap();
+
+    assert_eq!(
+        resumed
+            .seat_changes
+            .iter()
+            .map(|s| (s.class_name.as_str(), s.seats_left))
+            .collect::<Vec<_>>(),
+        vec![("11:00 chem 201", 99), ("11:00 chem 201", 100)]
+    );
+
+    drop(seat_changes_stream);
+
+    // Resuming from the first message gets the first change again.
+    let mut seat_changes_stream = client
+        .watch_seat_changes(watch_seat_changes_request(first.resume_token))
+        .await
+        .unwrap()
+        .into_inner();
+
+    let resumed_again = seat_changes_stream.message().await.unwrap().unwrap();
+
+    assert_eq!(resumed_again.seat_changes[0], second.seat_changes[0]);
+    assert_eq!(resumed_again.seat_changes.len(), 3);
+    assert_eq!(resumed_again.resume_token, resumed.resume_token);
+}
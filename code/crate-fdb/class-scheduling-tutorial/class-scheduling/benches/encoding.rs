@@ -0,0 +1,90 @@
+// Measures building keys with the tuple layer, and decoding them again,
+// along with the ranges of the key prefixes. Run them with `cargo
+// bench --bench encoding`.
+
+use class_scheduling::{
+    AttendsKey, AttendsPrefix, AttendsStudentPrefix, Class, ClassKey, ClassPrefix, Student,
+};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use fdb::Key;
+
+use std::convert::TryFrom;
+
+fn class_name() -> Class {
+    Class("10:00 chem intro".to_string())
+}
+
+fn student() -> Student {
+    Student("s0".to_string())
+}
+
+fn class_key(c: &mut Criterion) {
+    let mut group = c.benchmark_group("class_key");
+
+    group.bench_function("encode", |b| {
+        b.iter_batched(
+            || ClassKey::new(class_name()),
+            Key::from,
+            BatchSize::SmallInput,
+        )
+    });
+
+    let key = Key::from(ClassKey::new(class_name()));
+
+    group.bench_function("decode", |b| {
+        b.iter_batched(
+            || key.clone(),
+            |key| ClassKey::try_from(key).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn attends_key(c: &mut Criterion) {
+    let mut group = c.benchmark_group("attends_key");
+
+    group.bench_function("encode", |b| {
+        b.iter_batched(
+            || AttendsKey::new(student(), class_name()),
+            Key::from,
+            BatchSize::SmallInput,
+        )
+    });
+
+    let key = Key::from(AttendsKey::new(student(), class_name()));
+
+    group.bench_function("decode", |b| {
+        b.iter_batched(
+            || key.clone(),
+            |key| AttendsKey::try_from(key).unwrap(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+fn prefix_ranges(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prefix_range");
+
+    group.bench_function("class", |b| b.iter(|| ClassPrefix::new().get_range()));
+
+    group.bench_function("attends", |b| b.iter(|| AttendsPrefix::new().get_range()));
+
+    group.bench_function("attends_student", |b| {
+        b.iter_batched(
+            || AttendsStudentPrefix::new(student()),
+            |prefix| prefix.get_range(),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, class_key, attends_key, prefix_ranges);
+criterion_main!(benches);
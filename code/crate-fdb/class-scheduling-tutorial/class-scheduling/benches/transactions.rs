@@ -0,0 +1,127 @@
+// Measures the throughput of `signup` followed by `dropout`, with a
+// number of students at the same time, either all of them for the same
+// class, where their transactions conflict, or each for a class of
+// their own. Run them with `cargo bench --bench transactions`.
+//
+// They always run on `MemoryDatabase`, and also on FoundationDB when
+// `FDB_CLUSTER_FILE` is set. Like `init`, they clear all scheduling
+// data in that cluster.
+
+use class_scheduling::storage::{KvDatabase, MemoryDatabase};
+use class_scheduling::{
+    available_classes, dropout, get_class_bundle, init, signup, Class, Student, Timestamp,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use tokio::runtime::Runtime;
+
+use std::env;
+
+// Students signing up and dropping out at the same time.
+const STUDENTS: [usize; 3] = [1, 8, 32];
+
+// Student `i` signs up for, and then drops, the class `i` of
+// `class_names`, wrapping around.
+async fn signup_and_dropout<D>(db: &D, students: usize, class_names: &[Class])
+where
+    D: KvDatabase + Clone + Send + Sync + 'static,
+{
+    let handles = (0..students)
+        .map(|i| {
+            let db = db.clone();
+
+            let student = Student(format!("s{}", i));
+            let class_name = class_names[i % class_names.len()].clone();
+
+            tokio::spawn(async move {
+                db.run(|tr| {
+                    let (student, class_name) = (student.clone(), class_name.clone());
+                    async move { signup(&tr, student, class_name, Timestamp::now()).await }
+                })
+                .await
+                .unwrap();
+
+                db.run(|tr| {
+                    let (student, class_name) = (student.clone(), class_name.clone());
+                    async move { dropout(&tr, student, class_name, Timestamp::now()).await }
+                })
+                .await
+                .unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+fn bench_database<D>(c: &mut Criterion, rt: &Runtime, name: &str, db: D)
+where
+    D: KvDatabase + Clone + Send + Sync + 'static,
+{
+    rt.block_on(init(&db)).unwrap();
+
+    // The classes of a bundle are not signed up for one at a time.
+    let class_names = rt
+        .block_on(db.run(|tr| async move {
+            let mut class_names = Vec::new();
+
+            for class_name in available_classes(&tr).await? {
+                if get_class_bundle(&tr, class_name.clone()).await?.is_none() {
+                    class_names.push(class_name);
+                }
+            }
+
+            Ok(class_names)
+        }))
+        .unwrap();
+
+    let mut group = c.benchmark_group(format!("signup_and_dropout/{}", name));
+
+    for students in STUDENTS {
+        // A signup and a dropout for every student.
+        group.throughput(Throughput::Elements(2 * students as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("same_class", students),
+            &students,
+            |b, &students| {
+                b.iter(|| rt.block_on(signup_and_dropout(&db, students, &class_names[..1])))
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("own_class", students),
+            &students,
+            |b, &students| b.iter(|| rt.block_on(signup_and_dropout(&db, students, &class_names))),
+        );
+    }
+
+    group.finish();
+}
+
+fn transactions(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+
+    bench_database(c, &rt, "memory", MemoryDatabase::new());
+
+    if let Ok(fdb_cluster_file) = env::var("FDB_CLUSTER_FILE") {
+        unsafe {
+            fdb::select_api_version(fdb::FDB_API_VERSION as i32);
+            fdb::start_network();
+        }
+
+        let fdb_database = fdb::open_database(fdb_cluster_file).unwrap();
+
+        bench_database(c, &rt, "fdb", fdb_database);
+
+        unsafe {
+            fdb::stop_network();
+        }
+    }
+}
+
+criterion_group!(benches, transactions);
+criterion_main!(benches);
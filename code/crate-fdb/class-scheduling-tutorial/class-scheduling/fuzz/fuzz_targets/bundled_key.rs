@@ -0,0 +1,7 @@
+#![no_main]
+
+use class_scheduling::BundledKey;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| class_scheduling_fuzz::check_key::<BundledKey>(data));
@@ -0,0 +1,7 @@
+#![no_main]
+
+use class_scheduling::RegistrationWindowKey;
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| class_scheduling_fuzz::check_key::<RegistrationWindowKey>(data));
@@ -0,0 +1,34 @@
+use bytes::Bytes;
+
+use class_scheduling::{KEY_CONVERTION_ERROR, VALUE_CONVERTION_ERROR};
+
+use fdb::error::FdbError;
+use fdb::{Key, Value};
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+
+// Decodes `data` as a key of type `T`, which must not panic. A key that
+// decodes must encode to a key that decodes the same, and any other key
+// must fail with `KEY_CONVERTION_ERROR`.
+pub fn check_key<T>(data: &[u8])
+where
+    T: TryFrom<Key, Error = FdbError> + Into<Key> + Clone + Debug + PartialEq,
+{
+    match T::try_from(Key::from(Bytes::copy_from_slice(data))) {
+        Ok(decoded) => assert_eq!(T::try_from(decoded.clone().into()).unwrap(), decoded),
+        Err(err) => assert_eq!(err.code(), KEY_CONVERTION_ERROR),
+    }
+}
+
+// Like `check_key`, for values, which must fail with
+// `VALUE_CONVERTION_ERROR`.
+pub fn check_value<T>(data: &[u8])
+where
+    T: TryFrom<Value, Error = FdbError> + Into<Value> + Clone + Debug + PartialEq,
+{
+    match T::try_from(Value::from(Bytes::copy_from_slice(data))) {
+        Ok(decoded) => assert_eq!(T::try_from(decoded.clone().into()).unwrap(), decoded),
+        Err(err) => assert_eq!(err.code(), VALUE_CONVERTION_ERROR),
+    }
+}
@@ -0,0 +1,195 @@
+// HTTP/JSON service for class scheduling.
+//
+// Start it with `FDB_CLUSTER_FILE` set, just like the command line
+// tool:
+//
+//     cargo run --bin server -- --listen 127.0.0.1:3000
+//
+// and then, for example:
+//
+//     curl localhost:3000/classes
+//     curl -X PUT "localhost:3000/students/s1/classes/10:00%20chem%20201"
+//     curl localhost:3000/students/s1/classes
+//     curl -N "localhost:3000/seats?classes=10:00%20chem%20201,11:00%20chem%20201"
+
+use class_scheduling::seats::{decode_seats_token, encode_seats_token, wait_for_seat_changes};
+use class_scheduling::{error_message, http, Class};
+
+use fdb::database::{DatabaseOption, FdbDatabase};
+
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+
+use clap::Parser;
+
+use futures::stream::{self, Stream};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use tracing::info;
+
+use tokio::net::TcpListener;
+use tokio::runtime::Runtime;
+
+use std::convert::Infallible;
+use std::env;
+use std::error::Error;
+use std::net::SocketAddr;
+
+#[derive(Deserialize, Debug)]
+struct SeatsQuery {
+    // Comma separated class names.
+    classes: String,
+    resume_token: Option<String>,
+}
+
+// Streams the seats left in the classes as server-sent events. The
+// first event has the seats left in every class, and every event after
+// that has the classes whose seats left changed.
+//
+// The id of each event is a resume token. Browsers send it back in
+// the `Last-Event-ID` header when they reconnect, and other clients
+// can pass it as `resume_token`. The stream then sends every change
+// made since that event, in the order it was made in its class, even
+// a change that was undone while disconnected. When the changes are
+// no longer kept, the stream ends with a "seat changes trimmed" error
+// event, and has to be started over without a resume token.
+async fn seat_changes(
+    State(db): State<FdbDatabase>,
+    headers: HeaderMap,
+    Query(seats_query): Query<SeatsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, Response> {
+    let class_names = seats_query
+        .classes
+        .split(',')
+        .map(|class_inner| Class(class_inner.to_string()))
+        .collect::<Vec<Class>>();
+
+    let resume_token = headers
+        .get("last-event-id")
+        .and_then(|header_value| header_value.to_str().ok())
+        .map(|header_value| header_value.to_string())
+        .or(seats_query.resume_token);
+
+    let versions = match resume_token {
+        Some(resume_token) => Some(
+            decode_seats_token(&resume_token, class_names.len()).ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": { "message": "invalid resume token" } })),
+                )
+                    .into_response()
+            })?,
+        ),
+        None => None,
+    };
+
+    // `None` once the stream has ended with an error.
+    let seat_changes_stream = stream::unfold(Some(versions), move |state| {
+        let db = db.clone();
+        let class_names = class_names.clone();
+
+        async move {
+            let versions = state?;
+
+            match wait_for_seat_changes(&db, &class_names, versions.as_deref()).await {
+                Ok((seat_changes, new_versions)) => {
+                    let seat_changes = seat_changes
+                        .into_iter()
+                        .map(|(Class(class_inner), seats_left)| {
+                            json!({ "class_name": class_inner, "seats_left": seats_left })
+                        })
+                        .collect::<Vec<serde_json::Value>>();
+
+                    let event = Event::default()
+                        .event("seats")
+                        .id(encode_seats_token(&new_versions))
+                        .data(json!({ "seat_changes": seat_changes }).to_string());
+
+                    Some((Ok(event), Some(Some(new_versions))))
+                }
+                Err(err) => {
+                    let event = Event::default().event("error").data(
+                        json!({ "error": { "code": err.code(), "message": error_message(&err) } })
+                            .to_string(),
+                    );
+
+                    Some((Ok(event), None))
+                }
+            }
+        }
+    });
+
+    Ok(Sse::new(seat_changes_stream).keep_alive(KeepAlive::default()))
+}
+
+fn router(db: FdbDatabase) -> Router {
+    http::router(db.clone()).merge(
+        Router::new()
+            .route("/seats", get(seat_changes))
+            .with_state(db),
+    )
+}
+
+/// HTTP/JSON service for class scheduling.
+#[derive(Parser, Debug)]
+#[command(name = "server")]
+struct Cli {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    listen: SocketAddr,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let fdb_cluster_file = env::var("FDB_CLUSTER_FILE").expect("FDB_CLUSTER_FILE not defined!");
+
+    unsafe {
+        fdb::select_api_version(fdb::FDB_API_VERSION as i32);
+        fdb::start_network();
+    }
+
+    let fdb_database = fdb::open_database(fdb_cluster_file)?;
+
+    // 60,000 ms = 1 minute
+    fdb_database.set_option(DatabaseOption::TransactionTimeout(60000))?;
+    fdb_database.set_option(DatabaseOption::TransactionRetryLimit(100))?;
+
+    let rt = Runtime::new()?;
+
+    let cloned_fdb_database = fdb_database.clone();
+
+    rt.block_on(async {
+        let fdb_database = cloned_fdb_database;
+
+        let listener = TcpListener::bind(cli.listen).await?;
+
+        info!(listen = %cli.listen, "serving");
+
+        axum::serve(listener, router(fdb_database))
+            .with_graceful_shutdown(async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await?;
+
+        Result::<(), Box<dyn Error>>::Ok(())
+    })?;
+
+    drop(fdb_database);
+
+    unsafe {
+        fdb::stop_network();
+    }
+
+    Ok(())
+}
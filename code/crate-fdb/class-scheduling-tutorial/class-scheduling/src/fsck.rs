@@ -0,0 +1,566 @@
+use bytes::Bytes;
+
+use fdb::error::FdbResult;
+use fdb::range::Range;
+use fdb::{Key, Value};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::scan::{parallel_scan, scan_shard};
+use crate::storage::{KvDatabase, KvTransaction};
+use crate::{
+    set_seats_left, AttendsKey, AttendsPrefix, Bundle, BundleKey, BundlePrefix, BundledKey,
+    BundledPrefix, BundledValue, Class, ClassKey, ClassPrefix, ClassValue, LotteryRequestKey,
+    LotteryRequestPrefix, LotteryRequestValue, PriorityGroupKey, PriorityGroupPrefix,
+    PriorityGroupValue, RegistrationWindowKey, RegistrationWindowPrefix, RegistrationWindowValue,
+    Student, CLASS_CAPACITY, MAX_CLASSES,
+};
+
+// The checker reads the database with `parallel_scan`, in many small
+// transactions, so that it works on more data than a single
+// transaction can read, and can run while students sign up. The
+// transactions read at different versions though, so a finding on a
+// busy database may only be a change that landed in between. Run it
+// again, or with no one else writing, before trusting a finding.
+//
+// Repairs also happen in small transactions. Each one reads again what
+// it is about to change, and leaves it alone when it is not what the
+// scan saw. What a repair writes is worked out from what it reads in
+// its own transaction, not from the scan, so a repair that commits is
+// right for the database it commits to.
+
+// Findings repaired in a single transaction.
+const REPAIR_BATCH_SIZE: usize = 100;
+
+// Whether a key-value pair decodes.
+type Decodes = fn(Key, Value) -> bool;
+
+// Students enrolled in every class, classes of every student, and what
+// is wrong with the attends keys.
+type AttendsCounts = (
+    BTreeMap<Class, usize>,
+    BTreeMap<Student, usize>,
+    Vec<Finding>,
+);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Finding {
+    // A key, or its value, that does not decode. `value` is what the
+    // scan read, so that a repair can tell whether it changed since.
+    Undecodable {
+        key: Bytes,
+        value: Bytes,
+    },
+    // There is a ("attends", student, class) key, but no ("class",
+    // class) key.
+    OrphanedAttends {
+        student: Student,
+        class: Class,
+    },
+    // seats_left + enrolled != CLASS_CAPACITY, where `seats_left` is
+    // `None` when the value of ("class", class) does not decode.
+    SeatCount {
+        class: Class,
+        seats_left: Option<u8>,
+        enrolled: usize,
+    },
+    TooManyClasses {
+        student: Student,
+        classes: usize,
+    },
+    // ("bundled", class) does not name `bundle`, the bundle that has
+    // a ("bundle", bundle, class) key. `bundle` is `None` when the
+    // class is in no bundle. `indexed` is the value of ("bundled",
+    // class) the scan read, `None` when there is no such key.
+    BundledIndex {
+        class: Class,
+        bundle: Option<Bundle>,
+        indexed: Option<Bytes>,
+    },
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::Undecodable { key, .. } => write!(f, "{:?} does not decode", key),
+            Finding::OrphanedAttends {
+                student: Student(student),
+                class: Class(class),
+            } => write!(f, "{} attends {}, which does not exist", student, class),
+            Finding::SeatCount {
+                class: Class(class),
+                seats_left: Some(seats_left),
+                enrolled,
+            } => write!(
+                f,
+                "{} has {} seats left and {} enrolled, capacity is {}",
+                class, seats_left, enrolled, CLASS_CAPACITY
+            ),
+            Finding::SeatCount {
+                class: Class(class),
+                seats_left: None,
+                enrolled,
+            } => write!(
+                f,
+                "{} has unreadable seats left and {} enrolled",
+                class, enrolled
+            ),
+            Finding::TooManyClasses {
+                student: Student(student),
+                classes,
+            } => write!(
+                f,
+                "{} attends {} classes, at most {} are allowed",
+                student, classes, MAX_CLASSES
+            ),
+            Finding::BundledIndex {
+                class: Class(class),
+                bundle: Some(Bundle(bundle)),
+                ..
+            } => write!(f, "{} is not indexed as part of bundle {}", class, bundle),
+            Finding::BundledIndex {
+                class: Class(class),
+                bundle: None,
+                ..
+            } => write!(
+                f,
+                "{} is indexed as part of a bundle, but is in none",
+                class
+            ),
+        }
+    }
+}
+
+// The seats left in a class with `enrolled` students, which is none
+// when it has more students than seats.
+fn expected_seats_left(enrolled: usize) -> u8 {
+    CLASS_CAPACITY.saturating_sub(u8::try_from(enrolled).unwrap_or(u8::MAX))
+}
+
+// The bundle a ("bundled", class_name) value names, `None` when it
+// does not decode.
+fn decode_bundled(value: Bytes) -> Option<Bundle> {
+    BundledValue::try_from(Value::from(value))
+        .ok()
+        .map(Bundle::from)
+}
+
+// Scans the scheduling data and returns what is wrong with it.
+pub async fn fsck<D>(db: &D) -> FdbResult<Vec<Finding>>
+where
+    D: KvDatabase + Clone + Send + Sync + 'static,
+{
+    let mut findings = Vec::new();
+
+    // ("class", ...)
+    let shards = parallel_scan(
+        db,
+        ClassPrefix::new().get_range(),
+        |(seats_left, findings): &mut (BTreeMap<Class, Option<u8>>, Vec<Finding>), key, value| {
+            match TryInto::<ClassKey>::try_into(key.clone()) {
+                Ok(class_key) => {
+                    seats_left.insert(
+                        Class::from(class_key),
+                        ClassValue::try_from(value).ok().map(|v| v.get_val()),
+                    );
+                }
+                Err(_) => findings.push(Finding::Undecodable {
+                    key: Bytes::from(key),
+                    value: Bytes::from(value),
+                }),
+            }
+        },
+    )
+    .await?;
+
+    let mut seats_left = BTreeMap::new();
+
+    for (shard_seats_left, shard_findings) in shards {
+        seats_left.extend(shard_seats_left);
+
+        findings.extend(shard_findings);
+    }
+
+    // ("attends", ...)
+    //
+    // Only counts are kept, and the keys of orphans, so that this does
+    // not hold all the attends keys in memory.
+    let class_names = Arc::new(seats_left.keys().cloned().collect::<BTreeSet<Class>>());
+
+    let shards = parallel_scan(
+        db,
+        AttendsPrefix::new().get_range(),
+        move |(enrolled, schedules, findings): &mut AttendsCounts, key, value| match TryInto::<
+            AttendsKey,
+        >::try_into(
+            key.clone()
+        ) {
+            Ok(attends_key) => {
+                let AttendsKey {
+                    student,
+                    class_name,
+                } = attends_key;
+
+                *schedules.entry(student.clone()).or_insert(0) += 1;
+
+                if class_names.contains(&class_name) {
+                    *enrolled.entry(class_name).or_insert(0) += 1;
+                } else {
+                    findings.push(Finding::OrphanedAttends {
+                        student,
+                        class: class_name,
+                    });
+                }
+            }
+            Err(_) => findings.push(Finding::Undecodable {
+                key: Bytes::from(key),
+                value: Bytes::from(value),
+            }),
+        },
+    )
+    .await?;
+
+    let mut enrolled = BTreeMap::<Class, usize>::new();
+
+    // The classes of a student can be split across two shards.
+    let mut schedules = BTreeMap::<Student, usize>::new();
+
+    for (shard_enrolled, shard_schedules, shard_findings) in shards {
+        for (class, count) in shard_enrolled {
+            *enrolled.entry(class).or_insert(0) += count;
+        }
+
+        for (student, classes) in shard_schedules {
+            *schedules.entry(student).or_insert(0) += classes;
+        }
+
+        findings.extend(shard_findings);
+    }
+
+    for (student, classes) in schedules {
+        if classes > MAX_CLASSES {
+            findings.push(Finding::TooManyClasses { student, classes });
+        }
+    }
+
+    for (class, seats_left) in seats_left {
+        let enrolled = enrolled.get(&class).copied().unwrap_or(0);
+
+        if seats_left != Some(expected_seats_left(enrolled))
+            || enrolled > usize::from(CLASS_CAPACITY)
+        {
+            findings.push(Finding::SeatCount {
+                class,
+                seats_left,
+                enrolled,
+            });
+        }
+    }
+
+    // ("bundle", ...)
+    let shards = parallel_scan(
+        db,
+        BundlePrefix::new().get_range(),
+        |(bundles, findings): &mut (BTreeMap<Class, Bundle>, Vec<Finding>), key, value| {
+            match TryInto::<BundleKey>::try_into(key.clone()) {
+                Ok(bundle_key) => {
+                    bundles.insert(bundle_key.class_name, bundle_key.bundle);
+                }
+                Err(_) => findings.push(Finding::Undecodable {
+                    key: Bytes::from(key),
+                    value: Bytes::from(value),
+                }),
+            }
+        },
+    )
+    .await?;
+
+    let mut bundles = BTreeMap::new();
+
+    for (shard_bundles, shard_findings) in shards {
+        bundles.extend(shard_bundles);
+
+        findings.extend(shard_findings);
+    }
+
+    // ("bundled", ...)
+    //
+    // A value that does not decode is taken as the class not being
+    // indexed at all.
+    let shards = parallel_scan(
+        db,
+        BundledPrefix::new().get_range(),
+        |(indexed, findings): &mut (BTreeMap<Class, Bytes>, Vec<Finding>), key, value| {
+            match TryInto::<BundledKey>::try_into(key.clone()) {
+                Ok(bundled_key) => {
+                    indexed.insert(Class::from(bundled_key), Bytes::from(value));
+                }
+                Err(_) => findings.push(Finding::Undecodable {
+                    key: Bytes::from(key),
+                    value: Bytes::from(value),
+                }),
+            }
+        },
+    )
+    .await?;
+
+    let mut indexed = BTreeMap::new();
+
+    for (shard_indexed, shard_findings) in shards {
+        indexed.extend(shard_indexed);
+
+        findings.extend(shard_findings);
+    }
+
+    for (class, value) in indexed {
+        let expected = bundles.remove(&class);
+
+        if decode_bundled(value.clone()) != expected {
+            findings.push(Finding::BundledIndex {
+                class,
+                bundle: expected,
+                indexed: Some(value),
+            });
+        }
+    }
+
+    // Classes in a bundle that have no ("bundled", class) key.
+    for (class, bundle) in bundles {
+        findings.push(Finding::BundledIndex {
+            class,
+            bundle: Some(bundle),
+            indexed: None,
+        });
+    }
+
+    // The other subspaces are only checked for keys and values that do
+    // not decode. A priority group is not checked against the
+    // registration windows, as students can be put in a group before
+    // its window is set up, and a group without a window only means
+    // its students wait for registration to open for everyone.
+    let subspaces: [(Range, Decodes); 3] = [
+        (RegistrationWindowPrefix::new().get_range(), |key, value| {
+            RegistrationWindowKey::try_from(key).is_ok()
+                && RegistrationWindowValue::try_from(value).is_ok()
+        }),
+        (PriorityGroupPrefix::new().get_range(), |key, value| {
+            PriorityGroupKey::try_from(key).is_ok() && PriorityGroupValue::try_from(value).is_ok()
+        }),
+        (LotteryRequestPrefix::new().get_range(), |key, value| {
+            LotteryRequestKey::try_from(key).is_ok() && LotteryRequestValue::try_from(value).is_ok()
+        }),
+    ];
+
+    for (range, decodes) in subspaces {
+        let shards = parallel_scan(db, range, move |findings: &mut Vec<Finding>, key, value| {
+            if !decodes(key.clone(), value.clone()) {
+                findings.push(Finding::Undecodable {
+                    key: Bytes::from(key),
+                    value: Bytes::from(value),
+                });
+            }
+        })
+        .await?;
+
+        findings.extend(shards.into_iter().flatten());
+    }
+
+    Ok(findings)
+}
+
+// Keys that are cleared when they, or their values, do not decode.
+// An attends or class key that doesn't decode is not counted by
+// anything, and the ("bundled", class_name) index is rebuilt from the
+// bundles. The other subspaces are only reported: clearing a
+// registration window would open registration, and clearing a bundle,
+// a priority group or a lottery request would lose what it holds.
+fn droppable(key: &Bytes) -> bool {
+    [
+        ClassPrefix::new().get_range(),
+        AttendsPrefix::new().get_range(),
+        BundledPrefix::new().get_range(),
+    ]
+    .iter()
+    .any(|range| {
+        Bytes::from(range.begin().clone()) <= *key && *key < Bytes::from(range.end().clone())
+    })
+}
+
+// Students enrolled in each of `class_names`. The attends keys are
+// read a batch per transaction, like the scan, so the counts are not
+// taken at a single version either.
+async fn count_enrolled<D: KvDatabase>(
+    db: &D,
+    class_names: &BTreeSet<Class>,
+) -> FdbResult<BTreeMap<Class, usize>> {
+    // ("attends", ...)
+    let range = AttendsPrefix::new().get_range();
+
+    scan_shard(
+        db,
+        range.begin().clone(),
+        range.end().clone(),
+        &|enrolled: &mut BTreeMap<Class, usize>, key, _| {
+            // Keys that don't decode are findings of their own.
+            if let Ok(attends_key) = TryInto::<AttendsKey>::try_into(key) {
+                if class_names.contains(&attends_key.class_name) {
+                    *enrolled.entry(attends_key.class_name).or_insert(0) += 1;
+                }
+            }
+        },
+    )
+    .await
+}
+
+// Repairs `finding`, and returns `false` when it cannot be repaired,
+// or when what it is about has changed since the scan. `enrolled` has
+// the students in each class of a `SeatCount` finding, counted after
+// the scan.
+async fn repair_finding<T: KvTransaction>(
+    tr: &T,
+    finding: &Finding,
+    enrolled: &BTreeMap<Class, usize>,
+) -> FdbResult<bool> {
+    match finding {
+        Finding::Undecodable { key, value } => {
+            if !droppable(key) {
+                return Ok(false);
+            }
+
+            let key = Key::from(key.clone());
+
+            if tr.get(key.clone()).await?.map(Bytes::from) != Some(value.clone()) {
+                return Ok(false);
+            }
+
+            // Nothing can read the key anyway.
+            tr.clear(key);
+
+            Ok(true)
+        }
+        Finding::OrphanedAttends { student, class } => {
+            // ("class", class_name)
+            if tr.get(ClassKey::new(class.clone())).await?.is_some() {
+                return Ok(false);
+            }
+
+            // ("attends", student, class_name)
+            tr.clear(AttendsKey::new(student.clone(), class.clone()));
+
+            Ok(true)
+        }
+        Finding::SeatCount {
+            class, seats_left, ..
+        } => {
+            // ("class", class_name)
+            let class_key = ClassKey::new(class.clone());
+
+            let current = match tr.get(class_key).await? {
+                Some(value) => ClassValue::try_from(value).ok().map(|v| v.get_val()),
+                None => return Ok(false),
+            };
+
+            // Signups and dropouts change the seats left along with the
+            // students enrolled, so unless the seats left changed and
+            // changed back while we counted, the count still holds.
+            if current != *seats_left {
+                return Ok(false);
+            }
+
+            let enrolled = enrolled.get(class).copied().unwrap_or(0);
+
+            set_seats_left(tr, class.clone(), expected_seats_left(enrolled)).await?;
+
+            // Someone still needs to decide who leaves a class with
+            // more students than seats.
+            Ok(enrolled <= usize::from(CLASS_CAPACITY))
+        }
+        // Which classes to drop is not for us to decide.
+        Finding::TooManyClasses { .. } => Ok(false),
+        Finding::BundledIndex {
+            class,
+            bundle,
+            indexed,
+        } => {
+            // ("bundled", class_name)
+            let bundled_key = BundledKey::new(class.clone());
+
+            if tr.get(bundled_key.clone()).await?.map(Bytes::from) != *indexed {
+                return Ok(false);
+            }
+
+            // Only the ("bundle", bundle, class_name) key the finding is
+            // about is read again.
+            let unchanged = match (bundle, indexed.clone().and_then(decode_bundled)) {
+                // The class is still in `bundle`.
+                (Some(bundle), _) => tr
+                    .get(BundleKey::new(bundle.clone(), class.clone()))
+                    .await?
+                    .is_some(),
+                // The class is still not in the bundle it is indexed
+                // as part of.
+                (None, Some(indexed_bundle)) => tr
+                    .get(BundleKey::new(indexed_bundle, class.clone()))
+                    .await?
+                    .is_none(),
+                // The scan only reports a class in no bundle when its
+                // index names one.
+                (None, None) => false,
+            };
+
+            if !unchanged {
+                return Ok(false);
+            }
+
+            match bundle {
+                Some(bundle) => tr.set(bundled_key, BundledValue::new(bundle.clone())),
+                None => tr.clear(bundled_key),
+            }
+
+            Ok(true)
+        }
+    }
+}
+
+// Repairs what it can of `findings`, and returns whether each of them
+// was repaired.
+pub async fn repair<D: KvDatabase>(db: &D, findings: &[Finding]) -> FdbResult<Vec<bool>> {
+    let class_names = findings
+        .iter()
+        .filter_map(|finding| match finding {
+            Finding::SeatCount { class, .. } => Some(class.clone()),
+            _ => None,
+        })
+        .collect::<BTreeSet<Class>>();
+
+    let enrolled = if class_names.is_empty() {
+        BTreeMap::new()
+    } else {
+        count_enrolled(db, &class_names).await?
+    };
+
+    let enrolled = &enrolled;
+
+    let mut repaired = Vec::new();
+
+    for batch in findings.chunks(REPAIR_BATCH_SIZE) {
+        let batch_repaired = db
+            .run(|tr| async move {
+                let mut batch_repaired = Vec::new();
+
+                for finding in batch {
+                    batch_repaired.push(repair_finding(&tr, finding, enrolled).await?);
+                }
+
+                Ok(batch_repaired)
+            })
+            .await?;
+
+        repaired.extend(batch_repaired);
+    }
+
+    Ok(repaired)
+}
@@ -0,0 +1,380 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+
+use fdb::error::FdbError;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use std::time::Duration;
+
+use crate::storage::{KvDatabase, KvTransaction};
+use crate::{
+    available_classes, build_schedule, dropout, dropout_bundle, error_message,
+    get_student_schedule, signup, signup_bundle, submit_preferences, switch_classes, trade_seats,
+    Class, NewClass, OldClass, Student, Timestamp, ALREADY_SIGNED_UP, INCOMPLETE_BUNDLE,
+    KEY_CONVERTION_ERROR, LOTTERY_CLOSED, NOT_SIGNED_UP, NO_REMAINING_SEATS, NO_SUCH_CLASS,
+    PRIORITY_REGISTRATION_ONLY, REGISTRATION_CLOSED, TIME_CONFLICT, TOO_MANY_CLASSES,
+    VALUE_CONVERTION_ERROR,
+};
+
+// The routes of the HTTP/JSON service in `src/bin/server.rs` that work
+// on any `KvDatabase`, so that they can be tested on `MemoryDatabase`.
+// The stream of seats left needs FoundationDB watches, and is added by
+// the server.
+
+// HTTP status codes for the errors returned by our transactions. Any
+// other error is a `500 Internal Server Error`.
+const ERROR_STATUS_CODES: [(i32, StatusCode); 12] = [
+    (NO_REMAINING_SEATS, StatusCode::CONFLICT),
+    (ALREADY_SIGNED_UP, StatusCode::CONFLICT),
+    (TOO_MANY_CLASSES, StatusCode::CONFLICT),
+    (NOT_SIGNED_UP, StatusCode::CONFLICT),
+    (TIME_CONFLICT, StatusCode::CONFLICT),
+    (INCOMPLETE_BUNDLE, StatusCode::UNPROCESSABLE_ENTITY),
+    (REGISTRATION_CLOSED, StatusCode::FORBIDDEN),
+    (PRIORITY_REGISTRATION_ONLY, StatusCode::FORBIDDEN),
+    (LOTTERY_CLOSED, StatusCode::FORBIDDEN),
+    (NO_SUCH_CLASS, StatusCode::NOT_FOUND),
+    (KEY_CONVERTION_ERROR, StatusCode::INTERNAL_SERVER_ERROR),
+    (VALUE_CONVERTION_ERROR, StatusCode::INTERNAL_SERVER_ERROR),
+];
+
+struct ApiError(FdbError);
+
+impl From<FdbError> for ApiError {
+    fn from(err: FdbError) -> ApiError {
+        ApiError(err)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let ApiError(err) = self;
+
+        let status_code = ERROR_STATUS_CODES
+            .iter()
+            .find(|(code, _)| *code == err.code())
+            .map(|(_, status_code)| *status_code)
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        let body = json!({
+            "error": { "code": err.code(), "message": error_message(&err) }
+        });
+
+        (status_code, Json(body)).into_response()
+    }
+}
+
+type ApiResult = Result<Json<serde_json::Value>, ApiError>;
+
+// Time allowed for the health check to get a read version from the
+// cluster.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn health<D: KvDatabase>(State(db): State<D>) -> Response {
+    let read_version = tokio::time::timeout(
+        HEALTH_CHECK_TIMEOUT,
+        db.run(|tr| async move { tr.get_read_version().await }),
+    )
+    .await;
+
+    match read_version {
+        Ok(Ok(_)) => Json(json!({ "status": "ok" })).into_response(),
+        Ok(Err(err)) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "unavailable", "message": error_message(&err) })),
+        )
+            .into_response(),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "status": "unavailable", "message": "timed out" })),
+        )
+            .into_response(),
+    }
+}
+
+async fn list_classes<D: KvDatabase>(State(db): State<D>) -> ApiResult {
+    let class_names = db
+        .run(|tr| async move { available_classes(&tr).await })
+        .await?;
+
+    let class_names = class_names
+        .into_iter()
+        .map(|Class(class_inner)| class_inner)
+        .collect::<Vec<String>>();
+
+    Ok(Json(json!({ "classes": class_names })))
+}
+
+async fn student_schedule<D: KvDatabase>(
+    State(db): State<D>,
+    Path(student): Path<String>,
+) -> ApiResult {
+    let student_ref = &student;
+
+    let class_names = db
+        .run(|tr| async move { get_student_schedule(&tr, Student(student_ref.clone())).await })
+        .await?;
+
+    let class_names = class_names
+        .into_iter()
+        .map(|Class(class_inner)| class_inner)
+        .collect::<Vec<String>>();
+
+    Ok(Json(json!({ "classes": class_names })))
+}
+
+async fn student_signup<D: KvDatabase>(
+    State(db): State<D>,
+    Path((student, class)): Path<(String, String)>,
+) -> ApiResult {
+    let (student_ref, class_ref) = (&student, &class);
+
+    db.run(|tr| async move {
+        signup(
+            &tr,
+            Student(student_ref.clone()),
+            Class(class_ref.clone()),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+async fn student_dropout<D: KvDatabase>(
+    State(db): State<D>,
+    Path((student, class)): Path<(String, String)>,
+) -> ApiResult {
+    let (student_ref, class_ref) = (&student, &class);
+
+    db.run(|tr| async move {
+        dropout(
+            &tr,
+            Student(student_ref.clone()),
+            Class(class_ref.clone()),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+async fn student_dropout_bundle<D: KvDatabase>(
+    State(db): State<D>,
+    Path((student, class)): Path<(String, String)>,
+) -> ApiResult {
+    let (student_ref, class_ref) = (&student, &class);
+
+    db.run(|tr| async move {
+        dropout_bundle(
+            &tr,
+            Student(student_ref.clone()),
+            Class(class_ref.clone()),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+#[derive(Deserialize, Debug)]
+struct BundleRequest {
+    classes: Vec<String>,
+}
+
+async fn student_signup_bundle<D: KvDatabase>(
+    State(db): State<D>,
+    Path(student): Path<String>,
+    Json(bundle_request): Json<BundleRequest>,
+) -> ApiResult {
+    let (student_ref, bundle_request_ref) = (&student, &bundle_request);
+
+    db.run(|tr| async move {
+        signup_bundle(
+            &tr,
+            Student(student_ref.clone()),
+            bundle_request_ref
+                .classes
+                .iter()
+                .cloned()
+                .map(Class)
+                .collect(),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+#[derive(Deserialize, Debug)]
+struct ScheduleRequest {
+    classes: Vec<String>,
+}
+
+// Responds with the classes the student was signed up for.
+async fn student_build_schedule<D: KvDatabase>(
+    State(db): State<D>,
+    Path(student): Path<String>,
+    Json(schedule_request): Json<ScheduleRequest>,
+) -> ApiResult {
+    let (student_ref, schedule_request_ref) = (&student, &schedule_request);
+
+    let class_names = db
+        .run(|tr| async move {
+            build_schedule(
+                &tr,
+                Student(student_ref.clone()),
+                schedule_request_ref
+                    .classes
+                    .iter()
+                    .cloned()
+                    .map(Class)
+                    .collect(),
+                Timestamp::now(),
+            )
+            .await
+        })
+        .await?;
+
+    let class_names = class_names
+        .into_iter()
+        .map(|Class(class_inner)| class_inner)
+        .collect::<Vec<String>>();
+
+    Ok(Json(json!({ "classes": class_names })))
+}
+
+#[derive(Deserialize, Debug)]
+struct PreferencesRequest {
+    classes: Vec<String>,
+}
+
+async fn student_preferences<D: KvDatabase>(
+    State(db): State<D>,
+    Path(student): Path<String>,
+    Json(preferences_request): Json<PreferencesRequest>,
+) -> ApiResult {
+    let (student_ref, preferences_request_ref) = (&student, &preferences_request);
+
+    db.run(|tr| async move {
+        submit_preferences(
+            &tr,
+            Student(student_ref.clone()),
+            preferences_request_ref
+                .classes
+                .iter()
+                .cloned()
+                .map(Class)
+                .collect(),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+#[derive(Deserialize, Debug)]
+struct SwitchRequest {
+    old_class: String,
+    new_class: String,
+}
+
+async fn student_switch<D: KvDatabase>(
+    State(db): State<D>,
+    Path(student): Path<String>,
+    Json(switch_request): Json<SwitchRequest>,
+) -> ApiResult {
+    let (student_ref, switch_request_ref) = (&student, &switch_request);
+
+    db.run(|tr| async move {
+        switch_classes(
+            &tr,
+            Student(student_ref.clone()),
+            OldClass(Class(switch_request_ref.old_class.clone())),
+            NewClass(Class(switch_request_ref.new_class.clone())),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+#[derive(Deserialize, Debug)]
+struct TradeRequest {
+    student_a: String,
+    class_a: String,
+    student_b: String,
+    class_b: String,
+}
+
+async fn trade<D: KvDatabase>(
+    State(db): State<D>,
+    Json(trade_request): Json<TradeRequest>,
+) -> ApiResult {
+    let trade_request_ref = &trade_request;
+
+    db.run(|tr| async move {
+        trade_seats(
+            &tr,
+            Student(trade_request_ref.student_a.clone()),
+            Class(trade_request_ref.class_a.clone()),
+            Student(trade_request_ref.student_b.clone()),
+            Class(trade_request_ref.class_b.clone()),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await?;
+
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+pub fn router<D>(db: D) -> Router
+where
+    D: KvDatabase + Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/health", get(health::<D>))
+        .route("/classes", get(list_classes::<D>))
+        .route("/students/{student}/classes", get(student_schedule::<D>))
+        .route(
+            "/students/{student}/classes/{class}",
+            put(student_signup::<D>).delete(student_dropout::<D>),
+        )
+        .route(
+            "/students/{student}/bundles",
+            post(student_signup_bundle::<D>),
+        )
+        .route(
+            "/students/{student}/bundles/{class}",
+            delete(student_dropout_bundle::<D>),
+        )
+        .route(
+            "/students/{student}/preferences",
+            put(student_preferences::<D>),
+        )
+        .route(
+            "/students/{student}/schedule",
+            post(student_build_schedule::<D>),
+        )
+        .route("/students/{student}/switch", post(student_switch::<D>))
+        .route("/trades", post(trade::<D>))
+        .with_state(db)
+}
@@ -0,0 +1,2326 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use fdb::error::{FdbError, FdbResult};
+use fdb::range::Range;
+use fdb::tuple::Tuple;
+use fdb::{Key, Value};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+use tokio_stream::StreamExt;
+
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod fsck;
+pub mod http;
+pub mod scan;
+pub mod seats;
+pub mod sim;
+pub mod storage;
+
+use storage::{KvDatabase, KvTransaction};
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Class(pub String);
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Student(pub String);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bundle(pub String);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriorityGroup(pub String);
+
+// Seconds since the Unix epoch.
+//
+// Registration windows start and end at a timestamp, and every
+// operation that checks them is passed the current time by its
+// caller, so that tests can choose it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub i64);
+
+impl Timestamp {
+    pub fn now() -> Timestamp {
+        // Safety: the system clock is not set before 1970.
+        Timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64,
+        )
+    }
+}
+
+// Registration runs in phases. Students in a priority group (for
+// example, seniors) register first, then everyone registers, then
+// there is an add/drop period. Outside of these windows registration
+// is closed.
+//
+// Alternatively, oversubscribed classes can be allocated by lottery,
+// in which case students submit their preferences during the lottery
+// window instead of signing up.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Phase {
+    Priority(PriorityGroup),
+    Open,
+    AddDrop,
+    Lottery,
+}
+
+// ("class", class_name)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassKey {
+    class_name: Class,
+}
+
+impl ClassKey {
+    pub fn new(class_name: Class) -> ClassKey {
+        ClassKey { class_name }
+    }
+}
+
+impl From<ClassKey> for Key {
+    fn from(c: ClassKey) -> Key {
+        let key_tup: (&'static str, Class) = ("class", c.class_name);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Class(class_inner) = key_tup.1;
+            tup.add_string(class_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl From<ClassKey> for Class {
+    fn from(c: ClassKey) -> Class {
+        c.class_name
+    }
+}
+
+pub const VALUE_CONVERTION_ERROR: i32 = 998;
+
+pub const KEY_CONVERTION_ERROR: i32 = 999;
+
+impl TryFrom<Key> for ClassKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<ClassKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("class", class_name)
+                if tup.get_string_ref(0)?.as_str() != "class" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let class_name = Class(tup.get_string_ref(1)?.to_string());
+
+                Ok(ClassKey::new(class_name))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassValue {
+    val: u8,
+}
+
+impl ClassValue {
+    pub fn new(val: u8) -> ClassValue {
+        ClassValue { val }
+    }
+
+    pub fn get_val(&self) -> u8 {
+        self.val
+    }
+}
+
+impl From<ClassValue> for Value {
+    fn from(c: ClassValue) -> Value {
+        let val_bytes = Bytes::from({
+            let mut b = BytesMut::new();
+            b.put_u8(c.val);
+            b
+        });
+
+        val_bytes.into()
+    }
+}
+
+impl TryFrom<Value> for ClassValue {
+    type Error = FdbError;
+
+    fn try_from(value: Value) -> FdbResult<ClassValue> {
+        // seats_left, as a single byte
+        match Bytes::from(value)[..] {
+            [val] => Ok(ClassValue::new(val)),
+            _ => Err(FdbError::new(VALUE_CONVERTION_ERROR)),
+        }
+    }
+}
+
+// ("seat_change", class_name, version)
+//
+// Every change to the seats left in a class is also written here, with
+// the version of the change, so that a stream of seat changes can send
+// the changes it missed rather than only the latest seats left. See
+// `set_seats_left`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeatChangeKey {
+    class_name: Class,
+    version: i64,
+}
+
+impl SeatChangeKey {
+    pub fn new(class_name: Class, version: i64) -> SeatChangeKey {
+        SeatChangeKey {
+            class_name,
+            version,
+        }
+    }
+
+    pub fn get_version(&self) -> i64 {
+        self.version
+    }
+}
+
+impl From<SeatChangeKey> for Key {
+    fn from(s: SeatChangeKey) -> Key {
+        let key_tup: (&'static str, Class, i64) = ("seat_change", s.class_name, s.version);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Class(class_inner) = key_tup.1;
+            tup.add_string(class_inner);
+
+            tup.add_i64(key_tup.2);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl TryFrom<Key> for SeatChangeKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<SeatChangeKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("seat_change", class_name, version)
+                if tup.get_string_ref(0)?.as_str() != "seat_change" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let class_name = Class(tup.get_string_ref(1)?.to_string());
+
+                let version = tup.get_i64(2)?;
+
+                Ok(SeatChangeKey::new(class_name, version))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+// The value of a `SeatChangeKey` is the seats left after the change,
+// as a `ClassValue`.
+
+// ("seat_change_version", class_name)
+//
+// The version of the last change to the seats left in a class. A class
+// whose seats left never changed since the log was added has no
+// version, which is version 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeatChangeVersionKey {
+    class_name: Class,
+}
+
+impl SeatChangeVersionKey {
+    pub fn new(class_name: Class) -> SeatChangeVersionKey {
+        SeatChangeVersionKey { class_name }
+    }
+}
+
+impl From<SeatChangeVersionKey> for Key {
+    fn from(s: SeatChangeVersionKey) -> Key {
+        let key_tup: (&'static str, Class) = ("seat_change_version", s.class_name);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Class(class_inner) = key_tup.1;
+            tup.add_string(class_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeatChangeVersionValue {
+    version: i64,
+}
+
+impl SeatChangeVersionValue {
+    pub fn new(version: i64) -> SeatChangeVersionValue {
+        SeatChangeVersionValue { version }
+    }
+
+    pub fn get_version(&self) -> i64 {
+        self.version
+    }
+}
+
+impl From<SeatChangeVersionValue> for Value {
+    fn from(s: SeatChangeVersionValue) -> Value {
+        let val_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_i64(s.version);
+
+            tup
+        }
+        .pack();
+
+        val_bytes.into()
+    }
+}
+
+impl TryFrom<Value> for SeatChangeVersionValue {
+    type Error = FdbError;
+
+    fn try_from(value: Value) -> FdbResult<SeatChangeVersionValue> {
+        Tuple::from_bytes(value)
+            .and_then(|tup| {
+                // (version)
+                let version = tup.get_i64(0)?;
+
+                Ok(SeatChangeVersionValue::new(version))
+            })
+            .map_err(|_| FdbError::new(VALUE_CONVERTION_ERROR))
+    }
+}
+
+// ("attends", student, class_name)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttendsKey {
+    student: Student,
+    class_name: Class,
+}
+
+impl AttendsKey {
+    pub fn new(student: Student, class_name: Class) -> AttendsKey {
+        AttendsKey {
+            student,
+            class_name,
+        }
+    }
+}
+
+impl From<AttendsKey> for Key {
+    fn from(a: AttendsKey) -> Key {
+        let key_tup: (&'static str, Student, Class) = ("attends", a.student, a.class_name);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Student(student_inner) = key_tup.1;
+            tup.add_string(student_inner);
+
+            let Class(class_inner) = key_tup.2;
+            tup.add_string(class_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl From<AttendsKey> for Class {
+    fn from(a: AttendsKey) -> Class {
+        a.class_name
+    }
+}
+
+impl TryFrom<Key> for AttendsKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<AttendsKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("attends", student, class_name)
+                if tup.get_string_ref(0)?.as_str() != "attends" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let student = Student(tup.get_string_ref(1)?.to_string());
+
+                let class_name = Class(tup.get_string_ref(2)?.to_string());
+
+                Ok(AttendsKey::new(student, class_name))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+struct AttendsValue;
+
+impl AttendsValue {
+    fn new() -> AttendsValue {
+        AttendsValue
+    }
+}
+
+impl From<AttendsValue> for Value {
+    fn from(_: AttendsValue) -> Value {
+        let val_bytes = Bytes::new();
+
+        val_bytes.into()
+    }
+}
+
+// ("bundle", bundle, class_name)
+#[derive(Clone, Debug, PartialEq)]
+pub struct BundleKey {
+    bundle: Bundle,
+    class_name: Class,
+}
+
+impl BundleKey {
+    fn new(bundle: Bundle, class_name: Class) -> BundleKey {
+        BundleKey { bundle, class_name }
+    }
+}
+
+impl From<BundleKey> for Key {
+    fn from(b: BundleKey) -> Key {
+        let key_tup: (&'static str, Bundle, Class) = ("bundle", b.bundle, b.class_name);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Bundle(bundle_inner) = key_tup.1;
+            tup.add_string(bundle_inner);
+
+            let Class(class_inner) = key_tup.2;
+            tup.add_string(class_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl From<BundleKey> for Class {
+    fn from(b: BundleKey) -> Class {
+        b.class_name
+    }
+}
+
+impl TryFrom<Key> for BundleKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<BundleKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("bundle", bundle, class_name)
+                if tup.get_string_ref(0)?.as_str() != "bundle" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let bundle = Bundle(tup.get_string_ref(1)?.to_string());
+
+                let class_name = Class(tup.get_string_ref(2)?.to_string());
+
+                Ok(BundleKey::new(bundle, class_name))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+struct BundleValue;
+
+impl BundleValue {
+    fn new() -> BundleValue {
+        BundleValue
+    }
+}
+
+impl From<BundleValue> for Value {
+    fn from(_: BundleValue) -> Value {
+        let val_bytes = Bytes::new();
+
+        val_bytes.into()
+    }
+}
+
+// ("bundled", class_name)
+//
+// Reverse index from a class to the bundle it belongs to, so that we
+// do not have to scan all the bundles when dropping a bundled class.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BundledKey {
+    class_name: Class,
+}
+
+impl BundledKey {
+    fn new(class_name: Class) -> BundledKey {
+        BundledKey { class_name }
+    }
+}
+
+impl From<BundledKey> for Key {
+    fn from(b: BundledKey) -> Key {
+        let key_tup: (&'static str, Class) = ("bundled", b.class_name);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Class(class_inner) = key_tup.1;
+            tup.add_string(class_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl From<BundledKey> for Class {
+    fn from(b: BundledKey) -> Class {
+        b.class_name
+    }
+}
+
+impl TryFrom<Key> for BundledKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<BundledKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("bundled", class_name)
+                if tup.get_string_ref(0)?.as_str() != "bundled" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let class_name = Class(tup.get_string_ref(1)?.to_string());
+
+                Ok(BundledKey::new(class_name))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+// (bundle)
+#[derive(Clone, Debug, PartialEq)]
+pub struct BundledValue {
+    bundle: Bundle,
+}
+
+impl BundledValue {
+    fn new(bundle: Bundle) -> BundledValue {
+        BundledValue { bundle }
+    }
+}
+
+impl From<BundledValue> for Value {
+    fn from(b: BundledValue) -> Value {
+        let val_bytes = {
+            let mut tup = Tuple::new();
+
+            let Bundle(bundle_inner) = b.bundle;
+            tup.add_string(bundle_inner);
+
+            tup
+        }
+        .pack();
+
+        val_bytes.into()
+    }
+}
+
+impl TryFrom<Value> for BundledValue {
+    type Error = FdbError;
+
+    fn try_from(value: Value) -> FdbResult<BundledValue> {
+        Tuple::from_bytes(value)
+            .and_then(|tup| {
+                // (bundle)
+                let bundle = Bundle(tup.get_string_ref(0)?.to_string());
+
+                Ok(BundledValue::new(bundle))
+            })
+            .map_err(|_| FdbError::new(VALUE_CONVERTION_ERROR))
+    }
+}
+
+impl From<BundledValue> for Bundle {
+    fn from(b: BundledValue) -> Bundle {
+        b.bundle
+    }
+}
+
+// ("registration_window", "priority", priority_group)
+// ("registration_window", "open")
+// ("registration_window", "add_drop")
+// ("registration_window", "lottery")
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegistrationWindowKey {
+    phase: Phase,
+}
+
+impl RegistrationWindowKey {
+    fn new(phase: Phase) -> RegistrationWindowKey {
+        RegistrationWindowKey { phase }
+    }
+}
+
+impl From<RegistrationWindowKey> for Key {
+    fn from(r: RegistrationWindowKey) -> Key {
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string("registration_window".to_string());
+
+            match r.phase {
+                Phase::Priority(PriorityGroup(priority_group_inner)) => {
+                    tup.add_string("priority".to_string());
+                    tup.add_string(priority_group_inner);
+                }
+                Phase::Open => tup.add_string("open".to_string()),
+                Phase::AddDrop => tup.add_string("add_drop".to_string()),
+                Phase::Lottery => tup.add_string("lottery".to_string()),
+            }
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl From<RegistrationWindowKey> for Phase {
+    fn from(r: RegistrationWindowKey) -> Phase {
+        r.phase
+    }
+}
+
+impl TryFrom<Key> for RegistrationWindowKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<RegistrationWindowKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("registration_window", ...)
+                if tup.get_string_ref(0)?.as_str() != "registration_window" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let phase = match tup.get_string_ref(1)?.as_str() {
+                    "priority" => {
+                        Phase::Priority(PriorityGroup(tup.get_string_ref(2)?.to_string()))
+                    }
+                    "open" => Phase::Open,
+                    "add_drop" => Phase::AddDrop,
+                    "lottery" => Phase::Lottery,
+                    _ => return Err(FdbError::new(KEY_CONVERTION_ERROR)),
+                };
+
+                Ok(RegistrationWindowKey::new(phase))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+// (start, end)
+//
+// Start and end are Unix timestamps, and a window includes its start
+// but not its end.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegistrationWindowValue {
+    start: Timestamp,
+    end: Timestamp,
+}
+
+impl RegistrationWindowValue {
+    fn new(start: Timestamp, end: Timestamp) -> RegistrationWindowValue {
+        RegistrationWindowValue { start, end }
+    }
+
+    fn contains(&self, now: Timestamp) -> bool {
+        self.start <= now && now < self.end
+    }
+}
+
+impl From<RegistrationWindowValue> for Value {
+    fn from(r: RegistrationWindowValue) -> Value {
+        let val_bytes = {
+            let mut tup = Tuple::new();
+
+            let (Timestamp(start_inner), Timestamp(end_inner)) = (r.start, r.end);
+            tup.add_i64(start_inner);
+            tup.add_i64(end_inner);
+
+            tup
+        }
+        .pack();
+
+        val_bytes.into()
+    }
+}
+
+impl TryFrom<Value> for RegistrationWindowValue {
+    type Error = FdbError;
+
+    fn try_from(value: Value) -> FdbResult<RegistrationWindowValue> {
+        Tuple::from_bytes(value)
+            .and_then(|tup| {
+                // (start, end)
+                let start = Timestamp(tup.get_i64(0)?);
+
+                let end = Timestamp(tup.get_i64(1)?);
+
+                Ok(RegistrationWindowValue::new(start, end))
+            })
+            .map_err(|_| FdbError::new(VALUE_CONVERTION_ERROR))
+    }
+}
+
+// ("priority_group", student)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriorityGroupKey {
+    student: Student,
+}
+
+impl PriorityGroupKey {
+    fn new(student: Student) -> PriorityGroupKey {
+        PriorityGroupKey { student }
+    }
+}
+
+impl From<PriorityGroupKey> for Key {
+    fn from(p: PriorityGroupKey) -> Key {
+        let key_tup: (&'static str, Student) = ("priority_group", p.student);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Student(student_inner) = key_tup.1;
+            tup.add_string(student_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl TryFrom<Key> for PriorityGroupKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<PriorityGroupKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("priority_group", student)
+                if tup.get_string_ref(0)?.as_str() != "priority_group" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let student = Student(tup.get_string_ref(1)?.to_string());
+
+                Ok(PriorityGroupKey::new(student))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+// (priority_group)
+#[derive(Clone, Debug, PartialEq)]
+pub struct PriorityGroupValue {
+    priority_group: PriorityGroup,
+}
+
+impl PriorityGroupValue {
+    fn new(priority_group: PriorityGroup) -> PriorityGroupValue {
+        PriorityGroupValue { priority_group }
+    }
+}
+
+impl From<PriorityGroupValue> for Value {
+    fn from(p: PriorityGroupValue) -> Value {
+        let val_bytes = {
+            let mut tup = Tuple::new();
+
+            let PriorityGroup(priority_group_inner) = p.priority_group;
+            tup.add_string(priority_group_inner);
+
+            tup
+        }
+        .pack();
+
+        val_bytes.into()
+    }
+}
+
+impl TryFrom<Value> for PriorityGroupValue {
+    type Error = FdbError;
+
+    fn try_from(value: Value) -> FdbResult<PriorityGroupValue> {
+        Tuple::from_bytes(value)
+            .and_then(|tup| {
+                // (priority_group)
+                let priority_group = PriorityGroup(tup.get_string_ref(0)?.to_string());
+
+                Ok(PriorityGroupValue::new(priority_group))
+            })
+            .map_err(|_| FdbError::new(VALUE_CONVERTION_ERROR))
+    }
+}
+
+impl From<PriorityGroupValue> for PriorityGroup {
+    fn from(p: PriorityGroupValue) -> PriorityGroup {
+        p.priority_group
+    }
+}
+
+// ("lottery_request", student)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LotteryRequestKey {
+    student: Student,
+}
+
+impl LotteryRequestKey {
+    fn new(student: Student) -> LotteryRequestKey {
+        LotteryRequestKey { student }
+    }
+}
+
+impl From<LotteryRequestKey> for Key {
+    fn from(l: LotteryRequestKey) -> Key {
+        let key_tup: (&'static str, Student) = ("lottery_request", l.student);
+
+        let key_bytes = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((key_tup.0).to_string());
+
+            let Student(student_inner) = key_tup.1;
+            tup.add_string(student_inner);
+
+            tup
+        }
+        .pack();
+
+        key_bytes.into()
+    }
+}
+
+impl From<LotteryRequestKey> for Student {
+    fn from(l: LotteryRequestKey) -> Student {
+        l.student
+    }
+}
+
+impl TryFrom<Key> for LotteryRequestKey {
+    type Error = FdbError;
+
+    fn try_from(key: Key) -> FdbResult<LotteryRequestKey> {
+        Tuple::from_bytes(key)
+            .and_then(|tup| {
+                // ("lottery_request", student)
+                if tup.get_string_ref(0)?.as_str() != "lottery_request" {
+                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
+                }
+
+                let student = Student(tup.get_string_ref(1)?.to_string());
+
+                Ok(LotteryRequestKey::new(student))
+            })
+            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+    }
+}
+
+// (class_name, ...)
+//
+// Classes are in the order of the student's preference.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LotteryRequestValue {
+    class_names: Vec<Class>,
+}
+
+impl LotteryRequestValue {
+    fn new(class_names: Vec<Class>) -> LotteryRequestValue {
+        LotteryRequestValue { class_names }
+    }
+}
+
+impl From<LotteryRequestValue> for Value {
+    fn from(l: LotteryRequestValue) -> Value {
+        let val_bytes = {
+            let mut tup = Tuple::new();
+
+            for Class(class_inner) in l.class_names {
+                tup.add_string(class_inner);
+            }
+
+            tup
+        }
+        .pack();
+
+        val_bytes.into()
+    }
+}
+
+impl TryFrom<Value> for LotteryRequestValue {
+    type Error = FdbError;
+
+    fn try_from(value: Value) -> FdbResult<LotteryRequestValue> {
+        Tuple::from_bytes(value)
+            .and_then(|tup| {
+                // (class_name, ...)
+                let mut class_names = Vec::new();
+
+                for i in 0..tup.size() {
+                    class_names.push(Class(tup.get_string_ref(i)?.to_string()));
+                }
+
+                Ok(LotteryRequestValue::new(class_names))
+            })
+            .map_err(|_| FdbError::new(VALUE_CONVERTION_ERROR))
+    }
+}
+
+impl From<LotteryRequestValue> for Vec<Class> {
+    fn from(l: LotteryRequestValue) -> Vec<Class> {
+        l.class_names
+    }
+}
+
+// ("class")
+#[derive(Default)]
+pub struct ClassPrefix;
+
+impl ClassPrefix {
+    pub fn new() -> ClassPrefix {
+        ClassPrefix
+    }
+
+    pub fn get_range(&self) -> Range {
+        // ("class")
+        let class_tup: (&'static str,) = ("class",);
+
+        let class_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((class_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        class_range
+    }
+}
+
+// ("attends")
+#[derive(Default)]
+pub struct AttendsPrefix;
+
+impl AttendsPrefix {
+    pub fn new() -> AttendsPrefix {
+        AttendsPrefix
+    }
+
+    pub fn get_range(&self) -> Range {
+        // ("attends")
+        let attends_tup: (&'static str,) = ("attends",);
+
+        let attends_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((attends_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        attends_range
+    }
+}
+
+// ("attends", student)
+pub struct AttendsStudentPrefix {
+    student: Student,
+}
+
+impl AttendsStudentPrefix {
+    pub fn new(student: Student) -> AttendsStudentPrefix {
+        AttendsStudentPrefix { student }
+    }
+
+    pub fn get_range(&self) -> Range {
+        // ("attends", student)
+        let attends_student_tup: (&'static str, Student) = ("attends", self.student.clone());
+
+        let attends_student_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((attends_student_tup.0).to_string());
+
+            let Student(student_inner) = attends_student_tup.1;
+            tup.add_string(student_inner);
+
+            tup
+        }
+        .range(Bytes::new());
+
+        attends_student_range
+    }
+}
+
+// ("bundle")
+struct BundlePrefix;
+
+impl BundlePrefix {
+    fn new() -> BundlePrefix {
+        BundlePrefix
+    }
+
+    fn get_range(&self) -> Range {
+        // ("bundle")
+        let bundle_tup: (&'static str,) = ("bundle",);
+
+        let bundle_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((bundle_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        bundle_range
+    }
+}
+
+// ("bundle", bundle)
+struct BundleClassPrefix {
+    bundle: Bundle,
+}
+
+impl BundleClassPrefix {
+    fn new(bundle: Bundle) -> BundleClassPrefix {
+        BundleClassPrefix { bundle }
+    }
+
+    fn get_range(&self) -> Range {
+        // ("bundle", bundle)
+        let bundle_class_tup: (&'static str, Bundle) = ("bundle", self.bundle.clone());
+
+        let bundle_class_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((bundle_class_tup.0).to_string());
+
+            let Bundle(bundle_inner) = bundle_class_tup.1;
+            tup.add_string(bundle_inner);
+
+            tup
+        }
+        .range(Bytes::new());
+
+        bundle_class_range
+    }
+}
+
+// ("bundled")
+struct BundledPrefix;
+
+impl BundledPrefix {
+    fn new() -> BundledPrefix {
+        BundledPrefix
+    }
+
+    fn get_range(&self) -> Range {
+        // ("bundled")
+        let bundled_tup: (&'static str,) = ("bundled",);
+
+        let bundled_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((bundled_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        bundled_range
+    }
+}
+
+// ("registration_window")
+struct RegistrationWindowPrefix;
+
+impl RegistrationWindowPrefix {
+    fn new() -> RegistrationWindowPrefix {
+        RegistrationWindowPrefix
+    }
+
+    fn get_range(&self) -> Range {
+        // ("registration_window")
+        let registration_window_tup: (&'static str,) = ("registration_window",);
+
+        let registration_window_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((registration_window_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        registration_window_range
+    }
+}
+
+// ("priority_group")
+struct PriorityGroupPrefix;
+
+impl PriorityGroupPrefix {
+    fn new() -> PriorityGroupPrefix {
+        PriorityGroupPrefix
+    }
+
+    fn get_range(&self) -> Range {
+        // ("priority_group")
+        let priority_group_tup: (&'static str,) = ("priority_group",);
+
+        let priority_group_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((priority_group_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        priority_group_range
+    }
+}
+
+// ("lottery_request")
+struct LotteryRequestPrefix;
+
+impl LotteryRequestPrefix {
+    fn new() -> LotteryRequestPrefix {
+        LotteryRequestPrefix
+    }
+
+    fn get_range(&self) -> Range {
+        // ("lottery_request")
+        let lottery_request_tup: (&'static str,) = ("lottery_request",);
+
+        let lottery_request_range = {
+            let mut tup = Tuple::new();
+
+            tup.add_string((lottery_request_tup.0).to_string());
+
+            tup
+        }
+        .range(Bytes::new());
+
+        lottery_request_range
+    }
+}
+
+// Seats in every class created by `init`.
+pub const CLASS_CAPACITY: u8 = 100;
+
+async fn add_class<T: KvTransaction>(tr: &T, class_name: Class) -> FdbResult<()> {
+    set_seats_left(tr, class_name, CLASS_CAPACITY).await
+}
+
+// How many changes to the seats left in a class the seat change log
+// keeps. A stream of seat changes cannot be resumed after more changes
+// than that.
+pub const SEAT_CHANGES_KEPT: i64 = 1000;
+
+// Sets the seats left in `class_name`, and adds the change to the seat
+// change log, dropping the oldest change once there are more than
+// `SEAT_CHANGES_KEPT`. Everything that changes the seats left in a
+// class goes through here.
+//
+// Transactions changing the seats left in the same class conflict on
+// the version, but they already conflict on the seats left.
+pub(crate) async fn set_seats_left<T: KvTransaction>(
+    tr: &T,
+    class_name: Class,
+    seats_left: u8,
+) -> FdbResult<()> {
+    // ("seat_change_version", class_name)
+    let version_key = SeatChangeVersionKey::new(class_name.clone());
+
+    let version = match tr.get(version_key.clone()).await? {
+        Some(value) => TryInto::<SeatChangeVersionValue>::try_into(value)?.get_version(),
+        None => 0,
+    } + 1;
+
+    tr.set(version_key, SeatChangeVersionValue::new(version));
+
+    // ("seat_change", class_name, version)
+    tr.set(
+        SeatChangeKey::new(class_name.clone(), version),
+        ClassValue::new(seats_left),
+    );
+
+    if version > SEAT_CHANGES_KEPT {
+        tr.clear(SeatChangeKey::new(
+            class_name.clone(),
+            version - SEAT_CHANGES_KEPT,
+        ));
+    }
+
+    // ("class", class_name)
+    tr.set(ClassKey::new(class_name), ClassValue::new(seats_left));
+
+    Ok(())
+}
+
+fn add_bundle<T: KvTransaction>(tr: &T, bundle: Bundle, class_names: Vec<Class>) {
+    for class_name in class_names {
+        // ("bundle", bundle, class_name)
+        let bundle_key = BundleKey::new(bundle.clone(), class_name.clone());
+
+        // ""
+        let bundle_value = BundleValue::new();
+
+        tr.set(bundle_key, bundle_value);
+
+        // ("bundled", class_name)
+        let bundled_key = BundledKey::new(class_name);
+
+        // (bundle)
+        let bundled_value = BundledValue::new(bundle.clone());
+
+        tr.set(bundled_key, bundled_value);
+    }
+}
+
+pub fn set_registration_window<T: KvTransaction>(
+    tr: &T,
+    phase: Phase,
+    start: Timestamp,
+    end: Timestamp,
+) {
+    // ("registration_window", ...)
+    let registration_window_key = RegistrationWindowKey::new(phase);
+
+    // (start, end)
+    let registration_window_value = RegistrationWindowValue::new(start, end);
+
+    tr.set(registration_window_key, registration_window_value);
+}
+
+pub fn set_priority_group<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    priority_group: PriorityGroup,
+) {
+    // ("priority_group", student)
+    let priority_group_key = PriorityGroupKey::new(student);
+
+    // (priority_group)
+    let priority_group_value = PriorityGroupValue::new(priority_group);
+
+    tr.set(priority_group_key, priority_group_value);
+}
+
+const LEVELS: [&str; 9] = [
+    "intro",
+    "for dummies",
+    "remedial",
+    "101",
+    "201",
+    "301",
+    "mastery",
+    "lab",
+    "seminar",
+];
+
+const TYPES: [&str; 10] = [
+    "chem", "bio", "cs", "geometry", "calc", "alg", "film", "music", "art", "dance",
+];
+
+const TIMES: [&str; 18] = [
+    "2:00", "3:00", "4:00", "5:00", "6:00", "7:00", "8:00", "9:00", "10:00", "11:00", "12:00",
+    "13:00", "14:00", "15:00", "16:00", "17:00", "18:00", "19:00",
+];
+
+pub(crate) fn init_class_names() -> Vec<Class> {
+    let mut class_names = Vec::new();
+
+    for level in LEVELS {
+        // we can't use type here as that is a keyword in Rust.
+        for typ in TYPES {
+            for time in TIMES {
+                class_names.push(Class(format!("{} {} {}", time, typ, level).to_string()));
+            }
+        }
+    }
+
+    class_names
+}
+
+// Class names are of the form "time type level", so two classes are
+// held at the same time when their names start with the same time.
+fn class_time(class_name: &Class) -> &str {
+    let Class(class_inner) = class_name;
+
+    // Safety: `split` always returns at least one item.
+    class_inner.split(' ').next().unwrap()
+}
+
+// A "lab" is taken together with the "101" lecture of the same type,
+// and a "seminar" with the "301" lecture. The lab or seminar is held
+// in the time slot that immediately follows the lecture.
+fn init_bundles() -> Vec<(Bundle, Vec<Class>)> {
+    let mut bundles = Vec::new();
+
+    for typ in TYPES {
+        for times in TIMES.windows(2) {
+            let (lecture_time, follow_up_time) = (times[0], times[1]);
+
+            for (lecture, follow_up) in [("101", "lab"), ("301", "seminar")] {
+                bundles.push((
+                    Bundle(format!(
+                        "{} {} {} with {}",
+                        lecture_time, typ, lecture, follow_up
+                    )),
+                    vec![
+                        Class(format!("{} {} {}", lecture_time, typ, lecture)),
+                        Class(format!("{} {} {}", follow_up_time, typ, follow_up)),
+                    ],
+                ));
+            }
+        }
+    }
+
+    bundles
+}
+
+pub async fn init<D: KvDatabase>(db: &D) -> FdbResult<()> {
+    db.run(|tr| async move {
+        // ("attends")
+        let attends_prefix_range = AttendsPrefix::new().get_range();
+        tr.clear_range(attends_prefix_range);
+
+        // ("class")
+        let class_prefix_range = ClassPrefix::new().get_range();
+        tr.clear_range(class_prefix_range);
+
+        // ("bundle")
+        let bundle_prefix_range = BundlePrefix::new().get_range();
+        tr.clear_range(bundle_prefix_range);
+
+        // ("bundled")
+        let bundled_prefix_range = BundledPrefix::new().get_range();
+        tr.clear_range(bundled_prefix_range);
+
+        // ("registration_window")
+        let registration_window_prefix_range = RegistrationWindowPrefix::new().get_range();
+        tr.clear_range(registration_window_prefix_range);
+
+        // ("priority_group")
+        let priority_group_prefix_range = PriorityGroupPrefix::new().get_range();
+        tr.clear_range(priority_group_prefix_range);
+
+        // ("lottery_request")
+        let lottery_request_prefix_range = LotteryRequestPrefix::new().get_range();
+        tr.clear_range(lottery_request_prefix_range);
+
+        // The seat change log is kept, so that streams of seat changes
+        // see the seats left go back to `CLASS_CAPACITY`.
+        for class_name in init_class_names() {
+            add_class(&tr, class_name).await?;
+        }
+
+        for (bundle, class_names) in init_bundles() {
+            add_bundle(&tr, bundle, class_names);
+        }
+
+        Ok(())
+    })
+    .await
+}
+
+// async fn available_classes(tr: &FdbTransaction) -> FdbResult<Vec<Class>> {
+//     // ("class", ...)
+//     let mut class_range_stream = ClassPrefix::new()
+//         .get_range()
+//         .into_stream(tr, RangeOptions::default());
+
+//     let mut class_names = Vec::new();
+
+//     while let Some(x) = class_range_stream.next().await {
+//         let key = x?.into_key();
+
+//         let class_key = TryInto::<ClassKey>::try_into(key)?;
+
+//         class_names.push(class_key.into());
+//     }
+
+//     Ok(class_names)
+// }
+
+pub async fn available_classes<T: KvTransaction>(tr: &T) -> FdbResult<Vec<Class>> {
+    // ("class", ...)
+    let mut class_range_stream = tr.get_range(ClassPrefix::new().get_range());
+
+    let mut class_names = Vec::new();
+
+    while let Some(x) = class_range_stream.next().await {
+        let (key, value) = x?;
+
+        let class_key = TryInto::<ClassKey>::try_into(key)?;
+
+        let seats_available = TryInto::<ClassValue>::try_into(value)?.get_val();
+
+        if seats_available > 0 {
+            class_names.push(class_key.into());
+        }
+    }
+
+    Ok(class_names)
+}
+
+// fn signup(tr: &FdbTransaction, student: Student, class_name: Class) {
+//     // ("attends", student, class_name)
+//     let attends_key = AttendsKey::new(student, class_name);
+
+//     // ""
+//     let attends_value = AttendsValue::new();
+
+//     tr.set(attends_key, attends_value);
+// }
+
+pub const NO_REMAINING_SEATS: i32 = 996;
+pub const ALREADY_SIGNED_UP: i32 = 997;
+
+// async fn signup(tr: &FdbTransaction, student: Student, class_name: Class) -> FdbResult<()> {
+//     // ("attends", student, class_name)
+//     let attends_key = AttendsKey::new(student, class_name.clone());
+
+//     // ""
+//     let attends_value = AttendsValue::new();
+
+//     if tr.get(attends_key.clone()).await?.is_some() {
+//         Err(FdbError::new(ALREADY_SIGNED_UP))
+//     } else {
+//         // ("class", class_name)
+//         let class_key = ClassKey::new(class_name);
+
+//         // Safety: It is safe to `unwrap()` here because in our data
+//         // model assume that key `("class", class_name)` will *always*
+//         // have seats left value.
+//         let class_value = ClassValue::from(tr.get(class_key.clone()).await?.unwrap());
+
+//         let seats_left = class_value.get_val();
+
+//         if seats_left == 0 {
+//             Err(FdbError::new(NO_REMAINING_SEATS))
+//         } else {
+//             let updated_class_value = ClassValue::new(seats_left - 1);
+
+//             tr.set(class_key, updated_class_value);
+
+//             tr.set(attends_key, attends_value);
+
+//             Ok(())
+//         }
+//     }
+// }
+
+async fn get_attends_student_keyvalue<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+) -> FdbResult<Vec<(Key, Value)>> {
+    // ("attends", student, ...)
+    let mut range_stream = tr.get_range(AttendsStudentPrefix::new(student).get_range());
+
+    let mut kvs = Vec::new();
+
+    while let Some(x) = range_stream.next().await {
+        let kv = x?;
+
+        kvs.push(kv);
+    }
+
+    Ok(kvs)
+}
+
+pub const TOO_MANY_CLASSES: i32 = 995;
+
+// Classes a student attends at most.
+pub const MAX_CLASSES: usize = 5;
+
+pub const NO_SUCH_CLASS: i32 = 988;
+
+pub const PRIORITY_REGISTRATION_ONLY: i32 = 990;
+pub const REGISTRATION_CLOSED: i32 = 991;
+
+async fn get_registration_windows<T: KvTransaction>(
+    tr: &T,
+) -> FdbResult<Vec<(Phase, RegistrationWindowValue)>> {
+    // ("registration_window", ...)
+    let mut range_stream = tr.get_range(RegistrationWindowPrefix::new().get_range());
+
+    let mut registration_windows = Vec::new();
+
+    while let Some(x) = range_stream.next().await {
+        let (key, value) = x?;
+
+        let phase = Phase::from(TryInto::<RegistrationWindowKey>::try_into(key)?);
+
+        let registration_window_value = TryInto::<RegistrationWindowValue>::try_into(value)?;
+
+        registration_windows.push((phase, registration_window_value));
+    }
+
+    Ok(registration_windows)
+}
+
+async fn check_registration_open<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    now: Timestamp,
+) -> FdbResult<()> {
+    let registration_windows = get_registration_windows(tr).await?;
+
+    // Registration is always open until the first registration window
+    // is set up.
+    if registration_windows.is_empty() {
+        return Ok(());
+    }
+
+    let mut priority_phase = false;
+
+    for (phase, registration_window_value) in registration_windows {
+        if !registration_window_value.contains(now) {
+            continue;
+        }
+
+        match phase {
+            Phase::Open | Phase::AddDrop => return Ok(()),
+            // Students only submit their preferences during the
+            // lottery.
+            Phase::Lottery => {}
+            Phase::Priority(priority_group) => {
+                priority_phase = true;
+
+                // ("priority_group", student)
+                let priority_group_key = PriorityGroupKey::new(student.clone());
+
+                if let Some(value) = tr.get(priority_group_key).await? {
+                    if PriorityGroup::from(TryInto::<PriorityGroupValue>::try_into(value)?)
+                        == priority_group
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    if priority_phase {
+        Err(FdbError::new(PRIORITY_REGISTRATION_ONLY))
+    } else {
+        Err(FdbError::new(REGISTRATION_CLOSED))
+    }
+}
+
+pub async fn signup<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_name: Class,
+    now: Timestamp,
+) -> FdbResult<()> {
+    check_registration_open(tr, student.clone(), now).await?;
+
+    // The classes of a bundle are only taken together, with
+    // `signup_bundle`.
+    if get_class_bundle(tr, class_name.clone()).await?.is_some() {
+        return Err(FdbError::new(INCOMPLETE_BUNDLE));
+    }
+
+    take_seat(tr, student, class_name).await
+}
+
+// Takes a seat in `class_name`, whether or not the class is part of a
+// bundle.
+async fn take_seat<T: KvTransaction>(tr: &T, student: Student, class_name: Class) -> FdbResult<()> {
+    // ("attends", student, class_name)
+    let attends_key = AttendsKey::new(student.clone(), class_name.clone());
+
+    // ""
+    let attends_value = AttendsValue::new();
+
+    if tr.get(attends_key.clone()).await?.is_some() {
+        Err(FdbError::new(ALREADY_SIGNED_UP))
+    } else {
+        // ("class", class_name)
+        let class_key = ClassKey::new(class_name.clone());
+
+        // The class name comes from the user, and may not exist.
+        let class_value = TryInto::<ClassValue>::try_into(
+            tr.get(class_key)
+                .await?
+                .ok_or_else(|| FdbError::new(NO_SUCH_CLASS))?,
+        )?;
+
+        let seats_left = class_value.get_val();
+
+        if seats_left == 0 {
+            Err(FdbError::new(NO_REMAINING_SEATS))
+        } else {
+            let attends_student_kvs = get_attends_student_keyvalue(tr, student).await?;
+
+            if attends_student_kvs.len() == MAX_CLASSES {
+                Err(FdbError::new(TOO_MANY_CLASSES))
+            } else {
+                set_seats_left(tr, class_name, seats_left - 1).await?;
+
+                tr.set(attends_key, attends_value);
+
+                Ok(())
+            }
+        }
+    }
+}
+
+// // Unlike other bindings, we cannot name this function as `drop`,
+// // because `drop` is already used in Rust.
+// fn dropout(tr: &FdbTransaction, student: Student, class_name: Class) {
+//     // ("attends", student, class_name)
+//     let attends_key = AttendsKey::new(student, class_name);
+
+//     tr.clear(attends_key);
+// }
+
+// Unlike other bindings, we cannot name this function as `drop`,
+// because `drop` is already used in Rust.
+pub async fn dropout<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_name: Class,
+    now: Timestamp,
+) -> FdbResult<()> {
+    check_registration_open(tr, student.clone(), now).await?;
+
+    // The classes of a bundle are only dropped together, with
+    // `dropout_bundle`.
+    if get_class_bundle(tr, class_name.clone()).await?.is_some() {
+        return Err(FdbError::new(INCOMPLETE_BUNDLE));
+    }
+
+    give_up_seat(tr, student, class_name).await
+}
+
+// Drops `class_name` and the other classes of its bundle, in the same
+// transaction, so the student is left with either all of them or none.
+pub async fn dropout_bundle<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_name: Class,
+    now: Timestamp,
+) -> FdbResult<()> {
+    check_registration_open(tr, student.clone(), now).await?;
+
+    let bundle = match get_class_bundle(tr, class_name).await? {
+        Some(bundle) => bundle,
+        None => return Err(FdbError::new(INCOMPLETE_BUNDLE)),
+    };
+
+    for bundle_class_name in get_bundle_classes(tr, bundle).await? {
+        give_up_seat(tr, student.clone(), bundle_class_name).await?;
+    }
+
+    Ok(())
+}
+
+// Gives up the seat in `class_name`, whether or not the class is part
+// of a bundle.
+async fn give_up_seat<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_name: Class,
+) -> FdbResult<()> {
+    // ("attends", student, class_name)
+    let attends_key = AttendsKey::new(student, class_name.clone());
+
+    if tr.get(attends_key.clone()).await?.is_none() {
+        // not taking class
+        Ok(())
+    } else {
+        // ("class", class_name)
+        let class_key = ClassKey::new(class_name.clone());
+
+        // A class can be deleted while students still attend it. There
+        // are no seats to give back then, but the student is still
+        // dropped.
+        if let Some(value) = tr.get(class_key).await? {
+            let class_value = TryInto::<ClassValue>::try_into(value)?;
+
+            let seats_left = class_value.get_val();
+
+            set_seats_left(tr, class_name, seats_left + 1).await?;
+        }
+
+        tr.clear(attends_key);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OldClass(pub Class);
+
+#[derive(Clone, Debug)]
+pub struct NewClass(pub Class);
+
+pub async fn switch_classes<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    old_class: OldClass,
+    new_class: NewClass,
+    now: Timestamp,
+) -> FdbResult<()> {
+    check_registration_open(tr, student.clone(), now).await?;
+
+    // ("attends", student, old_class)
+    let old_attends_key = AttendsKey::new(student.clone(), old_class.0.clone());
+
+    // ("attends", student, new_class)
+    let new_attends_key = AttendsKey::new(student.clone(), new_class.0.clone());
+
+    if tr.get(old_attends_key).await?.is_none() {
+        if tr.get(new_attends_key).await?.is_some() {
+            // already switched, by an earlier attempt that was
+            // committed without us knowing
+            return Ok(());
+        }
+
+        return Err(FdbError::new(NOT_SIGNED_UP));
+    }
+
+    // A switch is a dropout followed by a signup, in one transaction.
+    // When the signup fails, for example because the student already
+    // attends `new_class`, the error rolls back the dropout too.
+    dropout(
+        tr,
+        student.clone(),
+        {
+            let OldClass(class_name) = old_class;
+            class_name
+        },
+        now,
+    )
+    .await?;
+
+    signup(
+        tr,
+        student,
+        {
+            let NewClass(class_name) = new_class;
+            class_name
+        },
+        now,
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub const NOT_SIGNED_UP: i32 = 993;
+pub const TIME_CONFLICT: i32 = 992;
+
+// Checks that `student` can attend `class_name` at its time, once
+// `replaced_class_name` has been given up.
+async fn check_time_conflict<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_name: &Class,
+    replaced_class_name: &Class,
+) -> FdbResult<()> {
+    for kv in get_attends_student_keyvalue(tr, student).await? {
+        let attends_key = TryInto::<AttendsKey>::try_into(kv.0)?;
+
+        let attends_class_name = Class::from(attends_key);
+
+        if attends_class_name != *replaced_class_name
+            && class_time(&attends_class_name) == class_time(class_name)
+        {
+            return Err(FdbError::new(TIME_CONFLICT));
+        }
+    }
+
+    Ok(())
+}
+
+// Unlike `switch_classes`, trading seats does not need a free seat in
+// either class. Each student takes the seat the other student gives
+// up, so the seats left in both classes stay the same.
+pub async fn trade_seats<T: KvTransaction>(
+    tr: &T,
+    student_a: Student,
+    class_a: Class,
+    student_b: Student,
+    class_b: Class,
+    now: Timestamp,
+) -> FdbResult<()> {
+    check_registration_open(tr, student_a.clone(), now).await?;
+    check_registration_open(tr, student_b.clone(), now).await?;
+
+    // ("attends", student_a, class_a)
+    let attends_a_key = AttendsKey::new(student_a.clone(), class_a.clone());
+
+    // ("attends", student_b, class_b)
+    let attends_b_key = AttendsKey::new(student_b.clone(), class_b.clone());
+
+    if tr.get(attends_a_key.clone()).await?.is_none()
+        || tr.get(attends_b_key.clone()).await?.is_none()
+    {
+        return Err(FdbError::new(NOT_SIGNED_UP));
+    }
+
+    // Trading a class of a bundle on its own would split the bundle.
+    for class_name in [&class_a, &class_b] {
+        if get_class_bundle(tr, class_name.clone()).await?.is_some() {
+            return Err(FdbError::new(INCOMPLETE_BUNDLE));
+        }
+    }
+
+    // ("attends", student_a, class_b)
+    let traded_a_key = AttendsKey::new(student_a.clone(), class_b.clone());
+
+    // ("attends", student_b, class_a)
+    let traded_b_key = AttendsKey::new(student_b.clone(), class_a.clone());
+
+    if tr.get(traded_a_key.clone()).await?.is_some()
+        || tr.get(traded_b_key.clone()).await?.is_some()
+    {
+        return Err(FdbError::new(ALREADY_SIGNED_UP));
+    }
+
+    // Trading is one class for another, so it cannot take a student
+    // over `MAX_CLASSES`.
+    check_time_conflict(tr, student_a, &class_b, &class_a).await?;
+    check_time_conflict(tr, student_b, &class_a, &class_b).await?;
+
+    tr.clear(attends_a_key);
+    tr.clear(attends_b_key);
+
+    tr.set(traded_a_key, AttendsValue::new());
+    tr.set(traded_b_key, AttendsValue::new());
+
+    Ok(())
+}
+
+// Signs `student` up for the best schedule that can be made out of
+// `class_names`, which are in the order of the student's preference.
+// Classes without seats, at the same time as a class the student
+// already has, or that are part of a bundle, are passed over in favour
+// of the next one in the list.
+//
+// We only read the seats of the classes we try, rather than all
+// `available_classes`, so that signups for unrelated classes do not
+// conflict with this transaction. If a seat we took is gone by the
+// time we commit, `run()` retries and the next-best class is picked.
+pub async fn build_schedule<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_names: Vec<Class>,
+    now: Timestamp,
+) -> FdbResult<Vec<Class>> {
+    let mut schedule = Vec::new();
+
+    for class_name in class_names {
+        match check_time_conflict(tr, student.clone(), &class_name, &class_name).await {
+            Ok(()) => {}
+            Err(err) if err.code() == TIME_CONFLICT => continue,
+            Err(err) => return Err(err),
+        }
+
+        match signup(tr, student.clone(), class_name.clone(), now).await {
+            Ok(()) => schedule.push(class_name),
+            Err(err)
+                if err.code() == NO_REMAINING_SEATS
+                    || err.code() == ALREADY_SIGNED_UP
+                    || err.code() == INCOMPLETE_BUNDLE => {}
+            Err(err) if err.code() == TOO_MANY_CLASSES => break,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(schedule)
+}
+
+pub const LOTTERY_CLOSED: i32 = 989;
+
+pub async fn submit_preferences<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_names: Vec<Class>,
+    now: Timestamp,
+) -> FdbResult<()> {
+    let registration_windows = get_registration_windows(tr).await?;
+
+    // Just like registration, the lottery is open until the first
+    // registration window is set up.
+    if !registration_windows.is_empty()
+        && !registration_windows
+            .iter()
+            .any(|(phase, registration_window_value)| {
+                *phase == Phase::Lottery && registration_window_value.contains(now)
+            })
+    {
+        return Err(FdbError::new(LOTTERY_CLOSED));
+    }
+
+    // The lottery assigns classes one at a time, so it cannot assign
+    // the classes of a bundle.
+    for class_name in &class_names {
+        if get_class_bundle(tr, class_name.clone()).await?.is_some() {
+            return Err(FdbError::new(INCOMPLETE_BUNDLE));
+        }
+    }
+
+    // ("lottery_request", student)
+    let lottery_request_key = LotteryRequestKey::new(student);
+
+    // (class_name, ...)
+    let lottery_request_value = LotteryRequestValue::new(class_names);
+
+    tr.set(lottery_request_key, lottery_request_value);
+
+    Ok(())
+}
+
+struct LotteryEntry {
+    student: Student,
+    preferences: Vec<Class>,
+    classes: Vec<Class>,
+    won: Vec<Class>,
+}
+
+// Number of students whose classes are read, or whose lottery results
+// are written, in a single transaction.
+const LOTTERY_BATCH_SIZE: usize = 50;
+
+// Reads the key-value pairs of `range`, a batch of them per
+// transaction, so that no transaction runs into FoundationDB's five
+// second limit, however many classes or requests there are.
+async fn read_range_in_batches<D: KvDatabase>(
+    db: &D,
+    range: Range,
+) -> FdbResult<Vec<(Key, Value)>> {
+    scan::scan_shard(
+        db,
+        range.begin().clone(),
+        range.end().clone(),
+        &|kvs: &mut Vec<(Key, Value)>, key, value| kvs.push((key, value)),
+    )
+    .await
+}
+
+// Signs `student` up for `class_name` as a lottery result, if it can
+// still be taken. Seats, classes, or times can be taken by students
+// signing up while the lottery runs, and classes can be deleted.
+async fn take_lottery_seat<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_name: &Class,
+) -> FdbResult<bool> {
+    // ("attends", student, class_name)
+    let attends_key = AttendsKey::new(student.clone(), class_name.clone());
+
+    // ("class", class_name)
+    let class_key = ClassKey::new(class_name.clone());
+
+    let seats_left = match tr.get(class_key).await? {
+        Some(value) => TryInto::<ClassValue>::try_into(value)?.get_val(),
+        None => return Ok(false),
+    };
+
+    if seats_left == 0
+        || tr.get(attends_key.clone()).await?.is_some()
+        || get_attends_student_keyvalue(tr, student.clone())
+            .await?
+            .len()
+            >= MAX_CLASSES
+    {
+        return Ok(false);
+    }
+
+    match check_time_conflict(tr, student, class_name, class_name).await {
+        Ok(()) => {}
+        Err(err) if err.code() == TIME_CONFLICT => return Ok(false),
+        Err(err) => return Err(err),
+    }
+
+    set_seats_left(tr, class_name.clone(), seats_left - 1).await?;
+
+    tr.set(attends_key, AttendsValue::new());
+
+    Ok(true)
+}
+
+// Allocates seats among the submitted preferences. Students take turns
+// picking their most preferred class that still has a seat, one class
+// per turn, in a random order derived from `seed` that is shuffled
+// again for every round, so that nobody picks first in every round.
+// Running the lottery with the same seed against the same data gives
+// the same result.
+//
+// The classes, the requests, and the classes every student attends
+// are read in batches, at different versions. Anything that changes
+// in the meantime is checked again when the results are written, and
+// a student whose seat is gone by then gets their next preference that
+// is still free.
+pub async fn run_lottery<D: KvDatabase>(db: &D, seed: u64) -> FdbResult<Vec<(Student, Class)>> {
+    let mut seats_left = HashMap::new();
+
+    // ("class", ...)
+    for (key, value) in read_range_in_batches(db, ClassPrefix::new().get_range()).await? {
+        let class_key = TryInto::<ClassKey>::try_into(key)?;
+
+        seats_left.insert(
+            Class::from(class_key),
+            TryInto::<ClassValue>::try_into(value)?.get_val(),
+        );
+    }
+
+    let mut requests = Vec::new();
+
+    // ("lottery_request", ...)
+    for (key, value) in read_range_in_batches(db, LotteryRequestPrefix::new().get_range()).await? {
+        let student = Student::from(TryInto::<LotteryRequestKey>::try_into(key)?);
+
+        let preferences: Vec<Class> = TryInto::<LotteryRequestValue>::try_into(value)?.into();
+
+        requests.push((student, preferences));
+    }
+
+    let mut entries = Vec::new();
+
+    for batch in requests.chunks(LOTTERY_BATCH_SIZE) {
+        let batch_entries = db
+            .run(|tr| async move {
+                let mut batch_entries = Vec::new();
+
+                for (student, preferences) in batch {
+                    let mut classes = Vec::new();
+
+                    for kv in get_attends_student_keyvalue(&tr, student.clone()).await? {
+                        let attends_key = TryInto::<AttendsKey>::try_into(kv.0)?;
+
+                        classes.push(Class::from(attends_key));
+                    }
+
+                    batch_entries.push(LotteryEntry {
+                        student: student.clone(),
+                        preferences: preferences.clone(),
+                        classes,
+                        won: Vec::new(),
+                    });
+                }
+
+                Ok(batch_entries)
+            })
+            .await?;
+
+        entries.extend(batch_entries);
+    }
+
+    // `entries` starts out in key order, so shuffling it with a seeded
+    // `StdRng` is reproducible.
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut assigned = true;
+
+    while assigned {
+        assigned = false;
+
+        entries.shuffle(&mut rng);
+
+        for entry in entries.iter_mut() {
+            if entry.classes.len() + entry.won.len() >= MAX_CLASSES {
+                continue;
+            }
+
+            let pick = entry.preferences.iter().position(|class_name| {
+                seats_left.get(class_name).copied().unwrap_or(0) > 0
+                    && entry
+                        .classes
+                        .iter()
+                        .chain(entry.won.iter())
+                        .all(|c| c != class_name && class_time(c) != class_time(class_name))
+            });
+
+            if let Some(i) = pick {
+                let class_name = entry.preferences.remove(i);
+
+                // Safety: It is safe to `unwrap()` here because we
+                // only pick classes that have seats left.
+                *seats_left.get_mut(&class_name).unwrap() -= 1;
+
+                entry.won.push(class_name);
+
+                assigned = true;
+            }
+        }
+    }
+
+    let mut results = Vec::new();
+
+    for batch in entries.chunks(LOTTERY_BATCH_SIZE) {
+        let batch_results = db
+            .run(|tr| async move {
+                let mut batch_results = Vec::new();
+
+                for entry in batch {
+                    // Preferences the student did not win, most wanted
+                    // first.
+                    let mut fallbacks: Vec<&Class> = entry.preferences.iter().collect();
+
+                    for (i, class_name) in entry.won.iter().enumerate() {
+                        let mut candidate = Some(class_name);
+
+                        // When the seat is gone, the student gets their
+                        // next preference that can still be taken
+                        // instead, leaving out those at the same time as
+                        // a class that is still to be written.
+                        while let Some(class_name) = candidate {
+                            if take_lottery_seat(&tr, entry.student.clone(), class_name).await? {
+                                batch_results.push((entry.student.clone(), class_name.clone()));
+
+                                break;
+                            }
+
+                            candidate = fallbacks
+                                .iter()
+                                .position(|fallback| {
+                                    entry.won[i + 1..]
+                                        .iter()
+                                        .all(|c| class_time(c) != class_time(fallback))
+                                })
+                                .map(|j| fallbacks.remove(j));
+                        }
+                    }
+
+                    // ("lottery_request", student)
+                    tr.clear(LotteryRequestKey::new(entry.student.clone()));
+                }
+
+                Ok(batch_results)
+            })
+            .await?;
+
+        results.extend(batch_results);
+    }
+
+    Ok(results)
+}
+
+async fn get_bundle_classes<T: KvTransaction>(tr: &T, bundle: Bundle) -> FdbResult<Vec<Class>> {
+    // ("bundle", bundle, ...)
+    let mut range_stream = tr.get_range(BundleClassPrefix::new(bundle).get_range());
+
+    let mut class_names = Vec::new();
+
+    while let Some(x) = range_stream.next().await {
+        let key = x?.0;
+
+        let bundle_key = TryInto::<BundleKey>::try_into(key)?;
+
+        class_names.push(bundle_key.into());
+    }
+
+    Ok(class_names)
+}
+
+// The bundle `class_name` is part of, if any.
+pub async fn get_class_bundle<T: KvTransaction>(
+    tr: &T,
+    class_name: Class,
+) -> FdbResult<Option<Bundle>> {
+    // ("bundled", class_name)
+    let bundled_key = BundledKey::new(class_name);
+
+    match tr.get(bundled_key).await? {
+        Some(value) => Ok(Some(TryInto::<BundledValue>::try_into(value)?.into())),
+        None => Ok(None),
+    }
+}
+
+pub const INCOMPLETE_BUNDLE: i32 = 994;
+
+// All classes of a bundle are signed up for in the same transaction,
+// so the student either gets every class of the bundle or none of
+// them.
+pub async fn signup_bundle<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+    class_names: Vec<Class>,
+    now: Timestamp,
+) -> FdbResult<()> {
+    check_registration_open(tr, student.clone(), now).await?;
+
+    // No classes are no bundle.
+    let first_class_name = match class_names.first() {
+        Some(class_name) => class_name.clone(),
+        None => return Err(FdbError::new(INCOMPLETE_BUNDLE)),
+    };
+
+    let bundle = match get_class_bundle(tr, first_class_name).await? {
+        Some(bundle) => bundle,
+        None => return Err(FdbError::new(INCOMPLETE_BUNDLE)),
+    };
+
+    let bundle_classes = get_bundle_classes(tr, bundle).await?;
+
+    if bundle_classes.len() != class_names.len()
+        || !bundle_classes.iter().all(|c| class_names.contains(c))
+    {
+        return Err(FdbError::new(INCOMPLETE_BUNDLE));
+    }
+
+    // Every class in the bundle counts towards `MAX_CLASSES`.
+    let attends_student_kvs = get_attends_student_keyvalue(tr, student.clone()).await?;
+
+    if attends_student_kvs.len() + class_names.len() > MAX_CLASSES {
+        return Err(FdbError::new(TOO_MANY_CLASSES));
+    }
+
+    // The classes of a bundle are at different times, so only the
+    // classes the student already has can be in the way.
+    for class_name in &class_names {
+        check_time_conflict(tr, student.clone(), class_name, class_name).await?;
+    }
+
+    for class_name in class_names {
+        take_seat(tr, student.clone(), class_name).await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_class_roster<T: KvTransaction>(
+    tr: &T,
+    class_name: Class,
+) -> FdbResult<Vec<Student>> {
+    // ("attends", ...)
+    let mut range_stream = tr.get_range(AttendsPrefix::new().get_range());
+
+    let mut students = Vec::new();
+
+    while let Some(x) = range_stream.next().await {
+        let attends_key = TryInto::<AttendsKey>::try_into(x?.0)?;
+
+        if attends_key.class_name == class_name {
+            students.push(attends_key.student);
+        }
+    }
+
+    Ok(students)
+}
+
+pub async fn get_seats_left<T: KvTransaction>(tr: &T, class_name: Class) -> FdbResult<u8> {
+    // ("class", class_name)
+    let class_key = ClassKey::new(class_name);
+
+    let class_value = tr
+        .get(class_key)
+        .await?
+        .ok_or_else(|| FdbError::new(NO_SUCH_CLASS))?;
+
+    Ok(TryInto::<ClassValue>::try_into(class_value)?.get_val())
+}
+
+pub async fn get_student_schedule<T: KvTransaction>(
+    tr: &T,
+    student: Student,
+) -> FdbResult<Vec<Class>> {
+    let mut class_names = Vec::new();
+
+    for kv in get_attends_student_keyvalue(tr, student).await? {
+        let attends_key = TryInto::<AttendsKey>::try_into(kv.0)?;
+
+        class_names.push(attends_key.into());
+    }
+
+    Ok(class_names)
+}
+
+const ERROR_MESSAGES: [(i32, &str); 13] = [
+    (NO_REMAINING_SEATS, "no remaining seats"),
+    (ALREADY_SIGNED_UP, "already signed up"),
+    (TOO_MANY_CLASSES, "too many classes"),
+    (NOT_SIGNED_UP, "not signed up"),
+    (TIME_CONFLICT, "time conflict"),
+    (INCOMPLETE_BUNDLE, "incomplete bundle"),
+    (REGISTRATION_CLOSED, "registration closed"),
+    (PRIORITY_REGISTRATION_ONLY, "priority registration only"),
+    (LOTTERY_CLOSED, "lottery closed"),
+    (NO_SUCH_CLASS, "no such class"),
+    (seats::SEAT_CHANGES_TRIMMED, "seat changes trimmed"),
+    (KEY_CONVERTION_ERROR, "key conversion error"),
+    (VALUE_CONVERTION_ERROR, "value conversion error"),
+];
+
+// Describes the errors returned by our transactions, falling back to
+// the FDB error message for any other error.
+pub fn error_message(err: &FdbError) -> String {
+    ERROR_MESSAGES
+        .iter()
+        .find(|(code, _)| *code == err.code())
+        .map(|(_, message)| message.to_string())
+        .unwrap_or_else(|| err.to_string())
+}
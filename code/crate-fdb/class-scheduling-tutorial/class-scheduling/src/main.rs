@@ -1,770 +1,763 @@
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use class_scheduling::fsck::{fsck, repair, Finding};
+use class_scheduling::sim::{
+    read_trace, replay, run_deterministic, run_sim, Faults, MoodWeights, Profile, SimConfig,
+    SimFailure, SimStats,
+};
+use class_scheduling::{
+    available_classes, build_schedule, dropout, dropout_bundle, error_message, get_class_roster,
+    get_student_schedule, init, run_lottery, set_priority_group, set_registration_window, signup,
+    signup_bundle, submit_preferences, switch_classes, trade_seats, Class, NewClass, OldClass,
+    Phase, PriorityGroup, Student, Timestamp, ALREADY_SIGNED_UP, INCOMPLETE_BUNDLE,
+    KEY_CONVERTION_ERROR, LOTTERY_CLOSED, NOT_SIGNED_UP, NO_REMAINING_SEATS, NO_SUCH_CLASS,
+    PRIORITY_REGISTRATION_ONLY, REGISTRATION_CLOSED, TIME_CONFLICT, TOO_MANY_CLASSES,
+    VALUE_CONVERTION_ERROR,
+};
 
 use fdb::database::{DatabaseOption, FdbDatabase};
 use fdb::error::{FdbError, FdbResult};
-use fdb::range::{Range, RangeOptions};
-use fdb::transaction::{FdbTransaction, ReadTransaction, Transaction};
-use fdb::tuple::Tuple;
-use fdb::{Key, KeyValue, Value};
 
-use rand::rngs::StdRng;
-use rand::seq::SliceRandom;
-use rand::SeedableRng;
-
-use tracing::{debug, debug_span, Instrument};
+use clap::{Args, Parser, Subcommand};
 
 use tokio::runtime::Runtime;
-use tokio::sync::mpsc::{self, Sender};
-
-use tokio_stream::StreamExt;
 
-use std::convert::{TryFrom, TryInto};
 use std::env;
 use std::error::Error;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+// Exit codes for the errors returned by our transactions. Any other
+// error exits with `1`, and `clap` exits with `2` on a usage error.
+const ERROR_EXIT_CODES: [(i32, u8); 12] = [
+    (NO_REMAINING_SEATS, 3),
+    (ALREADY_SIGNED_UP, 4),
+    (TOO_MANY_CLASSES, 5),
+    (NOT_SIGNED_UP, 6),
+    (TIME_CONFLICT, 7),
+    (INCOMPLETE_BUNDLE, 8),
+    (REGISTRATION_CLOSED, 9),
+    (PRIORITY_REGISTRATION_ONLY, 10),
+    (LOTTERY_CLOSED, 11),
+    (KEY_CONVERTION_ERROR, 12),
+    (VALUE_CONVERTION_ERROR, 13),
+    (NO_SUCH_CLASS, 14),
+];
 
-#[derive(Clone, Debug, PartialEq)]
-struct Class(String);
-
-#[derive(Clone, Debug, PartialEq)]
-struct Student(String);
-
-// ("class", class_name)
-#[derive(Clone, Debug)]
-struct ClassKey {
-    class_name: Class,
-}
-
-impl ClassKey {
-    fn new(class_name: Class) -> ClassKey {
-        ClassKey { class_name }
-    }
-}
+// Exit code when the simulation leaves the database inconsistent.
+const VIOLATIONS_EXIT_CODE: u8 = 15;
 
-impl From<ClassKey> for Key {
-    fn from(c: ClassKey) -> Key {
-        let key_tup: (&'static str, Class) = ("class", c.class_name);
+// Exit code when students of the simulation failed.
+const STUDENT_FAILURES_EXIT_CODE: u8 = 16;
 
-        let key_bytes = {
-            let mut tup = Tuple::new();
+// Exit code when a replayed trace had other outcomes than recorded.
+const MISMATCHES_EXIT_CODE: u8 = 17;
 
-            tup.add_string((key_tup.0).to_string());
+// Exit code when `fsck` finds something wrong that is not repaired.
+const FINDINGS_EXIT_CODE: u8 = 18;
 
-            let Class(class_inner) = key_tup.1;
-            tup.add_string(class_inner);
+/// Class scheduling with FoundationDB.
+///
+/// Class names are of the form "time type level", for example
+/// "10:00 chem 201", and need to be quoted on the command line.
+#[derive(Parser, Debug)]
+#[command(name = "class-scheduling")]
+struct Cli {
+    /// Print output as JSON
+    #[arg(long, global = true)]
+    json: bool,
 
-            tup
-        }
-        .pack();
+    /// Print tracing output, including the simulation's
+    #[arg(short, long, global = true)]
+    verbose: bool,
 
-        key_bytes.into()
-    }
+    #[command(subcommand)]
+    command: Command,
 }
 
-impl From<ClassKey> for Class {
-    fn from(c: ClassKey) -> Class {
-        c.class_name
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Clear all scheduling data and create the classes
+    Init,
+    /// List classes that have seats left
+    List,
+    /// Sign a student up for a class
+    Signup { student: String, class: String },
+    /// Sign a student up for all classes of a bundle, such as a
+    /// lecture and its lab
+    SignupBundle {
+        student: String,
+        #[arg(required = true)]
+        classes: Vec<String>,
+    },
+    /// Drop a student from a class
+    Drop { student: String, class: String },
+    /// Drop a student from a class and the other classes of its bundle
+    DropBundle { student: String, class: String },
+    /// Switch a student from one class to another
+    Switch {
+        student: String,
+        old_class: String,
+        new_class: String,
+    },
+    /// Sign a student up for as many of the classes as fit, most
+    /// wanted first, and print the classes signed up for
+    BuildSchedule {
+        student: String,
+        #[arg(required = true)]
+        classes: Vec<String>,
+    },
+    /// Trade the seats of two students, each taking the class the
+    /// other gives up
+    Trade {
+        student_a: String,
+        class_a: String,
+        student_b: String,
+        class_b: String,
+    },
+    /// List the students attending a class
+    Roster { class: String },
+    /// List the classes a student attends
+    Schedule { student: String },
+    /// Open a registration phase from `start` until `end`, in seconds
+    /// since the Unix epoch. Once any phase has a window, registration
+    /// is closed outside of the windows
+    SetWindow {
+        /// One of "priority:<group>", "open", "add-drop" or "lottery"
+        #[arg(value_parser = parse_phase)]
+        phase: Phase,
+        start: i64,
+        end: i64,
+    },
+    /// Put a student in a priority group
+    SetPriorityGroup { student: String, group: String },
+    /// Submit the classes a student wants for the lottery, most wanted
+    /// first
+    SubmitPreferences {
+        student: String,
+        #[arg(required = true)]
+        classes: Vec<String>,
+    },
+    /// Assign seats to the submitted preferences, and print the
+    /// students with the classes they got
+    Lottery {
+        /// Seed to draw the order of the students, a random seed is
+        /// used by default
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Initialize the database and replay a trace recorded with
+    /// `simulate --record`
+    Replay { trace: PathBuf },
+    /// Run the simulation of indecisive students
+    Simulate(SimulateArgs),
+    /// Check the scheduling data for inconsistencies
+    Fsck {
+        /// Repair what can be repaired
+        #[arg(long)]
+        repair: bool,
+    },
 }
 
-// Exists for documentation purposes.
-#[allow(dead_code)]
-const VALUE_CONVERTION_ERROR: i32 = 998;
-
-const KEY_CONVERTION_ERROR: i32 = 999;
-
-impl TryFrom<Key> for ClassKey {
-    type Error = FdbError;
+#[derive(Args, Debug)]
+struct SimulateArgs {
+    /// Number of students
+    #[arg(long, default_value_t = 10)]
+    students: usize,
+
+    /// Operations per student
+    #[arg(long, default_value_t = 10)]
+    ops: usize,
+
+    /// Seed to replay an earlier simulation, a random seed is used
+    /// by default
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Workload profile: uniform, zipfian, hot-spot, browse-heavy or
+    /// registration-day
+    #[arg(long, default_value = "uniform")]
+    profile: Profile,
+
+    /// Override the weights of the profile, as
+    /// "add,dropout,switch,browse"
+    #[arg(long)]
+    mood_weights: Option<MoodWeights>,
+
+    /// Inject faults into the transactions of the students, with
+    /// the given probabilities as "cancel,timeout,commit_unknown"
+    #[arg(long, default_value = "0,0,0")]
+    faults: Faults,
+
+    /// Most students running at the same time, all of them by
+    /// default
+    #[arg(long)]
+    concurrency: Option<NonZeroUsize>,
+
+    /// Record every operation of the students to this JSON Lines
+    /// file
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Write the statistics of the simulation as JSON to this file
+    #[arg(long)]
+    stats_json: Option<PathBuf>,
+
+    /// Write the statistics of the simulation as CSV to this file
+    #[arg(long)]
+    stats_csv: Option<PathBuf>,
+
+    /// Run on a single thread against an in-memory database, with the
+    /// clock paused, so that a seed always runs the very same steps
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Fraction of the commits of a deterministic simulation that fail
+    /// with an injected conflict
+    #[arg(long, default_value_t = 0.0, value_parser = parse_rate, requires = "deterministic")]
+    conflicts: f64,
+}
 
-    fn try_from(key: Key) -> FdbResult<ClassKey> {
-        Tuple::from_bytes(key)
-            .and_then(|tup| {
-                // ("class", class_name)
-                if tup.get_string_ref(0)?.as_str() != "class" {
-                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
-                }
+impl SimulateArgs {
+    fn into_config(self) -> SimConfig {
+        let mut profile = self.profile;
 
-                let class_name = Class(tup.get_string_ref(1)?.to_string());
+        if let Some(mood_weights) = self.mood_weights {
+            profile.mood_weights = mood_weights;
+        }
 
-                Ok(ClassKey::new(class_name))
-            })
-            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
+        SimConfig {
+            students: self.students,
+            ops_per_student: self.ops,
+            seed: self.seed.unwrap_or_else(rand::random),
+            profile,
+            faults: self.faults,
+            concurrency: self.concurrency,
+            record: self.record,
+        }
     }
 }
 
-struct ClassValue {
-    val: u8,
-}
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate = s
+        .parse::<f64>()
+        .map_err(|err| format!("invalid rate: {}", err))?;
 
-impl ClassValue {
-    fn new(val: u8) -> ClassValue {
-        ClassValue { val }
+    if !(0.0..=1.0).contains(&rate) {
+        return Err("rate must be between 0 and 1".to_string());
     }
 
-    fn get_val(&self) -> u8 {
-        self.val
-    }
+    Ok(rate)
 }
 
-impl From<ClassValue> for Value {
-    fn from(c: ClassValue) -> Value {
-        let val_bytes = Bytes::from({
-            let mut b = BytesMut::new();
-            b.put_u8(c.val);
-            b
-        });
-
-        val_bytes.into()
+fn parse_phase(s: &str) -> Result<Phase, String> {
+    match s {
+        "open" => Ok(Phase::Open),
+        "add-drop" => Ok(Phase::AddDrop),
+        "lottery" => Ok(Phase::Lottery),
+        _ => match s.strip_prefix("priority:") {
+            Some(priority_group_inner) if !priority_group_inner.is_empty() => Ok(Phase::Priority(
+                PriorityGroup(priority_group_inner.to_string()),
+            )),
+            _ => Err(format!("invalid phase: {}", s)),
+        },
     }
 }
 
-impl From<Value> for ClassValue {
-    fn from(v: Value) -> ClassValue {
-        let val = Bytes::from(v).get_u8();
-
-        ClassValue::new(val)
-    }
+enum Output {
+    Done,
+    Classes(Vec<Class>),
+    Students(Vec<Student>),
+    Assignments(Vec<(Student, Class)>),
+    Stats {
+        stats: Box<SimStats>,
+        stats_json: Option<PathBuf>,
+        stats_csv: Option<PathBuf>,
+    },
+    SimFailure(SimFailure),
+    // Every finding, and whether it was repaired.
+    Findings(Vec<(Finding, bool)>),
 }
 
-// ("attends", student, class_name)
-#[derive(Clone, Debug)]
-struct AttendsKey {
-    student: Student,
-    class_name: Class,
-}
+async fn run_command(db: FdbDatabase, command: Command) -> FdbResult<Output> {
+    match command {
+        Command::Init => {
+            init(&db).await?;
 
-impl AttendsKey {
-    fn new(student: Student, class_name: Class) -> AttendsKey {
-        AttendsKey {
-            student,
-            class_name,
+            Ok(Output::Done)
         }
-    }
-}
-
-impl From<AttendsKey> for Key {
-    fn from(a: AttendsKey) -> Key {
-        let key_tup: (&'static str, Student, Class) = ("attends", a.student, a.class_name);
-
-        let key_bytes = {
-            let mut tup = Tuple::new();
-
-            tup.add_string((key_tup.0).to_string());
-
-            let Student(student_inner) = key_tup.1;
-            tup.add_string(student_inner);
-
-            let Class(class_inner) = key_tup.2;
-            tup.add_string(class_inner);
+        Command::List => {
+            let class_names = db
+                .run(|tr| async move { available_classes(&tr).await })
+                .await?;
 
-            tup
+            Ok(Output::Classes(class_names))
         }
-        .pack();
-
-        key_bytes.into()
-    }
-}
-
-impl TryFrom<Key> for AttendsKey {
-    type Error = FdbError;
-
-    fn try_from(key: Key) -> FdbResult<AttendsKey> {
-        Tuple::from_bytes(key)
-            .and_then(|tup| {
-                // ("attends", student, class_name)
-                if tup.get_string_ref(0)?.as_str() != "attends" {
-                    return Err(FdbError::new(KEY_CONVERTION_ERROR));
-                }
-
-                let student = Student(tup.get_string_ref(1)?.to_string());
-
-                let class_name = Class(tup.get_string_ref(2)?.to_string());
-
-                Ok(AttendsKey::new(student, class_name))
+        Command::Signup { student, class } => {
+            let (student_ref, class_ref) = (&student, &class);
+
+            db.run(|tr| async move {
+                signup(
+                    &tr,
+                    Student(student_ref.clone()),
+                    Class(class_ref.clone()),
+                    Timestamp::now(),
+                )
+                .await
             })
-            .map_err(|_| FdbError::new(KEY_CONVERTION_ERROR))
-    }
-}
-
-struct AttendsValue;
-
-impl AttendsValue {
-    fn new() -> AttendsValue {
-        AttendsValue
-    }
-}
-
-impl From<AttendsValue> for Value {
-    fn from(_: AttendsValue) -> Value {
-        let val_bytes = Bytes::new();
-
-        val_bytes.into()
-    }
-}
-
-// ("class")
-struct ClassPrefix;
-
-impl ClassPrefix {
-    fn new() -> ClassPrefix {
-        ClassPrefix
-    }
-
-    fn get_range(&self) -> Range {
-        // ("class")
-        let class_tup: (&'static str,) = ("class",);
-
-        let class_range = {
-            let mut tup = Tuple::new();
-
-            tup.add_string((class_tup.0).to_string());
+            .await?;
 
-            tup
+            Ok(Output::Done)
         }
-        .range(Bytes::new());
-
-        class_range
-    }
-}
-
-// ("attends")
-struct AttendsPrefix;
-
-impl AttendsPrefix {
-    fn new() -> AttendsPrefix {
-        AttendsPrefix
-    }
-
-    fn get_range(&self) -> Range {
-        // ("attends")
-        let attends_tup: (&'static str,) = ("attends",);
-
-        let attends_range = {
-            let mut tup = Tuple::new();
+        Command::SignupBundle { student, classes } => {
+            let (student_ref, classes_ref) = (&student, &classes);
+
+            db.run(|tr| async move {
+                signup_bundle(
+                    &tr,
+                    Student(student_ref.clone()),
+                    classes_ref.iter().cloned().map(Class).collect(),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await?;
 
-            tup.add_string((attends_tup.0).to_string());
+            Ok(Output::Done)
+        }
+        Command::Drop { student, class } => {
+            let (student_ref, class_ref) = (&student, &class);
+
+            db.run(|tr| async move {
+                dropout(
+                    &tr,
+                    Student(student_ref.clone()),
+                    Class(class_ref.clone()),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await?;
 
-            tup
+            Ok(Output::Done)
         }
-        .range(Bytes::new());
+        Command::DropBundle { student, class } => {
+            let (student_ref, class_ref) = (&student, &class);
+
+            db.run(|tr| async move {
+                dropout_bundle(
+                    &tr,
+                    Student(student_ref.clone()),
+                    Class(class_ref.clone()),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await?;
 
-        attends_range
-    }
-}
+            Ok(Output::Done)
+        }
+        Command::Switch {
+            student,
+            old_class,
+            new_class,
+        } => {
+            let (student_ref, old_class_ref, new_class_ref) = (&student, &old_class, &new_class);
+
+            db.run(|tr| async move {
+                switch_classes(
+                    &tr,
+                    Student(student_ref.clone()),
+                    OldClass(Class(old_class_ref.clone())),
+                    NewClass(Class(new_class_ref.clone())),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await?;
 
-// ("attends", student)
-struct AttendsStudentPrefix {
-    student: Student,
-}
+            Ok(Output::Done)
+        }
+        Command::BuildSchedule { student, classes } => {
+            let (student_ref, classes_ref) = (&student, &classes);
+
+            let class_names = db
+                .run(|tr| async move {
+                    build_schedule(
+                        &tr,
+                        Student(student_ref.clone()),
+                        classes_ref.iter().cloned().map(Class).collect(),
+                        Timestamp::now(),
+                    )
+                    .await
+                })
+                .await?;
 
-impl AttendsStudentPrefix {
-    fn new(student: Student) -> AttendsStudentPrefix {
-        AttendsStudentPrefix { student }
-    }
+            Ok(Output::Classes(class_names))
+        }
+        Command::Trade {
+            student_a,
+            class_a,
+            student_b,
+            class_b,
+        } => {
+            let (student_a_ref, class_a_ref, student_b_ref, class_b_ref) =
+                (&student_a, &class_a, &student_b, &class_b);
+
+            db.run(|tr| async move {
+                trade_seats(
+                    &tr,
+                    Student(student_a_ref.clone()),
+                    Class(class_a_ref.clone()),
+                    Student(student_b_ref.clone()),
+                    Class(class_b_ref.clone()),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await?;
 
-    fn get_range(&self) -> Range {
-        // ("attends", student)
-        let attends_student_tup: (&'static str, Student) = ("attends", self.student.clone());
+            Ok(Output::Done)
+        }
+        Command::Roster { class } => {
+            let class_ref = &class;
 
-        let attends_student_range = {
-            let mut tup = Tuple::new();
+            let students = db
+                .run(|tr| async move { get_class_roster(&tr, Class(class_ref.clone())).await })
+                .await?;
 
-            tup.add_string((attends_student_tup.0).to_string());
+            Ok(Output::Students(students))
+        }
+        Command::Schedule { student } => {
+            let student_ref = &student;
 
-            let Student(student_inner) = attends_student_tup.1;
-            tup.add_string(student_inner);
+            let class_names =
+                db.run(|tr| async move {
+                    get_student_schedule(&tr, Student(student_ref.clone())).await
+                })
+                .await?;
 
-            tup
+            Ok(Output::Classes(class_names))
         }
-        .range(Bytes::new());
-
-        attends_student_range
-    }
-}
+        Command::SetWindow { phase, start, end } => {
+            let phase_ref = &phase;
 
-fn add_class(tr: &FdbTransaction, class_name: Class) {
-    // ("class", class_name)
-    let class_key = ClassKey::new(class_name);
+            db.run(|tr| async move {
+                set_registration_window(&tr, phase_ref.clone(), Timestamp(start), Timestamp(end));
 
-    let class_value = ClassValue::new(100);
+                Ok(())
+            })
+            .await?;
 
-    tr.set(class_key, class_value);
-}
+            Ok(Output::Done)
+        }
+        Command::SetPriorityGroup { student, group } => {
+            let (student_ref, group_ref) = (&student, &group);
 
-const LEVELS: [&str; 9] = [
-    "intro",
-    "for dummies",
-    "remedial",
-    "101",
-    "201",
-    "301",
-    "mastery",
-    "lab",
-    "seminar",
-];
+            db.run(|tr| async move {
+                set_priority_group(
+                    &tr,
+                    Student(student_ref.clone()),
+                    PriorityGroup(group_ref.clone()),
+                );
 
-const TYPES: [&str; 10] = [
-    "chem", "bio", "cs", "geometry", "calc", "alg", "film", "music", "art", "dance",
-];
+                Ok(())
+            })
+            .await?;
 
-const TIMES: [&str; 18] = [
-    "2:00", "3:00", "4:00", "5:00", "6:00", "7:00", "8:00", "9:00", "10:00", "11:00", "12:00",
-    "13:00", "14:00", "15:00", "16:00", "17:00", "18:00", "19:00",
-];
+            Ok(Output::Done)
+        }
+        Command::SubmitPreferences { student, classes } => {
+            let (student_ref, classes_ref) = (&student, &classes);
+
+            db.run(|tr| async move {
+                submit_preferences(
+                    &tr,
+                    Student(student_ref.clone()),
+                    classes_ref.iter().cloned().map(Class).collect(),
+                    Timestamp::now(),
+                )
+                .await
+            })
+            .await?;
 
-fn init_class_names() -> Vec<Class> {
-    let mut class_names = Vec::new();
+            Ok(Output::Done)
+        }
+        Command::Lottery { seed } => {
+            let assignments = run_lottery(&db, seed.unwrap_or_else(rand::random)).await?;
 
-    for level in LEVELS {
-        // we can't use type here as that is a keyword in Rust.
-        for typ in TYPES {
-            for time in TIMES {
-                class_names.push(Class(format!("{} {} {}", time, typ, level).to_string()));
+            Ok(Output::Assignments(assignments))
+        }
+        Command::Replay { trace } => {
+            let entries = match read_trace(&trace) {
+                Ok(entries) => entries,
+                Err(err) => return Ok(Output::SimFailure(err.into())),
+            };
+
+            match replay(db, entries).await {
+                Ok(_) => Ok(Output::Done),
+                Err(SimFailure::Fdb(err)) => Err(err),
+                Err(err) => Ok(Output::SimFailure(err)),
             }
         }
-    }
+        Command::Fsck {
+            repair: repair_findings,
+        } => {
+            let findings = fsck(&db).await?;
 
-    class_names
-}
-
-async fn init(db: &FdbDatabase) -> FdbResult<()> {
-    db.run(|tr| async move {
-        // ("attends")
-        let attends_prefix_range = AttendsPrefix::new().get_range();
-        tr.clear_range(attends_prefix_range);
-
-        // ("class")
-        let class_prefix_range = ClassPrefix::new().get_range();
-        tr.clear_range(class_prefix_range);
+            let repaired = if repair_findings {
+                repair(&db, &findings).await?
+            } else {
+                vec![false; findings.len()]
+            };
 
-        for class_name in init_class_names() {
-            add_class(&tr, class_name);
+            Ok(Output::Findings(
+                findings.into_iter().zip(repaired).collect(),
+            ))
         }
-
-        Ok(())
-    })
-    .await
-}
-
-// async fn available_classes(tr: &FdbTransaction) -> FdbResult<Vec<Class>> {
-//     // ("class", ...)
-//     let mut class_range_stream = ClassPrefix::new()
-//         .get_range()
-//         .into_stream(tr, RangeOptions::default());
-
-//     let mut class_names = Vec::new();
-
-//     while let Some(x) = class_range_stream.next().await {
-//         let key = x?.into_key();
-
-//         let class_key = TryInto::<ClassKey>::try_into(key)?;
-
-//         class_names.push(class_key.into());
-//     }
-
-//     Ok(class_names)
-// }
-
-async fn available_classes(tr: &FdbTransaction) -> FdbResult<Vec<Class>> {
-    // ("class", ...)
-    let mut class_range_stream = ClassPrefix::new()
-        .get_range()
-        .into_stream(tr, RangeOptions::default());
-
-    let mut class_names = Vec::new();
-
-    while let Some(x) = class_range_stream.next().await {
-        let (key, value) = x?.into_parts();
-
-        let class_key = TryInto::<ClassKey>::try_into(key)?;
-
-        let seats_available = ClassValue::from(value).get_val();
-
-        if seats_available > 0 {
-            class_names.push(class_key.into());
+        Command::Simulate(args) => {
+            let (stats_json, stats_csv) = (args.stats_json.clone(), args.stats_csv.clone());
+
+            let config = args.into_config();
+
+            // Ctrl-C stops the students, and the simulation is still
+            // checked.
+            let shutdown = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+
+            match run_sim(db, config, shutdown).await {
+                Ok(stats) => Ok(Output::Stats {
+                    stats: Box::new(stats),
+                    stats_json,
+                    stats_csv,
+                }),
+                Err(SimFailure::Fdb(err)) => Err(err),
+                Err(err) => Ok(Output::SimFailure(err)),
+            }
         }
     }
-
-    Ok(class_names)
-}
-
-// fn signup(tr: &FdbTransaction, student: Student, class_name: Class) {
-//     // ("attends", student, class_name)
-//     let attends_key = AttendsKey::new(student, class_name);
-
-//     // ""
-//     let attends_value = AttendsValue::new();
-
-//     tr.set(attends_key, attends_value);
-// }
-
-const NO_REMAINING_SEATS: i32 = 996;
-const ALREADY_SIGNED_UP: i32 = 997;
-
-// async fn signup(tr: &FdbTransaction, student: Student, class_name: Class) -> FdbResult<()> {
-//     // ("attends", student, class_name)
-//     let attends_key = AttendsKey::new(student, class_name.clone());
-
-//     // ""
-//     let attends_value = AttendsValue::new();
-
-//     if tr.get(attends_key.clone()).await?.is_some() {
-//         Err(FdbError::new(ALREADY_SIGNED_UP))
-//     } else {
-//         // ("class", class_name)
-//         let class_key = ClassKey::new(class_name);
-
-//         // Safety: It is safe to `unwrap()` here because in our data
-//         // model assume that key `("class", class_name)` will *always*
-//         // have seats left value.
-//         let class_value = ClassValue::from(tr.get(class_key.clone()).await?.unwrap());
-
-//         let seats_left = class_value.get_val();
-
-//         if seats_left == 0 {
-//             Err(FdbError::new(NO_REMAINING_SEATS))
-//         } else {
-//             let updated_class_value = ClassValue::new(seats_left - 1);
-
-//             tr.set(class_key, updated_class_value);
-
-//             tr.set(attends_key, attends_value);
-
-//             Ok(())
-//         }
-//     }
-// }
-
-async fn get_attends_student_keyvalue(
-    tr: &FdbTransaction,
-    student: Student,
-) -> FdbResult<Vec<KeyValue>> {
-    // ("attends", student, ...)
-    let mut range_stream = AttendsStudentPrefix::new(student)
-        .get_range()
-        .into_stream(tr, RangeOptions::default());
-
-    let mut kvs = Vec::new();
-
-    while let Some(x) = range_stream.next().await {
-        let kv = x?;
-
-        kvs.push(kv);
-    }
-
-    Ok(kvs)
 }
 
-const TOO_MANY_CLASSES: i32 = 995;
-
-async fn signup(tr: &FdbTransaction, student: Student, class_name: Class) -> FdbResult<()> {
-    // ("attends", student, class_name)
-    let attends_key = AttendsKey::new(student.clone(), class_name.clone());
-
-    // ""
-    let attends_value = AttendsValue::new();
-
-    if tr.get(attends_key.clone()).await?.is_some() {
-        Err(FdbError::new(ALREADY_SIGNED_UP))
-    } else {
-        // ("class", class_name)
-        let class_key = ClassKey::new(class_name);
-
-        // Safety: It is safe to `unwrap()` here because in our data
-        // model assume that key `("class", class_name)` will *always*
-        // have seats left value.
-        let class_value = ClassValue::from(tr.get(class_key.clone()).await?.unwrap());
-
-        let seats_left = class_value.get_val();
-
-        if seats_left == 0 {
-            Err(FdbError::new(NO_REMAINING_SEATS))
-        } else {
-            let attends_student_kvs = get_attends_student_keyvalue(tr, student).await?;
-
-            if attends_student_kvs.len() == 5 {
-                Err(FdbError::new(TOO_MANY_CLASSES))
+// Prints the output and returns the exit code, which is only non-zero
+// when statistics could not be written, the simulation failed or
+// `fsck` left something wrong.
+fn print_output(output: Output, json: bool) -> u8 {
+    match output {
+        Output::Done => {
+            if json {
+                println!("{}", serde_json::json!({ "status": "ok" }));
             } else {
-                let updated_class_value = ClassValue::new(seats_left - 1);
-
-                tr.set(class_key, updated_class_value);
-
-                tr.set(attends_key, attends_value);
-
-                Ok(())
+                println!("ok");
             }
         }
-    }
-}
-
-// // Unlike other bindings, we cannot name this function as `drop`,
-// // because `drop` is already used in Rust.
-// fn dropout(tr: &FdbTransaction, student: Student, class_name: Class) {
-//     // ("attends", student, class_name)
-//     let attends_key = AttendsKey::new(student, class_name);
-
-//     tr.clear(attends_key);
-// }
-
-// Unlike other bindings, we cannot name this function as `drop`,
-// because `drop` is already used in Rust.
-async fn dropout(tr: &FdbTransaction, student: Student, class_name: Class) -> FdbResult<()> {
-    // ("attends", student, class_name)
-    let attends_key = AttendsKey::new(student, class_name.clone());
-
-    if tr.get(attends_key.clone()).await?.is_none() {
-        // not taking class
-        Ok(())
-    } else {
-        // ("class", class_name)
-        let class_key = ClassKey::new(class_name);
-
-        // Safety: It is safe to `unwrap()` here because in our data
-        // model assume that key `("class", class_name)` will *always*
-        // have seats left value.
-        let class_value = ClassValue::from(tr.get(class_key.clone()).await?.unwrap());
-
-        let seats_left = class_value.get_val();
-
-        let updated_class_value = ClassValue::new(seats_left + 1);
-
-        tr.set(class_key, updated_class_value);
-
-        tr.clear(attends_key);
-
-        Ok(())
-    }
-}
-
-#[derive(Clone, Debug)]
-struct OldClass(Class);
-
-#[derive(Clone, Debug)]
-struct NewClass(Class);
-
-async fn switch_classes(
-    tr: &FdbTransaction,
-    student: Student,
-    old_class: OldClass,
-    new_class: NewClass,
-) -> FdbResult<()> {
-    let old_attends_key = AttendsKey::new(student.clone(), {
-        let OldClass(class_name) = old_class.clone();
-        class_name
-    });
-
-    let new_attends_key = AttendsKey::new(student.clone(), {
-        let NewClass(class_name) = new_class.clone();
-        class_name
-    });
-
-    if tr.get(old_attends_key).await?.is_some() && tr.get(new_attends_key).await?.is_some() {
-        // nothing to switch
-        Ok(())
-    } else {
-        // switching classes
-        dropout(tr, student.clone(), {
-            let OldClass(class_name) = old_class;
-            class_name
-        })
-        .await?;
-
-        signup(tr, student.clone(), {
-            let NewClass(class_name) = new_class;
-            class_name
-        })
-        .await?;
-
-        Ok(())
-    }
-}
-
-#[derive(Copy, Clone, Debug)]
-enum Mood {
-    Add,
-    Dropout,
-    Switch,
-}
-
-async fn indecisive_student(task_finished: Sender<()>, db: FdbDatabase, id: usize, ops: usize) {
-    let student_id = format!("s{}", id);
-
-    debug!(%student_id, "starting");
-
-    let mut all_classes = init_class_names();
-
-    let mut my_classes: Vec<Class> = Vec::new();
-
-    let mut rng = StdRng::from_entropy();
-
-    for _ in 0..ops {
-        let class_count = my_classes.len();
-
-        let mut moods = Vec::new();
-
-        if class_count > 0 {
-            moods.push(Mood::Dropout);
-            moods.push(Mood::Switch);
+        Output::Classes(class_names) => {
+            let class_names = class_names
+                .into_iter()
+                .map(|Class(class_inner)| class_inner)
+                .collect::<Vec<String>>();
+
+            if json {
+                println!("{}", serde_json::json!({ "classes": class_names }));
+            } else {
+                for class_inner in class_names {
+                    println!("{}", class_inner);
+                }
+            }
         }
-
-        if class_count < 5 {
-            moods.push(Mood::Add);
+        Output::Students(students) => {
+            let students = students
+                .into_iter()
+                .map(|Student(student_inner)| student_inner)
+                .collect::<Vec<String>>();
+
+            if json {
+                println!("{}", serde_json::json!({ "students": students }));
+            } else {
+                for student_inner in students {
+                    println!("{}", student_inner);
+                }
+            }
         }
+        Output::Assignments(assignments) => {
+            let assignments = assignments
+                .into_iter()
+                .map(|(Student(student_inner), Class(class_inner))| (student_inner, class_inner))
+                .collect::<Vec<(String, String)>>();
+
+            if json {
+                let assignments = assignments
+                    .iter()
+                    .map(|(student_inner, class_inner)| {
+                        serde_json::json!({ "student": student_inner, "class": class_inner })
+                    })
+                    .collect::<Vec<serde_json::Value>>();
 
-        // Safety: Fail in case we are unable to select a random mood.
-        let mood = *moods.choose(&mut rng).unwrap();
-
-        if all_classes.is_empty() {
-            // all_classes empty, populating from db.
-            all_classes = db
-                .run(|tr| async move { available_classes(&tr).await })
-                .await
-                .unwrap_or_else(|err| panic!("Error occurred during `run`: {:?}", err));
+                println!("{}", serde_json::json!({ "assignments": assignments }));
+            } else {
+                for (student_inner, class_inner) in assignments {
+                    println!("{}\t{}", student_inner, class_inner);
+                }
+            }
         }
-
-        match mood {
-            Mood::Add => {
-                // Safety: Fail in case we are unable to select a
-                // random class from `all_classes`.
-                let c = all_classes.choose(&mut rng).unwrap();
-
-                let student_id_ref = &student_id;
-
-                match db
-                    .run(|tr| async move {
-                        signup(&tr, Student(student_id_ref.clone()), c.clone()).await
+        Output::Findings(findings) => {
+            if json {
+                let findings = findings
+                    .iter()
+                    .map(|(finding, repaired)| {
+                        serde_json::json!({
+                            "finding": finding.to_string(),
+                            "repaired": repaired,
+                        })
                     })
-                    .await
-                {
-                    Ok(()) => my_classes.push(c.clone()),
-                    Err(err) => {
-                        if err.code() == NO_REMAINING_SEATS {
-                            // Populate available classes in the next iteration
-                            all_classes.clear();
-                        } else if err.code() == ALREADY_SIGNED_UP {
-                            // Ignore `Mood::Add` if we have already
-                            // signed up.
-                        } else if err.code() == TOO_MANY_CLASSES {
-                            debug!(err = "TOO_MANY_CLASSES");
-                            panic!("TOO_MANY_CLASSES");
-                        } else {
-                            debug!(?err);
-                            panic!("Error occurred during `run`: {:?}", err);
-                        }
+                    .collect::<Vec<serde_json::Value>>();
+
+                println!("{}", serde_json::json!({ "findings": findings }));
+            } else {
+                for (finding, repaired) in &findings {
+                    if *repaired {
+                        println!("{} (repaired)", finding);
+                    } else {
+                        println!("{}", finding);
                     }
                 }
             }
-            Mood::Dropout => {
-                // Safety: Fail in case we are unable to select a
-                // random class from `my_classes`.
-                let c = my_classes.choose(&mut rng).unwrap().clone();
 
-                let student_id_ref = &student_id;
-                let c_ref = &c;
+            if findings.iter().any(|(_, repaired)| !repaired) {
+                return FINDINGS_EXIT_CODE;
+            }
+        }
+        Output::Stats {
+            stats,
+            stats_json,
+            stats_csv,
+        } => {
+            if json {
+                println!("{}", stats.to_json());
+            } else {
+                print!("{}", stats.summary());
+            }
 
-                match db
-                    .run(|tr| async move {
-                        dropout(&tr, Student(student_id_ref.clone()), c_ref.clone()).await
-                    })
-                    .await
-                {
-                    Ok(()) => my_classes.retain(|x| *x != c),
-                    Err(err) => {
-                        // `dropout` should not fail.
-                        debug!(?err);
-                        panic!("Error occurred during `run`: {:?}", err);
+            let files = [
+                (stats_json, stats.to_json().to_string()),
+                (stats_csv, stats.to_csv()),
+            ];
+
+            for (path, contents) in files {
+                if let Some(path) = path {
+                    if let Err(err) = fs::write(&path, contents) {
+                        eprintln!("error: cannot write {}: {}", path.display(), err);
+                        return 1;
                     }
                 }
             }
-            Mood::Switch => {
-                // Safety: Fail in case we are unable to select a
-                // random class from `my_classes`.
-                let old_c = OldClass(my_classes.choose(&mut rng).unwrap().clone());
-
-                // Safety: Fail in case we are unable to select a
-                // random class from `all_classes`.
-                let new_c = NewClass(all_classes.choose(&mut rng).unwrap().clone());
-
-                let student_id_ref = &student_id;
-                let old_c_ref = &old_c;
-                let new_c_ref = &new_c;
-
-                match db
-                    .run(|tr| async move {
-                        switch_classes(
-                            &tr,
-                            Student(student_id_ref.clone()),
-                            old_c_ref.clone(),
-                            new_c_ref.clone(),
-                        )
-                        .await
-                    })
-                    .await
-                {
-                    Ok(()) => {
-                        // Remove `old_c` and add `new_c` to
-                        // `my_classes` upon successful swtich.
-                        let OldClass(old_class_name) = old_c;
-
-                        my_classes.retain(|x| *x != old_class_name);
-
-                        my_classes.push({
-                            let NewClass(class_name) = new_c;
-                            class_name
-                        });
-                    }
-                    Err(err) => {
-                        // Error handling for `switch_classes` is
-                        // similar to `signup`, but we should not be
-                        // seeing `TOO_MANY_CLASSES` and
-                        // `ALREADY_SIGNED_UP` errors.
-                        if err.code() == NO_REMAINING_SEATS {
-                            // Populate available classes in the next iteration
-                            all_classes.clear();
-                        } else {
-                            debug!(?err);
-                            panic!("Error occurred during `run`: {:?}", err);
-                        }
-                    }
+        }
+        Output::SimFailure(failure) => {
+            let (key, label, exit_code, lines) = match failure {
+                SimFailure::Fdb(err) => return print_error(err, json),
+                SimFailure::Io(err) => {
+                    eprintln!("error: {}", err);
+                    return 1;
+                }
+                SimFailure::Violations(violations) => (
+                    "violations",
+                    "violation",
+                    VIOLATIONS_EXIT_CODE,
+                    to_strings(&violations),
+                ),
+                SimFailure::Students(failures) => (
+                    "student_failures",
+                    "student failure",
+                    STUDENT_FAILURES_EXIT_CODE,
+                    to_strings(&failures),
+                ),
+                SimFailure::Mismatches(mismatches) => (
+                    "mismatches",
+                    "mismatch",
+                    MISMATCHES_EXIT_CODE,
+                    to_strings(&mismatches),
+                ),
+            };
+
+            if json {
+                println!("{}", serde_json::json!({ key: lines }));
+            } else {
+                for line in lines {
+                    eprintln!("{}: {}", label, line);
                 }
             }
+
+            return exit_code;
         }
     }
 
-    drop(task_finished);
-
-    debug!(%student_id, "finished");
+    0
 }
 
-async fn run_sim(db: FdbDatabase, students: usize, ops_per_student: usize) {
-    let (task_finished, mut task_finished_recv) = mpsc::channel::<()>(1);
-
-    for i in 0..students {
-        let cloned_task_finished = task_finished.clone();
-        let cloned_db = db.clone();
+fn to_strings<T: ToString>(items: &[T]) -> Vec<String> {
+    items.iter().map(|item| item.to_string()).collect()
+}
 
-        tokio::spawn(
-            async move {
-                indecisive_student(cloned_task_finished, cloned_db, i, ops_per_student).await;
-            }
-            .instrument(debug_span!("indecisive_student", %i)),
+// Prints the error on stderr, or on stdout as JSON, and returns the
+// exit code for it.
+fn print_error(err: FdbError, json: bool) -> u8 {
+    let message = error_message(&err);
+
+    let exit_code = ERROR_EXIT_CODES
+        .iter()
+        .find(|(code, _)| *code == err.code())
+        .map(|(_, exit_code)| *exit_code)
+        .unwrap_or(1);
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({ "error": { "code": err.code(), "message": message } })
         );
+    } else {
+        eprintln!("error: {} ({})", message, err.code());
     }
 
-    drop(task_finished);
-
-    let _ = task_finished_recv.recv().await;
-
-    debug!(
-        total_transactions = students * ops_per_student,
-        "transactions run"
-    );
+    exit_code
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    let cli = Cli::parse();
+
+    // Tracing output goes to stderr, so that it does not get mixed up
+    // with JSON output.
     tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::TRACE)
+        .with_max_level(if cli.verbose {
+            tracing::Level::TRACE
+        } else {
+            tracing::Level::INFO
+        })
+        .with_writer(io::stderr)
         .init();
 
+    // A deterministic simulation runs on its own runtime, and needs no
+    // cluster.
+    let command = match cli.command {
+        Command::Simulate(args) if args.deterministic => {
+            let (stats_json, stats_csv) = (args.stats_json.clone(), args.stats_csv.clone());
+
+            let conflicts = args.conflicts;
+
+            let output = match run_deterministic(args.into_config(), conflicts) {
+                Ok(stats) => Output::Stats {
+                    stats: Box::new(stats),
+                    stats_json,
+                    stats_csv,
+                },
+                Err(err) => Output::SimFailure(err),
+            };
+
+            return Ok(ExitCode::from(print_output(output, cli.json)));
+        }
+        command => command,
+    };
+
     let fdb_cluster_file = env::var("FDB_CLUSTER_FILE").expect(
         "FDB_CLUSTER_FILE not defined!
 ",
@@ -785,15 +778,16 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let cloned_fdb_database = fdb_database.clone();
 
-    rt.block_on(async {
-        let fdb_database = cloned_fdb_database;
-
-        init(&fdb_database).await?;
+    let json = cli.json;
 
-        run_sim(fdb_database, 10, 10).await;
+    let exit_code = rt.block_on(async {
+        let fdb_database = cloned_fdb_database;
 
-        Result::<(), Box<dyn Error>>::Ok(())
-    })?;
+        match run_command(fdb_database, command).await {
+            Ok(output) => print_output(output, json),
+            Err(err) => print_error(err, json),
+        }
+    });
 
     drop(fdb_database);
 
@@ -801,5 +795,5 @@ fn main() -> Result<(), Box<dyn Error>> {
         fdb::stop_network();
     }
 
-    Ok(())
+    Ok(ExitCode::from(exit_code))
 }
@@ -0,0 +1,136 @@
+use bytes::Bytes;
+
+use fdb::error::FdbResult;
+use fdb::range::Range;
+use fdb::{Key, Value};
+
+use tokio::task::JoinSet;
+
+use tokio_stream::StreamExt;
+
+use std::panic;
+use std::sync::Arc;
+
+use crate::storage::{key_after, KvDatabase, KvTransaction};
+
+// Scanning a range one key-value pair after the other takes long on a
+// large range, even though the pairs live on many storage servers that
+// could all be read at the same time. So we ask the cluster where to
+// split the range into shards of about `SHARD_SIZE` bytes, and scan
+// the shards in Tokio tasks of their own.
+//
+// A shard can still be larger than what a transaction can read, so
+// each task reads at most `BATCH_SIZE` pairs in a transaction, and
+// continues after the last key it read in the next one. The shards,
+// and the batches of a shard, are read at different versions.
+
+// Bytes of keys and values in a shard.
+const SHARD_SIZE: i64 = 1_000_000;
+
+// Shards scanned at the same time.
+const MAX_PARALLEL_SHARDS: usize = 16;
+
+// Key-value pairs read in a single transaction.
+const BATCH_SIZE: usize = 1_000;
+
+// Folds the key-value pairs of [begin, end) into an `A`, in key order.
+pub(crate) async fn scan_shard<D, A, F>(db: &D, mut begin: Key, end: Key, fold: &F) -> FdbResult<A>
+where
+    D: KvDatabase,
+    A: Default,
+    F: Fn(&mut A, Key, Value),
+{
+    let mut acc = A::default();
+
+    loop {
+        let (begin_ref, end_ref) = (&begin, &end);
+
+        let batch = db
+            .run(|tr| async move {
+                let mut range_stream = tr.get_range(Range::new(begin_ref.clone(), end_ref.clone()));
+
+                let mut batch = Vec::new();
+
+                while batch.len() < BATCH_SIZE {
+                    match range_stream.next().await {
+                        Some(x) => batch.push(x?),
+                        None => break,
+                    }
+                }
+
+                Ok(batch)
+            })
+            .await?;
+
+        let done = batch.len() < BATCH_SIZE;
+
+        if let Some((key, _)) = batch.last() {
+            begin = Key::from(key_after(&Bytes::from(key.clone())));
+        }
+
+        for (key, value) in batch {
+            fold(&mut acc, key, value);
+        }
+
+        if done {
+            return Ok(acc);
+        }
+    }
+}
+
+// Folds the key-value pairs of every shard of `range` into an `A`, in
+// key order, and returns them in the order of their shards.
+pub async fn parallel_scan<D, A, F>(db: &D, range: Range, fold: F) -> FdbResult<Vec<A>>
+where
+    D: KvDatabase + Clone + Send + Sync + 'static,
+    A: Default + Send + 'static,
+    F: Fn(&mut A, Key, Value) + Send + Sync + 'static,
+{
+    let split_points = db
+        .run(|tr| {
+            let range = Range::new(range.begin().clone(), range.end().clone());
+            async move { tr.get_range_split_points(range, SHARD_SIZE).await }
+        })
+        .await?;
+
+    let fold = Arc::new(fold);
+
+    let mut shards = split_points
+        .windows(2)
+        .map(|shard| (shard[0].clone(), shard[1].clone()))
+        .enumerate();
+
+    let mut results = (1..split_points.len())
+        .map(|_| None)
+        .collect::<Vec<Option<A>>>();
+
+    // The task of a shard is only spawned once there is room for it.
+    // When a shard fails, returning drops `tasks`, which aborts the
+    // tasks of the other shards.
+    let mut tasks = JoinSet::new();
+
+    loop {
+        while tasks.len() < MAX_PARALLEL_SHARDS {
+            match shards.next() {
+                Some((i, (begin, end))) => {
+                    let (db, fold) = (db.clone(), fold.clone());
+
+                    tasks.spawn(async move { (i, scan_shard(&db, begin, end, &*fold).await) });
+                }
+                None => break,
+            }
+        }
+
+        match tasks.join_next().await {
+            Some(Ok((i, res))) => results[i] = Some(res?),
+            Some(Err(err)) => panic::resume_unwind(err.into_panic()),
+            None => break,
+        }
+    }
+
+    // Safety: the task of every shard has returned its result.
+    Ok(results
+        .into_iter()
+        .map(|acc| acc.unwrap())
+        .collect::<Vec<A>>())
+}
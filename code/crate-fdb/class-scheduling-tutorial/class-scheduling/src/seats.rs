@@ -0,0 +1,187 @@
+use fdb::database::FdbDatabase;
+use fdb::error::{FdbError, FdbResult};
+use fdb::range::Range;
+use fdb::transaction::Transaction;
+use fdb::Key;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use std::convert::TryInto;
+use std::future;
+
+use crate::storage::KvTransaction;
+use crate::{
+    Class, ClassKey, ClassValue, SeatChangeKey, SeatChangeVersionKey, SeatChangeVersionValue,
+    NO_SUCH_CLASS,
+};
+
+// Everything that changes the seats left in a class also adds the
+// change to the seat change log of the class, under a version that
+// goes up by one with every change (see `set_seats_left`). A stream of
+// seat changes keeps the version of the last change it sent for each
+// class, and reads the changes after it the next time round. So every
+// change is sent, in the order it was made in its class, even one that
+// was undone right after.
+//
+// A watch on the version of each class tells us when there are changes
+// to read. Reading the changes and setting up the watches happens in
+// the same transaction, so a change that lands between two rounds of
+// watches is never missed. It either is in the changes we read, or it
+// fires a watch.
+
+pub const SEAT_CHANGES_TRIMMED: i32 = 987;
+
+// Changes to the seats left in `class_names` after `versions`, which
+// has a version for every class, and the version of the last change to
+// each class. When `versions` is `None`, the seats left now in every
+// class are returned instead.
+//
+// Only the last `SEAT_CHANGES_KEPT` changes to a class are kept, and
+// when changes after `versions` are gone, or `versions` are not from
+// this log, this fails with `SEAT_CHANGES_TRIMMED`. The stream has to
+// be started over then.
+pub async fn read_seat_changes<T: KvTransaction>(
+    tr: &T,
+    class_names: &[Class],
+    versions: Option<&[i64]>,
+) -> FdbResult<(Vec<(Class, u8)>, Vec<i64>)> {
+    let mut seat_changes = Vec::new();
+
+    let mut current_versions = Vec::new();
+
+    for (i, class_name) in class_names.iter().enumerate() {
+        // ("class", class_name)
+        let class_value = tr
+            .get(ClassKey::new(class_name.clone()))
+            .await?
+            .ok_or_else(|| FdbError::new(NO_SUCH_CLASS))?;
+
+        let seats_left = TryInto::<ClassValue>::try_into(class_value)?.get_val();
+
+        // ("seat_change_version", class_name)
+        let current_version = match tr
+            .get(SeatChangeVersionKey::new(class_name.clone()))
+            .await?
+        {
+            Some(value) => TryInto::<SeatChangeVersionValue>::try_into(value)?.get_version(),
+            None => 0,
+        };
+
+        current_versions.push(current_version);
+
+        let version = match versions {
+            Some(versions) => versions[i],
+            None => {
+                seat_changes.push((class_name.clone(), seats_left));
+                continue;
+            }
+        };
+
+        if version > current_version {
+            return Err(FdbError::new(SEAT_CHANGES_TRIMMED));
+        }
+
+        // ("seat_change", class_name, version + 1) up to
+        // ("seat_change", class_name, current_version)
+        let mut seat_change_range_stream = tr.get_range(Range::new(
+            Key::from(SeatChangeKey::new(class_name.clone(), version + 1)),
+            Key::from(SeatChangeKey::new(class_name.clone(), current_version + 1)),
+        ));
+
+        let mut next_version = version + 1;
+
+        while let Some(x) = seat_change_range_stream.next().await {
+            let (key, value) = x?;
+
+            // A change that is gone leaves a gap.
+            if TryInto::<SeatChangeKey>::try_into(key)?.get_version() != next_version {
+                return Err(FdbError::new(SEAT_CHANGES_TRIMMED));
+            }
+
+            let seats_left = TryInto::<ClassValue>::try_into(value)?.get_val();
+
+            seat_changes.push((class_name.clone(), seats_left));
+
+            next_version += 1;
+        }
+
+        if next_version != current_version + 1 {
+            return Err(FdbError::new(SEAT_CHANGES_TRIMMED));
+        }
+    }
+
+    Ok((seat_changes, current_versions))
+}
+
+// Waits until there are changes to the seats left in `class_names`
+// after `versions`, and returns them along with the versions of the
+// last changes, like `read_seat_changes`. When `versions` is `None`,
+// the current seats left are returned right away.
+pub async fn wait_for_seat_changes(
+    db: &FdbDatabase,
+    class_names: &[Class],
+    versions: Option<&[i64]>,
+) -> FdbResult<(Vec<(Class, u8)>, Vec<i64>)> {
+    loop {
+        let (seat_changes, current_versions, mut watches) = db
+            .run(|tr| async move {
+                let (seat_changes, current_versions) =
+                    read_seat_changes(&tr, class_names, versions).await?;
+
+                let watches = FuturesUnordered::new();
+
+                for class_name in class_names {
+                    // ("seat_change_version", class_name)
+                    watches.push(tr.watch(SeatChangeVersionKey::new(class_name.clone())));
+                }
+
+                Ok((seat_changes, current_versions, watches))
+            })
+            .await?;
+
+        if versions.is_none() || !seat_changes.is_empty() {
+            return Ok((seat_changes, current_versions));
+        }
+
+        match watches.next().await {
+            Some(res) => res?,
+            // Without any classes, there is nothing that can change.
+            None => future::pending().await,
+        }
+    }
+}
+
+// The versions of the last changes sent make a resume token. A client
+// that reconnects with it gets the changes made since, without having
+// to see the seats left of every class again.
+pub fn encode_seats_token(versions: &[i64]) -> String {
+    versions
+        .iter()
+        .map(|version| version.to_string())
+        .collect::<Vec<String>>()
+        .join(".")
+}
+
+// Decodes a resume token for a stream of `class_count` classes. A
+// token that is not one from `encode_seats_token`, or is for a
+// different number of classes, is `None`.
+pub fn decode_seats_token(token: &str, class_count: usize) -> Option<Vec<i64>> {
+    // `split` gives one empty version for an empty token.
+    if class_count == 0 {
+        return token.is_empty().then(Vec::new);
+    }
+
+    let versions = token
+        .split('.')
+        .map(|version| {
+            // `parse` would take a leading `+` too.
+            if version.is_empty() || !version.bytes().all(|b| b.is_ascii_digit()) {
+                None
+            } else {
+                version.parse().ok()
+            }
+        })
+        .collect::<Option<Vec<i64>>>()?;
+
+    (versions.len() == class_count).then_some(versions)
+}
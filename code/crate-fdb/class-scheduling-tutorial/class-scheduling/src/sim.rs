@@ -0,0 +1,666 @@
+use fdb::error::{FdbError, FdbResult};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use tracing::{debug, debug_span, info, Instrument};
+
+use tokio::sync::{watch, Semaphore};
+use tokio::task::{JoinError, JoinSet};
+use tokio::time::Instant;
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use trace::TraceWriter;
+
+use crate::storage::KvDatabase;
+use crate::{
+    available_classes, dropout, init_class_names, signup, switch_classes, Class, NewClass,
+    OldClass, Student, Timestamp, ALREADY_SIGNED_UP, INCOMPLETE_BUNDLE, MAX_CLASSES,
+    NO_REMAINING_SEATS, TOO_MANY_CLASSES,
+};
+
+mod deterministic;
+mod faults;
+mod profile;
+mod stats;
+mod trace;
+mod verify;
+
+pub use deterministic::run_deterministic;
+pub use faults::{FaultTarget, Faults};
+pub use profile::{ClassChoice, MoodWeights, Profile, PROFILE_NAMES};
+pub use stats::{LatencyHistogram, MoodStats, SimStats};
+pub use trace::{read_trace, replay, Mismatch, Outcome, TraceEntry};
+pub use verify::Violation;
+
+#[derive(Debug)]
+pub enum SimFailure {
+    Fdb(FdbError),
+    // The database does not agree with what the students believe, or
+    // with itself.
+    Violations(Vec<Violation>),
+    Students(Vec<StudentFailure>),
+    // Operations of a trace that had another outcome when replayed.
+    Mismatches(Vec<Mismatch>),
+    // A trace could not be written or read.
+    Io(io::Error),
+}
+
+impl fmt::Display for SimFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimFailure::Fdb(err) => write!(f, "{}", crate::error_message(err)),
+            SimFailure::Violations(violations) => {
+                write!(f, "{} invariant violations", violations.len())
+            }
+            SimFailure::Students(failures) => write!(f, "{} students failed", failures.len()),
+            SimFailure::Mismatches(mismatches) => {
+                write!(f, "{} operations replayed differently", mismatches.len())
+            }
+            SimFailure::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for SimFailure {}
+
+impl From<FdbError> for SimFailure {
+    fn from(err: FdbError) -> SimFailure {
+        SimFailure::Fdb(err)
+    }
+}
+
+impl From<io::Error> for SimFailure {
+    fn from(err: io::Error) -> SimFailure {
+        SimFailure::Io(err)
+    }
+}
+
+#[derive(Debug)]
+pub struct StudentFailure {
+    pub student: Student,
+    pub error: StudentError,
+}
+
+#[derive(Debug)]
+pub enum StudentError {
+    Fdb(FdbError),
+    Panicked(String),
+}
+
+impl fmt::Display for StudentFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Student(student) = &self.student;
+
+        match &self.error {
+            StudentError::Fdb(err) => write!(
+                f,
+                "{} failed: {} ({})",
+                student,
+                crate::error_message(err),
+                err.code()
+            ),
+            StudentError::Panicked(message) => write!(f, "{} panicked: {}", student, message),
+        }
+    }
+}
+
+// Returned by every student once it is done.
+struct StudentReport {
+    student: Student,
+    // What the student believes it attends.
+    classes: Vec<Class>,
+    stats: SimStats,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Mood {
+    Add,
+    Dropout,
+    Switch,
+    // Looks at the available classes, without changing anything.
+    Browse,
+}
+
+impl Mood {
+    const ALL: [Mood; 4] = [Mood::Add, Mood::Dropout, Mood::Switch, Mood::Browse];
+
+    fn name(self) -> &'static str {
+        match self {
+            Mood::Add => "add",
+            Mood::Dropout => "dropout",
+            Mood::Switch => "switch",
+            Mood::Browse => "browse",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Mood> {
+        Mood::ALL.iter().copied().find(|mood| mood.name() == name)
+    }
+}
+
+// Mixed into the seed of a student to seed its faults.
+const FAULT_SEED: u64 = 0x6661_756c_7473;
+
+// Runs the operations of a student, injecting faults, and records
+// statistics and, when there is one, a trace of them.
+struct OpRunner<D> {
+    db: D,
+    student_id: String,
+    faults: Faults,
+    fault_rng: StdRng,
+    stats: SimStats,
+    trace: Option<Arc<TraceWriter>>,
+}
+
+impl<D> OpRunner<D>
+where
+    D: KvDatabase,
+    D::Transaction: FaultTarget,
+{
+    // Runs `f` with `db.run`. An operation given up because of an
+    // injected fault is run again, which is safe because all of our
+    // operations are idempotent.
+    async fn run<T, F, Fut>(&mut self, mood: Mood, classes: &[&Class], mut f: F) -> FdbResult<T>
+    where
+        T: Send,
+        F: FnMut(D::Transaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send,
+    {
+        let op_start = Instant::now();
+
+        let (faults, fault_rng) = (self.faults, &mut self.fault_rng);
+
+        let res = loop {
+            let start = Instant::now();
+            let mut attempts = 0;
+
+            let res = self
+                .db
+                .run(|tr| {
+                    attempts += 1;
+
+                    let fault = faults.choose(fault_rng);
+                    let op = f(tr.clone());
+
+                    async move {
+                        if let Some(fault) = fault {
+                            fault.before(&tr)?;
+                        }
+
+                        let t = op.await?;
+
+                        if let Some(fault) = fault {
+                            fault.after(&tr).await?;
+                        }
+
+                        Ok(t)
+                    }
+                })
+                .await;
+
+            self.stats.record(mood, start.elapsed(), attempts, &res);
+
+            match res {
+                Err(err) if faults.is_fault_error(&err) => {
+                    debug!(?err, "retrying after injected fault");
+                }
+                res => break res,
+            }
+        };
+
+        if let Some(trace) = &self.trace {
+            trace.record(&self.student_id, mood, classes, &res, op_start);
+        }
+
+        res
+    }
+}
+
+// Stops early, after the operation it is running, once `stop` is set.
+async fn indecisive_student<D>(
+    db: D,
+    student_id: String,
+    seed: u64,
+    config: Arc<SimConfig>,
+    trace: Option<Arc<TraceWriter>>,
+    stop: watch::Receiver<bool>,
+) -> FdbResult<StudentReport>
+where
+    D: KvDatabase + Clone,
+    D::Transaction: FaultTarget,
+{
+    debug!(%student_id, seed, "starting");
+
+    let mut all_classes = init_class_names();
+
+    let mut my_classes: Vec<Class> = Vec::new();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let (profile, faults) = (&config.profile, config.faults);
+
+    let mut runner = OpRunner {
+        db: db.clone(),
+        student_id: student_id.clone(),
+        faults,
+        // Faults get their own random numbers, as the number of
+        // attempts of a transaction depends on the other students.
+        // They are not drawn from `rng`, so that turning faults on does
+        // not change what the student does.
+        fault_rng: StdRng::seed_from_u64(seed ^ FAULT_SEED),
+        stats: SimStats::default(),
+        trace,
+    };
+
+    for _ in 0..config.ops_per_student {
+        if *stop.borrow() {
+            debug!(%student_id, "stopping");
+            break;
+        }
+
+        let class_count = my_classes.len();
+
+        let mut moods = Vec::new();
+
+        if class_count > 0 {
+            moods.push(Mood::Dropout);
+            moods.push(Mood::Switch);
+        }
+
+        if class_count < MAX_CLASSES {
+            moods.push(Mood::Add);
+        }
+
+        moods.push(Mood::Browse);
+
+        // Safety: Fail in case we are unable to select a random mood.
+        let mood = *moods
+            .choose_weighted(&mut rng, |mood| profile.mood_weights.weight(*mood))
+            .unwrap();
+
+        if all_classes.is_empty() {
+            // all_classes empty, populating from db.
+            all_classes = db
+                .run(|tr| async move { available_classes(&tr).await })
+                .await?;
+        }
+
+        match mood {
+            Mood::Add => {
+                // Safety: Fail in case we are unable to select a
+                // random class from `all_classes`.
+                let c = profile.class_choice.choose(&all_classes, &mut rng).unwrap();
+
+                let student_id_ref = &student_id;
+
+                let res = runner
+                    .run(mood, &[c], |tr| async move {
+                        signup(
+                            &tr,
+                            Student(student_id_ref.clone()),
+                            c.clone(),
+                            Timestamp::now(),
+                        )
+                        .await
+                    })
+                    .await;
+
+                match res {
+                    Ok(()) => my_classes.push(c.clone()),
+                    Err(err) => {
+                        if err.code() == NO_REMAINING_SEATS {
+                            // Populate available classes in the next iteration
+                            all_classes.clear();
+                        } else if err.code() == ALREADY_SIGNED_UP {
+                            // Ignore `Mood::Add` if we have already
+                            // signed up. Otherwise an earlier attempt
+                            // was committed, without us knowing.
+                            if !my_classes.contains(c) {
+                                my_classes.push(c.clone());
+                            }
+                        } else if err.code() == INCOMPLETE_BUNDLE {
+                            // Ignore `Mood::Add` of a class that is
+                            // only taken with the rest of its bundle.
+                        } else if err.code() == TOO_MANY_CLASSES {
+                            debug!(err = "TOO_MANY_CLASSES");
+                            return Err(err);
+                        } else {
+                            debug!(?err);
+                            return Err(err);
+                        }
+                    }
+                }
+            }
+            Mood::Dropout => {
+                // Safety: Fail in case we are unable to select a
+                // random class from `my_classes`.
+                let c = my_classes.choose(&mut rng).unwrap().clone();
+
+                let student_id_ref = &student_id;
+                let c_ref = &c;
+
+                let res = runner
+                    .run(mood, &[&c], |tr| async move {
+                        dropout(
+                            &tr,
+                            Student(student_id_ref.clone()),
+                            c_ref.clone(),
+                            Timestamp::now(),
+                        )
+                        .await
+                    })
+                    .await;
+
+                match res {
+                    Ok(()) => my_classes.retain(|x| *x != c),
+                    Err(err) => {
+                        // `dropout` should not fail.
+                        debug!(?err);
+                        return Err(err);
+                    }
+                }
+            }
+            Mood::Switch => {
+                // Safety: Fail in case we are unable to select a
+                // random class from `my_classes`.
+                let old_c = OldClass(my_classes.choose(&mut rng).unwrap().clone());
+
+                // Safety: Fail in case we are unable to select a
+                // random class from `all_classes`.
+                let new_c = NewClass(
+                    profile
+                        .class_choice
+                        .choose(&all_classes, &mut rng)
+                        .unwrap()
+                        .clone(),
+                );
+
+                let student_id_ref = &student_id;
+                let old_c_ref = &old_c;
+                let new_c_ref = &new_c;
+
+                let res = runner
+                    .run(mood, &[&old_c.0, &new_c.0], |tr| async move {
+                        switch_classes(
+                            &tr,
+                            Student(student_id_ref.clone()),
+                            old_c_ref.clone(),
+                            new_c_ref.clone(),
+                            Timestamp::now(),
+                        )
+                        .await
+                    })
+                    .await;
+
+                let switched = match res {
+                    Ok(()) => true,
+                    Err(err) => {
+                        // Error handling for `switch_classes` is
+                        // similar to `signup`, but we should not be
+                        // seeing `TOO_MANY_CLASSES` errors.
+                        if err.code() == NO_REMAINING_SEATS {
+                            // Populate available classes in the next iteration
+                            all_classes.clear();
+
+                            false
+                        } else if err.code() == ALREADY_SIGNED_UP {
+                            // Ignore `Mood::Switch` if we are already
+                            // attending `new_c`.
+                            false
+                        } else if err.code() == INCOMPLETE_BUNDLE {
+                            // Ignore `Mood::Switch` to a class of a
+                            // bundle.
+                            false
+                        } else {
+                            debug!(?err);
+                            return Err(err);
+                        }
+                    }
+                };
+
+                if switched {
+                    // Remove `old_c` and add `new_c` to
+                    // `my_classes` upon successful swtich.
+                    let OldClass(old_class_name) = old_c;
+
+                    my_classes.retain(|x| *x != old_class_name);
+
+                    my_classes.push({
+                        let NewClass(class_name) = new_c;
+                        class_name
+                    });
+                }
+            }
+            Mood::Browse => {
+                let res = runner
+                    .run(mood, &[], |tr| async move { available_classes(&tr).await })
+                    .await;
+
+                match res {
+                    // What the student sees is what it picks from next.
+                    Ok(class_names) => all_classes = class_names,
+                    Err(err) => {
+                        // `available_classes` should not fail.
+                        debug!(?err);
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+
+    debug!(%student_id, "finished");
+
+    Ok(StudentReport {
+        student: Student(student_id),
+        classes: my_classes,
+        stats: runner.stats,
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct SimConfig {
+    pub students: usize,
+    pub ops_per_student: usize,
+    // Every student gets its own seed, derived from `seed`. Running the
+    // simulation again with the same seed makes the students intend the
+    // same operations, in the same order.
+    pub seed: u64,
+    pub profile: Profile,
+    pub faults: Faults,
+    // Most students running at the same time, all of them by default.
+    pub concurrency: Option<NonZeroUsize>,
+    // Record every operation to this JSON Lines file, to `replay` it
+    // later.
+    pub record: Option<PathBuf>,
+}
+
+// Once every student is done, the classes each student believes it
+// attends are checked against the database. Any disagreement is
+// returned as `SimFailure::Violations`, and students that failed are
+// returned as `SimFailure::Students`.
+//
+// When `shutdown` completes, the students stop after the operation
+// they are running, and the simulation is checked as usual.
+pub async fn run_sim<D>(
+    db: D,
+    config: SimConfig,
+    shutdown: impl Future<Output = ()>,
+) -> Result<SimStats, SimFailure>
+where
+    D: KvDatabase + Clone + Send + Sync + 'static,
+    D::Transaction: FaultTarget,
+{
+    info!(
+        seed = config.seed,
+        profile = ?config.profile,
+        faults = ?config.faults,
+        "starting simulation"
+    );
+
+    let config = Arc::new(config);
+
+    let start = Instant::now();
+
+    let trace = match &config.record {
+        Some(path) => Some(Arc::new(TraceWriter::create(path, start)?)),
+        None => None,
+    };
+
+    let (stop, stop_recv) = watch::channel(false);
+
+    let semaphore = Arc::new(Semaphore::new(
+        config
+            .concurrency
+            .map(NonZeroUsize::get)
+            .unwrap_or(config.students),
+    ));
+
+    let mut students = JoinSet::new();
+
+    let mut students_by_task = HashMap::new();
+
+    let mut seed_rng = StdRng::seed_from_u64(config.seed);
+
+    for i in 0..config.students {
+        let student_id = format!("s{}", i);
+        let cloned_db = db.clone();
+        let student_seed = seed_rng.gen::<u64>();
+        let cloned_config = config.clone();
+        let cloned_stop_recv = stop_recv.clone();
+        let cloned_semaphore = semaphore.clone();
+        let cloned_trace = trace.clone();
+
+        let student = Student(student_id.clone());
+
+        let task = students.spawn(
+            async move {
+                // The semaphore is closed when we are stopping, and
+                // then the students still waiting do not start.
+                let _permit = match cloned_semaphore.acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        return Ok(StudentReport {
+                            student: Student(student_id),
+                            classes: Vec::new(),
+                            stats: SimStats::default(),
+                        })
+                    }
+                };
+
+                indecisive_student(
+                    cloned_db,
+                    student_id,
+                    student_seed,
+                    cloned_config,
+                    cloned_trace,
+                    cloned_stop_recv,
+                )
+                .await
+            }
+            .instrument(debug_span!("indecisive_student", %i)),
+        );
+
+        students_by_task.insert(task.id(), student);
+    }
+
+    tokio::pin!(shutdown);
+
+    let mut stopping = false;
+
+    let mut stats = SimStats::default();
+
+    let mut local_views = BTreeMap::new();
+
+    let mut failures = Vec::new();
+
+    loop {
+        let joined = tokio::select! {
+            // Students are joined first, so that a deterministic
+            // simulation does not depend on which branch is polled.
+            biased;
+
+            joined = students.join_next_with_id() => joined,
+            _ = &mut shutdown, if !stopping => {
+                info!("stopping the students");
+
+                stopping = true;
+
+                // Safety: `stop_recv` is alive until the end of
+                // `run_sim`.
+                stop.send(true).unwrap();
+                semaphore.close();
+
+                continue;
+            }
+        };
+
+        match joined {
+            None => break,
+            Some(Ok((_, Ok(report)))) => {
+                stats.merge(report.stats);
+
+                local_views.insert(report.student, report.classes);
+            }
+            Some(Ok((task, Err(err)))) => failures.push(StudentFailure {
+                student: students_by_task[&task].clone(),
+                error: StudentError::Fdb(err),
+            }),
+            Some(Err(join_err)) => failures.push(StudentFailure {
+                student: students_by_task[&join_err.id()].clone(),
+                error: StudentError::Panicked(panic_message(join_err)),
+            }),
+        }
+    }
+
+    stats.set_elapsed(start.elapsed());
+
+    if let Some(trace) = trace {
+        // Safety: Every student, and its clone of `trace`, is done.
+        Arc::try_unwrap(trace).ok().unwrap().finish()?;
+    }
+
+    debug!(
+        total_transactions = config.students * config.ops_per_student,
+        "transactions run"
+    );
+
+    // Without the local view of a failed student, there is nothing to
+    // verify its classes against.
+    if !failures.is_empty() {
+        failures.sort_by(|a, b| a.student.cmp(&b.student));
+
+        return Err(SimFailure::Students(failures));
+    }
+
+    let violations = verify::verify(&db, &local_views).await?;
+
+    if !violations.is_empty() {
+        return Err(SimFailure::Violations(violations));
+    }
+
+    info!(students = local_views.len(), "verified simulation");
+
+    Ok(stats)
+}
+
+fn panic_message(join_err: JoinError) -> String {
+    match join_err.try_into_panic() {
+        Ok(panic) => panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "panicked".to_string()),
+        Err(join_err) => join_err.to_string(),
+    }
+}
@@ -0,0 +1,312 @@
+use fdb::error::{FdbError, FdbResult};
+use fdb::range::Range;
+use fdb::{Key, Value};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use tokio::runtime::Builder;
+use tokio::sync::Notify;
+use tokio::time::{self, Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+
+use super::verify::check_invariants;
+use super::{run_sim, FaultTarget, SimConfig, SimFailure, SimStats, Violation};
+use crate::init;
+use crate::storage::{
+    KeyValueStream, KvDatabase, KvTransaction, MemoryDatabase, MemoryTransaction,
+    COMMIT_UNKNOWN_RESULT, NOT_COMMITTED, TRANSACTION_CANCELLED, TRANSACTION_TIMED_OUT,
+    TRANSACTION_TOO_OLD,
+};
+
+// Longest a transaction waits before it starts, and before it commits,
+// in microseconds. The other students run in the meantime.
+const MAX_DELAY_US: u64 = 2_000;
+
+// Decides, from the seed, how long every transaction waits and which
+// commits fail with an injected conflict. After every commit, the
+// invariants of the database are checked.
+struct Scheduler {
+    db: MemoryDatabase,
+    conflicts: f64,
+    state: Mutex<SchedulerState>,
+    // Notified once an invariant is violated, to stop the students.
+    violated: Notify,
+}
+
+struct SchedulerState {
+    rng: StdRng,
+    // Transactions committed so far, not counting read only ones.
+    steps: u64,
+    // The first step that violated an invariant, and what it violated.
+    violations: Option<(u64, Vec<Violation>)>,
+}
+
+impl Scheduler {
+    async fn delay(&self) {
+        let delay_us = {
+            // Safety: Fail in case another student panicked while
+            // scheduling.
+            let mut state = self.state.lock().unwrap();
+
+            state.rng.gen_range(0..=MAX_DELAY_US)
+        };
+
+        time::sleep(Duration::from_micros(delay_us)).await;
+    }
+
+    fn inject_conflict(&self) -> bool {
+        if self.conflicts == 0.0 {
+            return false;
+        }
+
+        // Safety: Fail in case another student panicked while
+        // scheduling.
+        self.state.lock().unwrap().rng.gen_bool(self.conflicts)
+    }
+
+    async fn check_step(&self) -> FdbResult<()> {
+        let violations = check_invariants(&self.db.create_transaction()).await?;
+
+        // Safety: Fail in case another student panicked while
+        // scheduling.
+        let mut state = self.state.lock().unwrap();
+
+        state.steps += 1;
+
+        if !violations.is_empty() && state.violations.is_none() {
+            warn!(step = state.steps, "invariants violated");
+
+            state.violations = Some((state.steps, violations));
+
+            self.violated.notify_one();
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct InjectedFaults {
+    cancelled: bool,
+    deadline: Option<Instant>,
+}
+
+// A `MemoryTransaction` that waits for the scheduler before it commits,
+// and that faults can be injected into.
+#[derive(Clone)]
+struct SimulatedTransaction {
+    inner: MemoryTransaction,
+    scheduler: Arc<Scheduler>,
+    faults: Arc<Mutex<InjectedFaults>>,
+}
+
+impl SimulatedTransaction {
+    fn check_faults(&self) -> FdbResult<()> {
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let faults = self.faults.lock().unwrap();
+
+        if faults.cancelled {
+            return Err(FdbError::new(TRANSACTION_CANCELLED));
+        }
+
+        if let Some(deadline) = faults.deadline {
+            if Instant::now() >= deadline {
+                return Err(FdbError::new(TRANSACTION_TIMED_OUT));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl KvTransaction for SimulatedTransaction {
+    fn get(&self, key: impl Into<Key>) -> impl Future<Output = FdbResult<Option<Value>>> + Send {
+        let get = self.check_faults().map(|()| self.inner.get(key));
+
+        async move { get?.await }
+    }
+
+    fn get_range(&self, range: Range) -> KeyValueStream<'_> {
+        match self.check_faults() {
+            Ok(()) => self.inner.get_range(range),
+            Err(err) => Box::pin(tokio_stream::once(Err(err))),
+        }
+    }
+
+    fn get_read_version(&self) -> impl Future<Output = FdbResult<i64>> + Send {
+        let get_read_version = self.check_faults().map(|()| self.inner.get_read_version());
+
+        async move { get_read_version?.await }
+    }
+
+    fn get_range_split_points(
+        &self,
+        range: Range,
+        chunk_size: i64,
+    ) -> impl Future<Output = FdbResult<Vec<Key>>> + Send {
+        let get_range_split_points = self
+            .check_faults()
+            .map(|()| self.inner.get_range_split_points(range, chunk_size));
+
+        async move { get_range_split_points?.await }
+    }
+
+    fn set(&self, key: impl Into<Key>, value: impl Into<Value>) {
+        self.inner.set(key, value)
+    }
+
+    fn clear(&self, key: impl Into<Key>) {
+        self.inner.clear(key)
+    }
+
+    fn clear_range(&self, range: Range) {
+        self.inner.clear_range(range)
+    }
+}
+
+impl FaultTarget for SimulatedTransaction {
+    fn cancel(&self) {
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        self.faults.lock().unwrap().cancelled = true;
+    }
+
+    fn set_timeout(&self, timeout_ms: i32) -> FdbResult<()> {
+        let timeout = Duration::from_millis(timeout_ms as u64);
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        self.faults.lock().unwrap().deadline = Some(Instant::now() + timeout);
+
+        Ok(())
+    }
+
+    async fn commit(&self) -> FdbResult<()> {
+        self.check_faults()?;
+
+        // Read only transactions do not change anything, and are not
+        // scheduled.
+        if self.inner.is_read_only() {
+            return Ok(());
+        }
+
+        self.scheduler.delay().await;
+
+        self.check_faults()?;
+
+        if self.scheduler.inject_conflict() {
+            debug!("injecting a conflict");
+
+            return Err(FdbError::new(NOT_COMMITTED));
+        }
+
+        self.inner.commit()?;
+
+        self.scheduler.check_step().await
+    }
+}
+
+#[derive(Clone)]
+struct SimulatedDatabase {
+    scheduler: Arc<Scheduler>,
+}
+
+impl KvDatabase for SimulatedDatabase {
+    type Transaction = SimulatedTransaction;
+
+    async fn run<T, F, Fut>(&self, mut f: F) -> FdbResult<T>
+    where
+        T: Send,
+        F: FnMut(SimulatedTransaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send,
+    {
+        loop {
+            self.scheduler.delay().await;
+
+            let tr = SimulatedTransaction {
+                inner: self.scheduler.db.create_transaction(),
+                scheduler: self.scheduler.clone(),
+                faults: Arc::new(Mutex::new(InjectedFaults::default())),
+            };
+
+            let res = match f(tr.clone()).await {
+                Ok(t) => FaultTarget::commit(&tr).await.map(|()| t),
+                Err(err) => Err(err),
+            };
+
+            // Like `FdbDatabase::run`, cancelled and timed out
+            // transactions are left to the caller.
+            match res {
+                Err(err) if is_retryable(&err) => debug!(?err, "retrying"),
+                res => return res,
+            }
+        }
+    }
+}
+
+fn is_retryable(err: &FdbError) -> bool {
+    let code = err.code();
+
+    code == TRANSACTION_TOO_OLD || code == NOT_COMMITTED || code == COMMIT_UNKNOWN_RESULT
+}
+
+// Runs the simulation on `MemoryDatabase`, on a single thread, with
+// the clock paused. How long every transaction waits before it starts
+// and before it commits, and so how the students interleave, is drawn
+// from `config.seed`, and `conflicts` of the commits fail with an
+// injected conflict. Running it again with the same seed runs the very
+// same steps.
+//
+// The invariants of the database are checked after every step. Once
+// one is violated, the students are stopped, and the violations are
+// returned as `SimFailure::Violations`.
+pub fn run_deterministic(config: SimConfig, conflicts: f64) -> Result<SimStats, SimFailure> {
+    let rt = Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()?;
+
+    // Differs from the seeds of the students, which are drawn from
+    // `config.seed` too.
+    let scheduler_seed = !config.seed;
+
+    let scheduler = Arc::new(Scheduler {
+        db: MemoryDatabase::new(),
+        conflicts,
+        state: Mutex::new(SchedulerState {
+            rng: StdRng::seed_from_u64(scheduler_seed),
+            steps: 0,
+            violations: None,
+        }),
+        violated: Notify::new(),
+    });
+
+    rt.block_on(async {
+        let db = SimulatedDatabase {
+            scheduler: scheduler.clone(),
+        };
+
+        init(&db).await?;
+
+        let res = run_sim(db, config, scheduler.violated.notified()).await;
+
+        // Safety: Every student is done.
+        let state = scheduler.state.lock().unwrap();
+
+        if let Some((step, violations)) = &state.violations {
+            warn!(step, "stopped at the first violation");
+
+            return Err(SimFailure::Violations(violations.clone()));
+        }
+
+        info!(steps = state.steps, "checked every step");
+
+        res
+    })
+}
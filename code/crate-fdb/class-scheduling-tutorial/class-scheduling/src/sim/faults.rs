@@ -0,0 +1,162 @@
+use fdb::error::{FdbError, FdbResult};
+use fdb::transaction::{FdbTransaction, ReadTransaction, Transaction, TransactionOption};
+
+use rand::Rng;
+
+use std::future::{self, Future};
+use std::str::FromStr;
+
+use crate::storage::{
+    KvTransaction, MemoryTransaction, COMMIT_UNKNOWN_RESULT, TRANSACTION_CANCELLED,
+    TRANSACTION_TIMED_OUT,
+};
+
+// Longest timeout forced on a transaction, in milliseconds.
+const MAX_TIMEOUT_MS: i32 = 10;
+
+// Probabilities of injecting a fault into an attempt of a transaction.
+// At most one fault is injected into an attempt.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Faults {
+    // The transaction is cancelled before it does anything.
+    pub cancel: f64,
+    // The transaction times out after a few milliseconds, possibly
+    // while it is being committed.
+    pub timeout: f64,
+    // The transaction is committed, but `commit_unknown_result` is
+    // reported, so that `db.run` tries it again.
+    pub commit_unknown: f64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Fault {
+    Cancel,
+    Timeout(i32),
+    CommitUnknown,
+}
+
+impl Faults {
+    pub fn is_enabled(&self) -> bool {
+        self.cancel > 0.0 || self.timeout > 0.0 || self.commit_unknown > 0.0
+    }
+
+    pub(super) fn choose<R: Rng>(&self, rng: &mut R) -> Option<Fault> {
+        if !self.is_enabled() {
+            return None;
+        }
+
+        let x = rng.gen::<f64>();
+
+        if x < self.cancel {
+            Some(Fault::Cancel)
+        } else if x < self.cancel + self.timeout {
+            Some(Fault::Timeout(rng.gen_range(1..=MAX_TIMEOUT_MS)))
+        } else if x < self.cancel + self.timeout + self.commit_unknown {
+            Some(Fault::CommitUnknown)
+        } else {
+            None
+        }
+    }
+
+    // An operation that fails with one of these errors has been given
+    // up by `db.run`, and may or may not have been committed.
+    pub(super) fn is_fault_error(&self, err: &FdbError) -> bool {
+        self.is_enabled()
+            && (err.code() == TRANSACTION_CANCELLED || err.code() == TRANSACTION_TIMED_OUT)
+    }
+}
+
+// "cancel,timeout,commit_unknown", for example "0.01,0.01,0.05".
+impl FromStr for Faults {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Faults, String> {
+        let rates = s
+            .split(',')
+            .map(|rate| rate.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|err| format!("invalid rate: {}", err))?;
+
+        let faults = match rates[..] {
+            [cancel, timeout, commit_unknown] => Faults {
+                cancel,
+                timeout,
+                commit_unknown,
+            },
+            _ => return Err("expected three rates: cancel,timeout,commit_unknown".to_string()),
+        };
+
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err("rates must be between 0 and 1".to_string());
+        }
+
+        if rates.iter().sum::<f64>() > 1.0 {
+            return Err("rates must not add up to more than 1".to_string());
+        }
+
+        Ok(faults)
+    }
+}
+
+// A transaction that faults can be injected into.
+pub trait FaultTarget: KvTransaction + Clone {
+    // Later operations of the transaction fail with
+    // `transaction_cancelled`.
+    fn cancel(&self);
+
+    // Later operations of the transaction fail with
+    // `transaction_timed_out` once `timeout_ms` has passed.
+    fn set_timeout(&self, timeout_ms: i32) -> FdbResult<()>;
+
+    fn commit(&self) -> impl Future<Output = FdbResult<()>> + Send;
+}
+
+impl FaultTarget for FdbTransaction {
+    fn cancel(&self) {
+        unsafe { Transaction::cancel(self) }
+    }
+
+    fn set_timeout(&self, timeout_ms: i32) -> FdbResult<()> {
+        self.set_option(TransactionOption::Timeout(timeout_ms))
+    }
+
+    fn commit(&self) -> impl Future<Output = FdbResult<()>> + Send {
+        unsafe { Transaction::commit(self) }
+    }
+}
+
+// `MemoryTransaction` cannot be cancelled or time out, so only
+// `commit_unknown_result` is injected into it.
+impl FaultTarget for MemoryTransaction {
+    fn cancel(&self) {}
+
+    fn set_timeout(&self, _timeout_ms: i32) -> FdbResult<()> {
+        Ok(())
+    }
+
+    fn commit(&self) -> impl Future<Output = FdbResult<()>> + Send {
+        future::ready(MemoryTransaction::commit(self))
+    }
+}
+
+impl Fault {
+    pub(super) fn before<T: FaultTarget>(self, tr: &T) -> FdbResult<()> {
+        match self {
+            Fault::Cancel => tr.cancel(),
+            Fault::Timeout(timeout_ms) => tr.set_timeout(timeout_ms)?,
+            Fault::CommitUnknown => {}
+        }
+
+        Ok(())
+    }
+
+    pub(super) async fn after<T: FaultTarget>(self, tr: &T) -> FdbResult<()> {
+        if let Fault::CommitUnknown = self {
+            tr.commit().await?;
+
+            return Err(FdbError::new(COMMIT_UNKNOWN_RESULT));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use std::str::FromStr;
+
+use super::Mood;
+use crate::Class;
+
+// How a student picks a class out of the available classes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClassChoice {
+    Uniform,
+    // The n-th available class is picked with a probability
+    // proportional to 1 / n^exponent.
+    Zipfian { exponent: f64 },
+    // `fraction` of the picks go to the first `classes` available
+    // classes, the rest are uniform over all of them.
+    HotSpot { classes: usize, fraction: f64 },
+}
+
+impl ClassChoice {
+    pub fn choose<'a, R: Rng>(&self, classes: &'a [Class], rng: &mut R) -> Option<&'a Class> {
+        if classes.is_empty() {
+            return None;
+        }
+
+        match *self {
+            ClassChoice::Uniform => classes.choose(rng),
+            ClassChoice::Zipfian { exponent } => {
+                classes.get(zipf_index(classes.len(), exponent, rng.gen()))
+            }
+            ClassChoice::HotSpot {
+                classes: hot,
+                fraction,
+            } => {
+                if rng.gen_bool(fraction) {
+                    classes[..hot.clamp(1, classes.len())].choose(rng)
+                } else {
+                    classes.choose(rng)
+                }
+            }
+        }
+    }
+}
+
+// Inverts the continuous approximation of the Zipf distribution over
+// `1..=n` at `u` in `[0, 1)`, and returns a zero based index.
+fn zipf_index(n: usize, exponent: f64, u: f64) -> usize {
+    let n_1 = (n + 1) as f64;
+
+    let x = if (exponent - 1.0).abs() < f64::EPSILON {
+        n_1.powf(u)
+    } else {
+        let t = 1.0 - exponent;
+
+        (1.0 + u * (n_1.powf(t) - 1.0)).powf(1.0 / t)
+    };
+
+    (x.floor() as usize).clamp(1, n) - 1
+}
+
+// Relative weights of the moods. A mood that is not possible for a
+// student at the moment, for example `dropout` without any classes,
+// is left out of the choice.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoodWeights {
+    pub add: u32,
+    pub dropout: u32,
+    pub switch: u32,
+    pub browse: u32,
+}
+
+impl MoodWeights {
+    pub(super) fn weight(&self, mood: Mood) -> u32 {
+        match mood {
+            Mood::Add => self.add,
+            Mood::Dropout => self.dropout,
+            Mood::Switch => self.switch,
+            Mood::Browse => self.browse,
+        }
+    }
+}
+
+// "add,dropout,switch,browse", for example "4,1,2,3".
+impl FromStr for MoodWeights {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MoodWeights, String> {
+        let weights = s
+            .split(',')
+            .map(|weight| weight.trim().parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map_err(|err| format!("invalid weight: {}", err))?;
+
+        let mood_weights = match weights[..] {
+            [add, dropout, switch, browse] => MoodWeights {
+                add,
+                dropout,
+                switch,
+                browse,
+            },
+            _ => return Err("expected four weights: add,dropout,switch,browse".to_string()),
+        };
+
+        // A student without classes can only add or browse, and a
+        // student with a full schedule can only drop, switch or browse.
+        if mood_weights.add == 0 && mood_weights.browse == 0 {
+            return Err("add or browse needs a non-zero weight".to_string());
+        }
+
+        if mood_weights.dropout == 0 && mood_weights.switch == 0 && mood_weights.browse == 0 {
+            return Err("dropout, switch or browse needs a non-zero weight".to_string());
+        }
+
+        Ok(mood_weights)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub class_choice: ClassChoice,
+    pub mood_weights: MoodWeights,
+}
+
+pub const PROFILE_NAMES: [&str; 5] = [
+    "uniform",
+    "zipfian",
+    "hot-spot",
+    "browse-heavy",
+    "registration-day",
+];
+
+impl Profile {
+    pub fn named(name: &str) -> Option<Profile> {
+        let (class_choice, mood_weights) = match name {
+            // The original simulation.
+            "uniform" => (ClassChoice::Uniform, (1, 1, 1, 0)),
+            "zipfian" => (ClassChoice::Zipfian { exponent: 1.0 }, (1, 1, 1, 0)),
+            // 90% of the students fight over 10 classes.
+            "hot-spot" => (
+                ClassChoice::HotSpot {
+                    classes: 10,
+                    fraction: 0.9,
+                },
+                (1, 1, 1, 0),
+            ),
+            "browse-heavy" => (ClassChoice::Uniform, (1, 1, 1, 6)),
+            // Students mostly look around and sign up for popular
+            // classes, and change their minds now and then.
+            "registration-day" => (ClassChoice::Zipfian { exponent: 1.2 }, (6, 1, 2, 4)),
+            _ => return None,
+        };
+
+        let (add, dropout, switch, browse) = mood_weights;
+
+        Some(Profile {
+            class_choice,
+            mood_weights: MoodWeights {
+                add,
+                dropout,
+                switch,
+                browse,
+            },
+        })
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        // Safety: "uniform" is one of `PROFILE_NAMES`.
+        Profile::named("uniform").unwrap()
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Profile, String> {
+        Profile::named(s).ok_or_else(|| {
+            format!(
+                "unknown profile {:?}, expected one of: {}",
+                s,
+                PROFILE_NAMES.join(", ")
+            )
+        })
+    }
+}
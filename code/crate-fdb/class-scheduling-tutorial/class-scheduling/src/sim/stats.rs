@@ -0,0 +1,371 @@
+use fdb::error::FdbResult;
+
+use serde_json::json;
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use super::Mood;
+use crate::error_message;
+
+const PERCENTILES: [u32; 3] = [50, 95, 99];
+
+// Buckets in each power of two of nanoseconds. A latency is reported
+// as the largest latency in its bucket, which is off by less than
+// 1/64 of it.
+const SUB_BUCKETS: u64 = 64;
+
+// Counts of latencies in fixed buckets, so that a long simulation uses
+// no more memory than a short one, and merging the stats of students
+// only adds up counts. Latencies below `2 * SUB_BUCKETS` nanoseconds
+// get a bucket each, and each power of two above that is split into
+// `SUB_BUCKETS` buckets.
+#[derive(Clone, Debug, Default)]
+pub struct LatencyHistogram {
+    // Bucket to count. Only the buckets that have latencies in them are
+    // kept.
+    buckets: BTreeMap<u64, u64>,
+    len: u64,
+    min: Duration,
+    max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+
+        *self.buckets.entry(bucket(nanos)).or_insert(0) += 1;
+
+        if self.len == 0 || latency < self.min {
+            self.min = latency;
+        }
+
+        self.max = self.max.max(latency);
+
+        self.len += 1;
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if other.len == 0 {
+            return;
+        }
+
+        for (bucket, count) in &other.buckets {
+            *self.buckets.entry(*bucket).or_insert(0) += count;
+        }
+
+        if self.len == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+
+        self.max = self.max.max(other.max);
+
+        self.len += other.len;
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Nearest-rank percentile of the latencies, as the largest latency
+    // in its bucket. It is never outside of the latencies recorded, so
+    // with a single latency every percentile is that latency.
+    pub fn percentile(&self, percentile: u32) -> Duration {
+        if self.len == 0 {
+            return Duration::ZERO;
+        }
+
+        let rank = (u64::from(percentile.min(100)) * self.len)
+            .div_ceil(100)
+            .max(1);
+
+        let mut seen = 0;
+
+        for (bucket, count) in &self.buckets {
+            seen += count;
+
+            if seen >= rank {
+                return Duration::from_nanos(bucket_max(*bucket)).clamp(self.min, self.max);
+            }
+        }
+
+        self.max
+    }
+}
+
+fn bucket(nanos: u64) -> u64 {
+    if nanos < 2 * SUB_BUCKETS {
+        return nanos;
+    }
+
+    // `nanos >> shift` is in `SUB_BUCKETS..2 * SUB_BUCKETS`.
+    let shift = u64::from(63 - nanos.leading_zeros()) - SUB_BUCKETS.trailing_zeros() as u64;
+
+    (shift + 1) * SUB_BUCKETS + (nanos >> shift) - SUB_BUCKETS
+}
+
+fn bucket_max(bucket: u64) -> u64 {
+    if bucket < 2 * SUB_BUCKETS {
+        return bucket;
+    }
+
+    let shift = bucket / SUB_BUCKETS - 1;
+
+    let sub_bucket = bucket % SUB_BUCKETS + SUB_BUCKETS;
+
+    ((sub_bucket + 1) << shift).saturating_sub(1)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct MoodStats {
+    latencies: LatencyHistogram,
+    commits: usize,
+    // Error code to count.
+    errors: BTreeMap<i32, usize>,
+    // `db.run` attempts beyond the first.
+    retries: usize,
+}
+
+impl MoodStats {
+    fn record<T>(&mut self, latency: Duration, attempts: usize, res: &FdbResult<T>) {
+        self.latencies.record(latency);
+
+        self.retries += attempts.saturating_sub(1);
+
+        match res {
+            Ok(_) => self.commits += 1,
+            Err(err) => *self.errors.entry(err.code()).or_insert(0) += 1,
+        }
+    }
+
+    fn merge(&mut self, other: MoodStats) {
+        self.latencies.merge(&other.latencies);
+
+        self.commits += other.commits;
+
+        for (code, count) in other.errors {
+            *self.errors.entry(code).or_insert(0) += count;
+        }
+
+        self.retries += other.retries;
+    }
+
+    pub fn ops(&self) -> usize {
+        self.latencies.len() as usize
+    }
+
+    pub fn commits(&self) -> usize {
+        self.commits
+    }
+
+    pub fn errors(&self) -> &BTreeMap<i32, usize> {
+        &self.errors
+    }
+
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+
+    pub fn latencies(&self) -> &LatencyHistogram {
+        &self.latencies
+    }
+
+    pub fn latency_percentile(&self, percentile: u32) -> Duration {
+        self.latencies.percentile(percentile)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SimStats {
+    // Indexed by `Mood`.
+    moods: [MoodStats; 4],
+    elapsed: Duration,
+}
+
+impl SimStats {
+    pub(super) fn record<T>(
+        &mut self,
+        mood: Mood,
+        latency: Duration,
+        attempts: usize,
+        res: &FdbResult<T>,
+    ) {
+        self.moods[mood as usize].record(latency, attempts, res);
+    }
+
+    pub(super) fn merge(&mut self, other: SimStats) {
+        for (mood_stats, other_mood_stats) in self.moods.iter_mut().zip(other.moods) {
+            mood_stats.merge(other_mood_stats);
+        }
+    }
+
+    pub(super) fn set_elapsed(&mut self, elapsed: Duration) {
+        self.elapsed = elapsed;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    fn moods(&self) -> impl Iterator<Item = (&'static str, &MoodStats)> {
+        Mood::ALL
+            .iter()
+            .map(move |mood| (mood.name(), &self.moods[*mood as usize]))
+    }
+
+    fn ops(&self) -> usize {
+        self.moods.iter().map(|mood_stats| mood_stats.ops()).sum()
+    }
+
+    // Operations per second.
+    fn throughput(&self, ops: usize) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            ops as f64 / self.elapsed.as_secs_f64()
+        }
+    }
+
+    // Human readable table, followed by the errors of each mood.
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "{:<8} {:>6} {:>8} {:>7} {:>8} {:>8} {:>9} {:>9} {:>9}\n",
+            "mood",
+            "ops",
+            "commits",
+            "errors",
+            "retries",
+            "ops/s",
+            "p50 (ms)",
+            "p95 (ms)",
+            "p99 (ms)"
+        );
+
+        for (name, mood_stats) in self.moods() {
+            summary.push_str(&format!(
+                "{:<8} {:>6} {:>8} {:>7} {:>8} {:>8.1}",
+                name,
+                mood_stats.ops(),
+                mood_stats.commits(),
+                mood_stats.errors().values().sum::<usize>(),
+                mood_stats.retries(),
+                self.throughput(mood_stats.ops()),
+            ));
+
+            for percentile in PERCENTILES {
+                summary.push_str(&format!(
+                    " {:>9.2}",
+                    mood_stats.latency_percentile(percentile).as_secs_f64() * 1000.0
+                ));
+            }
+
+            summary.push('\n');
+        }
+
+        summary.push_str(&format!(
+            "{:<8} {:>6} in {:.2}s, {:.1} ops/s\n",
+            "total",
+            self.ops(),
+            self.elapsed.as_secs_f64(),
+            self.throughput(self.ops())
+        ));
+
+        for (name, mood_stats) in self.moods() {
+            for (code, count) in mood_stats.errors() {
+                summary.push_str(&format!(
+                    "{} error {} ({}): {}\n",
+                    name,
+                    code,
+                    error_message(&fdb::error::FdbError::new(*code)),
+                    count
+                ));
+            }
+        }
+
+        summary
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let moods = self
+            .moods()
+            .map(|(name, mood_stats)| {
+                let latency_ms = PERCENTILES
+                    .iter()
+                    .map(|percentile| {
+                        (
+                            format!("p{}", percentile),
+                            json!(
+                                mood_stats.latency_percentile(*percentile).as_secs_f64() * 1000.0
+                            ),
+                        )
+                    })
+                    .collect::<serde_json::Map<String, serde_json::Value>>();
+
+                let errors = mood_stats
+                    .errors()
+                    .iter()
+                    .map(|(code, count)| (code.to_string(), json!(count)))
+                    .collect::<serde_json::Map<String, serde_json::Value>>();
+
+                (
+                    name.to_string(),
+                    json!({
+                        "ops": mood_stats.ops(),
+                        "commits": mood_stats.commits(),
+                        "errors": errors,
+                        "retries": mood_stats.retries(),
+                        "ops_per_sec": self.throughput(mood_stats.ops()),
+                        "latency_ms": latency_ms,
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<String, serde_json::Value>>();
+
+        json!({
+            "elapsed_secs": self.elapsed.as_secs_f64(),
+            "ops": self.ops(),
+            "ops_per_sec": self.throughput(self.ops()),
+            "moods": moods,
+        })
+    }
+
+    // One row per mood and error code, with the error code empty on the
+    // row that has the totals for the mood.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "mood,error_code,ops,commits,errors,retries,ops_per_sec,p50_ms,p95_ms,p99_ms\n",
+        );
+
+        for (name, mood_stats) in self.moods() {
+            csv.push_str(&format!(
+                "{},,{},{},{},{},{}",
+                name,
+                mood_stats.ops(),
+                mood_stats.commits(),
+                mood_stats.errors().values().sum::<usize>(),
+                mood_stats.retries(),
+                self.throughput(mood_stats.ops()),
+            ));
+
+            for percentile in PERCENTILES {
+                csv.push_str(&format!(
+                    ",{}",
+                    mood_stats.latency_percentile(percentile).as_secs_f64() * 1000.0
+                ));
+            }
+
+            csv.push('\n');
+
+            for (code, count) in mood_stats.errors() {
+                csv.push_str(&format!("{},{},,,{},,,,,\n", name, code, count));
+            }
+        }
+
+        csv
+    }
+}
@@ -0,0 +1,298 @@
+use fdb::error::{FdbError, FdbResult};
+
+use serde::{Deserialize, Serialize};
+
+use tokio::time::Instant;
+
+use tracing::info;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use super::{verify, Mood, SimFailure};
+use crate::storage::KvDatabase;
+use crate::{
+    available_classes, dropout, error_message, init, signup, switch_classes, Class, NewClass,
+    OldClass, Student, Timestamp,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Ok,
+    Error(i32),
+}
+
+impl<T> From<&FdbResult<T>> for Outcome {
+    fn from(res: &FdbResult<T>) -> Outcome {
+        match res {
+            Ok(_) => Outcome::Ok,
+            Err(err) => Outcome::Error(err.code()),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Ok => write!(f, "ok"),
+            Outcome::Error(code) => {
+                write!(f, "{} ({})", error_message(&FdbError::new(*code)), code)
+            }
+        }
+    }
+}
+
+// One line of a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub student: String,
+    pub mood: String,
+    // The class for add and dropout, the old and the new class for
+    // switch, and none for browse.
+    pub classes: Vec<String>,
+    pub outcome: Outcome,
+    // Microseconds since the start of the simulation.
+    pub start_us: u64,
+    pub end_us: u64,
+}
+
+// Writes the operations of all students to a JSON Lines file. The
+// first error is kept, and returned by `finish`.
+pub(super) struct TraceWriter {
+    start: Instant,
+    out: Mutex<(BufWriter<File>, Option<io::Error>)>,
+}
+
+impl TraceWriter {
+    pub(super) fn create(path: &Path, start: Instant) -> io::Result<TraceWriter> {
+        let out = BufWriter::new(File::create(path)?);
+
+        Ok(TraceWriter {
+            start,
+            out: Mutex::new((out, None)),
+        })
+    }
+
+    pub(super) fn record<T>(
+        &self,
+        student: &str,
+        mood: Mood,
+        classes: &[&Class],
+        res: &FdbResult<T>,
+        start: Instant,
+    ) {
+        let entry = TraceEntry {
+            student: student.to_string(),
+            mood: mood.name().to_string(),
+            classes: classes
+                .iter()
+                .map(|Class(class_inner)| class_inner.clone())
+                .collect(),
+            outcome: res.into(),
+            start_us: (start - self.start).as_micros() as u64,
+            end_us: self.start.elapsed().as_micros() as u64,
+        };
+
+        // Safety: Fail in case another student panicked while writing.
+        let mut out = self.out.lock().unwrap();
+
+        if out.1.is_none() {
+            // Safety: `TraceEntry` always serializes.
+            let line = serde_json::to_string(&entry).unwrap();
+
+            if let Err(err) = writeln!(out.0, "{}", line) {
+                out.1 = Some(err);
+            }
+        }
+    }
+
+    pub(super) fn finish(self) -> io::Result<()> {
+        // Safety: Fail in case a student panicked while writing.
+        let (mut out, err) = self.out.into_inner().unwrap();
+
+        if let Some(err) = err {
+            return Err(err);
+        }
+
+        out.flush()
+    }
+}
+
+pub fn read_trace(path: &Path) -> io::Result<Vec<TraceEntry>> {
+    let mut entries = Vec::new();
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        entries.push(
+            serde_json::from_str(&line)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+        );
+    }
+
+    Ok(entries)
+}
+
+// An operation of a trace that did not have the same outcome when it
+// was replayed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    pub entry: TraceEntry,
+    pub replayed: Outcome,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {:?} at {}us: recorded {}, replayed {}",
+            self.entry.student,
+            self.entry.mood,
+            self.entry.classes,
+            self.entry.start_us,
+            self.entry.outcome,
+            self.replayed
+        )
+    }
+}
+
+// Runs `init`, then the operations of `entries` one at a time, in the
+// order they finished. That is the order they were committed in, give
+// or take operations that overlapped. Operations with a different
+// outcome than recorded are returned as `SimFailure::Mismatches`, and
+// the database is then checked like at the end of a simulation.
+//
+// A trace recorded with faults may not replay exactly, as an operation
+// run again after a fault can see the effects of its first attempt.
+pub async fn replay<D: KvDatabase>(
+    db: D,
+    mut entries: Vec<TraceEntry>,
+) -> Result<usize, SimFailure> {
+    init(&db).await?;
+
+    entries.sort_by_key(|entry| entry.end_us);
+
+    let mut local_views = BTreeMap::<Student, Vec<Class>>::new();
+
+    let mut mismatches = Vec::new();
+
+    for entry in &entries {
+        let student = Student(entry.student.clone());
+
+        let classes = entry
+            .classes
+            .iter()
+            .map(|class_inner| Class(class_inner.clone()))
+            .collect::<Vec<Class>>();
+
+        let mood = Mood::from_name(&entry.mood).ok_or_else(|| {
+            SimFailure::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unknown mood {:?}", entry.mood),
+            ))
+        })?;
+
+        let student_ref = &student;
+
+        let replayed = match (mood, &classes[..]) {
+            (Mood::Add, [c]) => {
+                let res = db
+                    .run(|tr| async move {
+                        signup(&tr, student_ref.clone(), c.clone(), Timestamp::now()).await
+                    })
+                    .await;
+
+                if res.is_ok() {
+                    local_views
+                        .entry(student.clone())
+                        .or_default()
+                        .push(c.clone());
+                }
+
+                Outcome::from(&res)
+            }
+            (Mood::Dropout, [c]) => {
+                let res = db
+                    .run(|tr| async move {
+                        dropout(&tr, student_ref.clone(), c.clone(), Timestamp::now()).await
+                    })
+                    .await;
+
+                if res.is_ok() {
+                    local_views
+                        .entry(student.clone())
+                        .or_default()
+                        .retain(|x| x != c);
+                }
+
+                Outcome::from(&res)
+            }
+            (Mood::Switch, [old_c, new_c]) => {
+                let res = db
+                    .run(|tr| async move {
+                        switch_classes(
+                            &tr,
+                            student_ref.clone(),
+                            OldClass(old_c.clone()),
+                            NewClass(new_c.clone()),
+                            Timestamp::now(),
+                        )
+                        .await
+                    })
+                    .await;
+
+                if res.is_ok() {
+                    let my_classes = local_views.entry(student.clone()).or_default();
+
+                    my_classes.retain(|x| x != old_c);
+                    my_classes.push(new_c.clone());
+                }
+
+                Outcome::from(&res)
+            }
+            (Mood::Browse, []) => {
+                let res = db
+                    .run(|tr| async move { available_classes(&tr).await })
+                    .await;
+
+                Outcome::from(&res)
+            }
+            _ => {
+                return Err(SimFailure::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("wrong classes for {}: {:?}", entry.mood, entry.classes),
+                )))
+            }
+        };
+
+        if replayed != entry.outcome {
+            mismatches.push(Mismatch {
+                entry: entry.clone(),
+                replayed,
+            });
+        }
+    }
+
+    if !mismatches.is_empty() {
+        return Err(SimFailure::Mismatches(mismatches));
+    }
+
+    let violations = verify::verify(&db, &local_views).await?;
+
+    if !violations.is_empty() {
+        return Err(SimFailure::Violations(violations));
+    }
+
+    info!(ops = entries.len(), "replayed trace");
+
+    Ok(entries.len())
+}
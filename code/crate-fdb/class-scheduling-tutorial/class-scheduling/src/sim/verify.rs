@@ -0,0 +1,212 @@
+use fdb::error::FdbResult;
+
+use tokio_stream::StreamExt;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::storage::{KvDatabase, KvTransaction};
+use crate::{
+    AttendsKey, AttendsPrefix, Class, ClassKey, ClassPrefix, ClassValue, Student, CLASS_CAPACITY,
+    MAX_CLASSES,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    // The student thinks it attends `class`, but there is no
+    // ("attends", student, class) key.
+    MissingAttends {
+        student: Student,
+        class: Class,
+    },
+    // There is a ("attends", student, class) key, but the student does
+    // not think it attends `class`.
+    UnexpectedAttends {
+        student: Student,
+        class: Class,
+    },
+    TooManyClasses {
+        student: Student,
+        classes: usize,
+    },
+    // seats_left + enrolled != CLASS_CAPACITY
+    SeatCount {
+        class: Class,
+        seats_left: u8,
+        enrolled: usize,
+    },
+    // There is a ("attends", student, class) key, but no
+    // ("class", class) key.
+    MissingClass {
+        student: Student,
+        class: Class,
+    },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MissingAttends {
+                student: Student(student),
+                class: Class(class),
+            } => write!(f, "{} is missing from the roster of {}", student, class),
+            Violation::UnexpectedAttends {
+                student: Student(student),
+                class: Class(class),
+            } => write!(f, "{} is unexpectedly on the roster of {}", student, class),
+            Violation::TooManyClasses {
+                student: Student(student),
+                classes,
+            } => write!(
+                f,
+                "{} attends {} classes, at most {} are allowed",
+                student, classes, MAX_CLASSES
+            ),
+            Violation::SeatCount {
+                class: Class(class),
+                seats_left,
+                enrolled,
+            } => write!(
+                f,
+                "{} has {} seats left and {} enrolled, capacity is {}",
+                class, seats_left, enrolled, CLASS_CAPACITY
+            ),
+            Violation::MissingClass {
+                student: Student(student),
+                class: Class(class),
+            } => write!(f, "{} attends {}, which does not exist", student, class),
+        }
+    }
+}
+
+// Compares what every student believes it attends with the
+// ("attends", ...) keys, and checks the invariants of the database.
+// Both are read in a single transaction, so the database must be
+// quiescent.
+pub(super) async fn verify<D: KvDatabase>(
+    db: &D,
+    local_views: &BTreeMap<Student, Vec<Class>>,
+) -> FdbResult<Vec<Violation>> {
+    let (seats_left, attends) = db.run(|tr| async move { read_classes(&tr).await }).await?;
+
+    let mut violations = Vec::new();
+
+    for (student, classes) in local_views {
+        for class in classes {
+            if !attends.contains(&(student.clone(), class.clone())) {
+                violations.push(Violation::MissingAttends {
+                    student: student.clone(),
+                    class: class.clone(),
+                });
+            }
+        }
+    }
+
+    for (student, class) in &attends {
+        let expected = local_views
+            .get(student)
+            .map(|classes| classes.contains(class))
+            .unwrap_or(false);
+
+        if !expected {
+            violations.push(Violation::UnexpectedAttends {
+                student: student.clone(),
+                class: class.clone(),
+            });
+        }
+    }
+
+    violations.extend(check_classes(seats_left, &attends));
+
+    Ok(violations)
+}
+
+// Checks the invariants that hold after every committed transaction,
+// whatever the students believe.
+pub(super) async fn check_invariants<T: KvTransaction>(tr: &T) -> FdbResult<Vec<Violation>> {
+    let (seats_left, attends) = read_classes(tr).await?;
+
+    Ok(check_classes(seats_left, &attends))
+}
+
+// The seats left in every class, and every (student, class) attended.
+async fn read_classes<T: KvTransaction>(
+    tr: &T,
+) -> FdbResult<(BTreeMap<Class, u8>, BTreeSet<(Student, Class)>)> {
+    // ("class", ...)
+    let mut class_range_stream = tr.get_range(ClassPrefix::new().get_range());
+
+    let mut seats_left = BTreeMap::new();
+
+    while let Some(x) = class_range_stream.next().await {
+        let (key, value) = x?;
+
+        let class_key = TryInto::<ClassKey>::try_into(key)?;
+
+        seats_left.insert(
+            Class::from(class_key),
+            TryInto::<ClassValue>::try_into(value)?.get_val(),
+        );
+    }
+
+    // ("attends", ...)
+    let mut attends_range_stream = tr.get_range(AttendsPrefix::new().get_range());
+
+    let mut attends = BTreeSet::new();
+
+    while let Some(x) = attends_range_stream.next().await {
+        let attends_key = TryInto::<AttendsKey>::try_into(x?.0)?;
+
+        attends.insert((attends_key.student, attends_key.class_name));
+    }
+
+    Ok((seats_left, attends))
+}
+
+fn check_classes(
+    seats_left: BTreeMap<Class, u8>,
+    attends: &BTreeSet<(Student, Class)>,
+) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    let mut enrolled = BTreeMap::<&Class, usize>::new();
+
+    let mut schedules = BTreeMap::<&Student, usize>::new();
+
+    for (student, class) in attends {
+        if !seats_left.contains_key(class) {
+            violations.push(Violation::MissingClass {
+                student: student.clone(),
+                class: class.clone(),
+            });
+        }
+
+        *enrolled.entry(class).or_insert(0) += 1;
+
+        *schedules.entry(student).or_insert(0) += 1;
+    }
+
+    for (student, classes) in schedules {
+        if classes > MAX_CLASSES {
+            violations.push(Violation::TooManyClasses {
+                student: student.clone(),
+                classes,
+            });
+        }
+    }
+
+    for (class, seats_left) in seats_left {
+        let enrolled = enrolled.get(&class).copied().unwrap_or(0);
+
+        if usize::from(seats_left) + enrolled != usize::from(CLASS_CAPACITY) {
+            violations.push(Violation::SeatCount {
+                class,
+                seats_left,
+                enrolled,
+            });
+        }
+    }
+
+    violations
+}
@@ -0,0 +1,130 @@
+use bytes::{BufMut, Bytes, BytesMut};
+
+use fdb::database::FdbDatabase;
+use fdb::error::FdbResult;
+use fdb::range::{Range, RangeOptions};
+use fdb::transaction::{FdbTransaction, ReadTransaction, Transaction};
+use fdb::{Key, KeyValue, Value};
+
+use futures::Stream;
+
+use tokio_stream::StreamExt;
+
+use std::future::Future;
+use std::pin::Pin;
+
+mod memory;
+
+pub use memory::{MemoryDatabase, MemoryTransaction};
+
+// FoundationDB error codes, returned by `MemoryTransaction` and by the
+// faults the simulation injects, like a cluster would return them.
+pub const TRANSACTION_TOO_OLD: i32 = 1007;
+pub const NOT_COMMITTED: i32 = 1020;
+pub const COMMIT_UNKNOWN_RESULT: i32 = 1021;
+pub const TRANSACTION_CANCELLED: i32 = 1025;
+pub const TRANSACTION_TIMED_OUT: i32 = 1031;
+
+// The first key after `key`. A range that begins there starts right
+// after `key`.
+pub(crate) fn key_after(key: &Bytes) -> Bytes {
+    let mut b = BytesMut::from(&key[..]);
+    b.put_u8(0x00);
+    b.freeze()
+}
+
+pub type KeyValueStream<'a> = Pin<Box<dyn Stream<Item = FdbResult<(Key, Value)>> + Send + 'a>>;
+
+// The parts of a FoundationDB transaction that the scheduling logic
+// uses, so that it can also run on `MemoryTransaction`.
+pub trait KvTransaction: Send + Sync {
+    fn get(&self, key: impl Into<Key>) -> impl Future<Output = FdbResult<Option<Value>>> + Send;
+
+    // Key-value pairs of `range`, in key order.
+    fn get_range(&self, range: Range) -> KeyValueStream<'_>;
+
+    fn get_read_version(&self) -> impl Future<Output = FdbResult<i64>> + Send;
+
+    // Keys that split `range` into chunks of about `chunk_size` bytes,
+    // starting with the begin key of `range` and ending with its end
+    // key. They are estimates, and do not conflict with anything.
+    fn get_range_split_points(
+        &self,
+        range: Range,
+        chunk_size: i64,
+    ) -> impl Future<Output = FdbResult<Vec<Key>>> + Send;
+
+    fn set(&self, key: impl Into<Key>, value: impl Into<Value>);
+
+    fn clear(&self, key: impl Into<Key>);
+
+    fn clear_range(&self, range: Range);
+}
+
+pub trait KvDatabase {
+    type Transaction: KvTransaction;
+
+    // Runs `f` in a transaction and commits it, retrying on conflicts
+    // like `FdbDatabase::run`.
+    fn run<T, F, Fut>(&self, f: F) -> impl Future<Output = FdbResult<T>> + Send
+    where
+        T: Send,
+        F: FnMut(Self::Transaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send;
+}
+
+impl KvTransaction for FdbTransaction {
+    fn get(&self, key: impl Into<Key>) -> impl Future<Output = FdbResult<Option<Value>>> + Send {
+        ReadTransaction::get(self, key)
+    }
+
+    fn get_range(&self, range: Range) -> KeyValueStream<'_> {
+        Box::pin(
+            range
+                .into_stream(self, RangeOptions::default())
+                .map(|x| x.map(KeyValue::into_parts)),
+        )
+    }
+
+    fn get_read_version(&self) -> impl Future<Output = FdbResult<i64>> + Send {
+        ReadTransaction::get_read_version(self)
+    }
+
+    fn get_range_split_points(
+        &self,
+        range: Range,
+        chunk_size: i64,
+    ) -> impl Future<Output = FdbResult<Vec<Key>>> + Send {
+        ReadTransaction::get_range_split_points(
+            self,
+            range.begin().clone(),
+            range.end().clone(),
+            chunk_size,
+        )
+    }
+
+    fn set(&self, key: impl Into<Key>, value: impl Into<Value>) {
+        Transaction::set(self, key, value)
+    }
+
+    fn clear(&self, key: impl Into<Key>) {
+        Transaction::clear(self, key)
+    }
+
+    fn clear_range(&self, range: Range) {
+        Transaction::clear_range(self, range)
+    }
+}
+
+impl KvDatabase for FdbDatabase {
+    type Transaction = FdbTransaction;
+
+    fn run<T, F, Fut>(&self, f: F) -> impl Future<Output = FdbResult<T>> + Send
+    where
+        T: Send,
+        F: FnMut(FdbTransaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send,
+    {
+        FdbDatabase::run(self, f)
+    }
+}
@@ -0,0 +1,360 @@
+use bytes::Bytes;
+
+use fdb::error::{FdbError, FdbResult};
+use fdb::range::Range;
+use fdb::{Key, Value};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::future::{self, Future};
+use std::sync::{Arc, Mutex};
+
+use super::{
+    key_after, KeyValueStream, KvDatabase, KvTransaction, COMMIT_UNKNOWN_RESULT, NOT_COMMITTED,
+    TRANSACTION_TOO_OLD,
+};
+
+// Commits kept around to check for conflicts. A transaction that read
+// before the oldest of them fails with `transaction_too_old`.
+const MAX_COMMITS: usize = 10_000;
+
+// [begin, end)
+type KeyRange = (Bytes, Bytes);
+
+#[derive(Debug, Default)]
+struct Store {
+    data: Arc<BTreeMap<Bytes, Bytes>>,
+    version: i64,
+    // Versions and write ranges of the latest commits, oldest first.
+    commits: VecDeque<(i64, Vec<KeyRange>)>,
+    // Version of the latest commit that is no longer in `commits`.
+    forgotten_version: i64,
+}
+
+// An ordered map with transactions, for running the scheduling logic
+// without a FoundationDB cluster.
+//
+// Like FoundationDB, transactions read a snapshot of the map and are
+// only committed if nothing they read was written in the meantime.
+// Otherwise they fail with `not_committed`, and `run` tries them
+// again, as it does after `commit_unknown_result`.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryDatabase {
+    store: Arc<Mutex<Store>>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> MemoryDatabase {
+        MemoryDatabase::default()
+    }
+
+    pub fn create_transaction(&self) -> MemoryTransaction {
+        MemoryTransaction {
+            store: self.store.clone(),
+            state: Arc::new(Mutex::new(TransactionState::default())),
+        }
+    }
+}
+
+impl KvDatabase for MemoryDatabase {
+    type Transaction = MemoryTransaction;
+
+    async fn run<T, F, Fut>(&self, mut f: F) -> FdbResult<T>
+    where
+        T: Send,
+        F: FnMut(MemoryTransaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send,
+    {
+        loop {
+            let tr = self.create_transaction();
+
+            let res = match f(tr.clone()).await {
+                Ok(t) => tr.commit().map(|()| t),
+                Err(err) => Err(err),
+            };
+
+            match res {
+                Err(err)
+                    if err.code() == NOT_COMMITTED
+                        || err.code() == TRANSACTION_TOO_OLD
+                        || err.code() == COMMIT_UNKNOWN_RESULT =>
+                {
+                    continue
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct TransactionState {
+    // Read version and snapshot, taken at the first read.
+    snapshot: Option<(i64, Arc<BTreeMap<Bytes, Bytes>>)>,
+    reads: Vec<KeyRange>,
+    // Keys set, or cleared with `None`, since the snapshot.
+    writes: BTreeMap<Bytes, Option<Bytes>>,
+    cleared: Vec<KeyRange>,
+    write_ranges: Vec<KeyRange>,
+}
+
+impl TransactionState {
+    fn snapshot(&mut self, store: &Mutex<Store>) -> (i64, Arc<BTreeMap<Bytes, Bytes>>) {
+        self.snapshot
+            .get_or_insert_with(|| {
+                // Safety: Fail in case another transaction panicked
+                // while committing.
+                let store = store.lock().unwrap();
+
+                (store.version, store.data.clone())
+            })
+            .clone()
+    }
+
+    fn is_cleared(&self, key: &Bytes) -> bool {
+        self.cleared.iter().any(|range| contains(range, key))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryTransaction {
+    store: Arc<Mutex<Store>>,
+    state: Arc<Mutex<TransactionState>>,
+}
+
+impl MemoryTransaction {
+    pub fn is_read_only(&self) -> bool {
+        // Safety: Fail in case a user of the transaction panicked.
+        self.state.lock().unwrap().write_ranges.is_empty()
+    }
+
+    pub fn commit(&self) -> FdbResult<()> {
+        // Safety: Fail in case a user of the transaction panicked.
+        let mut state = self.state.lock().unwrap();
+
+        // Read only transactions always commit.
+        if state.write_ranges.is_empty() {
+            return Ok(());
+        }
+
+        // Safety: Fail in case another transaction panicked while
+        // committing.
+        let mut store = self.store.lock().unwrap();
+
+        // Let go of the snapshot, so that the map is not copied when
+        // it is changed below.
+        if let Some((read_version, _)) = state.snapshot.take() {
+            if read_version < store.forgotten_version {
+                return Err(FdbError::new(TRANSACTION_TOO_OLD));
+            }
+
+            let conflict = store
+                .commits
+                .iter()
+                .rev()
+                .take_while(|(version, _)| *version > read_version)
+                .flat_map(|(_, write_ranges)| write_ranges)
+                .any(|write_range| {
+                    state
+                        .reads
+                        .iter()
+                        .any(|read_range| overlaps(read_range, write_range))
+                });
+
+            if conflict {
+                return Err(FdbError::new(NOT_COMMITTED));
+            }
+        }
+
+        let data = Arc::make_mut(&mut store.data);
+
+        for (begin, end) in &state.cleared {
+            let keys = data
+                .range(begin.clone()..end.clone())
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<Bytes>>();
+
+            for key in keys {
+                data.remove(&key);
+            }
+        }
+
+        for (key, value) in &state.writes {
+            match value {
+                Some(value) => data.insert(key.clone(), value.clone()),
+                None => data.remove(key),
+            };
+        }
+
+        store.version += 1;
+
+        let version = store.version;
+
+        store
+            .commits
+            .push_back((version, state.write_ranges.clone()));
+
+        if store.commits.len() > MAX_COMMITS {
+            // Safety: `commits` is not empty.
+            let (version, _) = store.commits.pop_front().unwrap();
+
+            store.forgotten_version = version;
+        }
+
+        Ok(())
+    }
+}
+
+impl KvTransaction for MemoryTransaction {
+    fn get(&self, key: impl Into<Key>) -> impl Future<Output = FdbResult<Option<Value>>> + Send {
+        let key = Bytes::from(key.into());
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        state.reads.push((key.clone(), key_after(&key)));
+
+        let value = match state.writes.get(&key) {
+            Some(value) => value.clone(),
+            None if state.is_cleared(&key) => None,
+            None => state.snapshot(&self.store).1.get(&key).cloned(),
+        };
+
+        future::ready(Ok(value.map(Value::from)))
+    }
+
+    fn get_range(&self, range: Range) -> KeyValueStream<'_> {
+        let (begin, end) = range_bounds(&range);
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        state.reads.push((begin.clone(), end.clone()));
+
+        let mut kvs = BTreeMap::new();
+
+        if begin < end {
+            let (_, snapshot) = state.snapshot(&self.store);
+
+            for (key, value) in snapshot.range(begin.clone()..end.clone()) {
+                if !state.is_cleared(key) {
+                    kvs.insert(key.clone(), value.clone());
+                }
+            }
+
+            for (key, value) in state.writes.range(begin..end) {
+                match value {
+                    Some(value) => kvs.insert(key.clone(), value.clone()),
+                    None => kvs.remove(key),
+                };
+            }
+        }
+
+        Box::pin(tokio_stream::iter(
+            kvs.into_iter()
+                .map(|(key, value)| Ok((Key::from(key), Value::from(value)))),
+        ))
+    }
+
+    fn get_read_version(&self) -> impl Future<Output = FdbResult<i64>> + Send {
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        future::ready(Ok(state.snapshot(&self.store).0))
+    }
+
+    fn get_range_split_points(
+        &self,
+        range: Range,
+        chunk_size: i64,
+    ) -> impl Future<Output = FdbResult<Vec<Key>>> + Send {
+        let (begin, end) = range_bounds(&range);
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        let mut split_points = vec![begin.clone()];
+
+        // Like FoundationDB, only committed data is taken into
+        // account, and nothing is added to the reads.
+        if begin < end {
+            let (_, snapshot) = state.snapshot(&self.store);
+
+            let mut chunk_bytes = 0;
+
+            for (key, value) in snapshot.range(begin..end.clone()) {
+                if chunk_bytes >= chunk_size {
+                    split_points.push(key.clone());
+
+                    chunk_bytes = 0;
+                }
+
+                chunk_bytes += (key.len() + value.len()) as i64;
+            }
+        }
+
+        split_points.push(end);
+
+        future::ready(Ok(split_points.into_iter().map(Key::from).collect()))
+    }
+
+    fn set(&self, key: impl Into<Key>, value: impl Into<Value>) {
+        let key = Bytes::from(key.into());
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        state.write_ranges.push((key.clone(), key_after(&key)));
+
+        state.writes.insert(key, Some(Bytes::from(value.into())));
+    }
+
+    fn clear(&self, key: impl Into<Key>) {
+        let key = Bytes::from(key.into());
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        state.write_ranges.push((key.clone(), key_after(&key)));
+
+        state.writes.insert(key, None);
+    }
+
+    fn clear_range(&self, range: Range) {
+        let (begin, end) = range_bounds(&range);
+
+        if begin >= end {
+            return;
+        }
+
+        // Safety: Fail in case another user of the transaction
+        // panicked.
+        let mut state = self.state.lock().unwrap();
+
+        state.writes.retain(|key, _| *key < begin || *key >= end);
+
+        state.cleared.push((begin.clone(), end.clone()));
+
+        state.write_ranges.push((begin, end));
+    }
+}
+
+fn range_bounds(range: &Range) -> KeyRange {
+    (
+        Bytes::from(range.begin().clone()),
+        Bytes::from(range.end().clone()),
+    )
+}
+
+fn contains((begin, end): &KeyRange, key: &Bytes) -> bool {
+    begin <= key && key < end
+}
+
+fn overlaps((begin_a, end_a): &KeyRange, (begin_b, end_b): &KeyRange) -> bool {
+    begin_a < end_b && begin_b < end_a
+}
@@ -0,0 +1,96 @@
+// These tests build schedules on `MemoryDatabase`, and do not need a
+// FoundationDB cluster.
+
+mod common;
+
+use common::{class, schedule};
+
+use class_scheduling::storage::{KvDatabase, KvTransaction, MemoryDatabase};
+use class_scheduling::{
+    build_schedule, init, signup, Class, ClassKey, ClassValue, Student, Timestamp, MAX_CLASSES,
+};
+
+fn student() -> Student {
+    common::student("s0")
+}
+
+async fn build(db: &MemoryDatabase, class_names: &[&str]) -> Vec<Class> {
+    db.run(|tr| {
+        let class_names = class_names.iter().map(|c| class(c)).collect();
+        async move { build_schedule(&tr, student(), class_names, Timestamp::now()).await }
+    })
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn passes_over_conflicts() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    db.run(|tr| async move {
+        signup(&tr, student(), class("12:00 art 201"), Timestamp::now()).await?;
+
+        tr.set(ClassKey::new(class("13:00 chem 201")), ClassValue::new(0));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let built = build(
+        &db,
+        &[
+            "10:00 chem 201",
+            // At the same time as the class picked before it.
+            "10:00 bio 201",
+            // At the same time as a class the student attends.
+            "12:00 chem 201",
+            // Full.
+            "13:00 chem 201",
+            // Part of a bundle.
+            "14:00 chem 101",
+            // Already attended.
+            "12:00 art 201",
+            "11:00 chem 201",
+        ],
+    )
+    .await;
+
+    assert_eq!(
+        built,
+        vec![class("10:00 chem 201"), class("11:00 chem 201")]
+    );
+
+    assert_eq!(
+        schedule(&db, &student()).await,
+        vec![
+            class("10:00 chem 201"),
+            class("11:00 chem 201"),
+            class("12:00 art 201")
+        ]
+    );
+}
+
+#[tokio::test]
+async fn stops_at_max_classes() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let class_names = [
+        "2:00 art 201",
+        "3:00 art 201",
+        "4:00 art 201",
+        "5:00 art 201",
+        "6:00 art 201",
+        "7:00 art 201",
+    ];
+
+    let built = build(&db, &class_names).await;
+
+    assert_eq!(built.len(), MAX_CLASSES);
+
+    assert_eq!(schedule(&db, &student()).await, built);
+}
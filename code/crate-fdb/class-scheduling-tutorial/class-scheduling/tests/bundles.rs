@@ -0,0 +1,217 @@
+// These tests sign students up for bundles of classes on
+// `MemoryDatabase`, and do not need a FoundationDB cluster.
+
+mod common;
+
+use common::{class, schedule, seats_left};
+
+use class_scheduling::storage::{KvDatabase, KvTransaction, MemoryDatabase};
+use class_scheduling::{
+    dropout, dropout_bundle, init, signup, signup_bundle, Class, ClassKey, ClassValue, Student,
+    Timestamp, INCOMPLETE_BUNDLE, NO_REMAINING_SEATS, TIME_CONFLICT, TOO_MANY_CLASSES,
+};
+
+use fdb::error::FdbResult;
+
+// "10:00 chem 101" is taken together with "11:00 chem lab".
+fn bundle() -> Vec<Class> {
+    vec![class("10:00 chem 101"), class("11:00 chem lab")]
+}
+
+async fn bundle_signup(
+    db: &MemoryDatabase,
+    student: &Student,
+    class_names: Vec<Class>,
+) -> FdbResult<()> {
+    db.run(|tr| {
+        let (student, class_names) = (student.clone(), class_names.clone());
+        async move { signup_bundle(&tr, student, class_names, Timestamp::now()).await }
+    })
+    .await
+}
+
+#[tokio::test]
+async fn signup_and_dropout_bundle() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    bundle_signup(&db, &student, bundle()).await.unwrap();
+
+    assert_eq!(schedule(&db, &student).await, bundle());
+
+    for class_name in bundle() {
+        assert_eq!(seats_left(&db, &class_name).await, 99);
+    }
+
+    // The lab is not dropped on its own.
+    let err = db
+        .run(|tr| {
+            let student = student.clone();
+            async move { dropout(&tr, student, class("11:00 chem lab"), Timestamp::now()).await }
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), INCOMPLETE_BUNDLE);
+
+    assert_eq!(schedule(&db, &student).await, bundle());
+
+    // Dropping the bundle of the lab drops the lecture too.
+    db.run(|tr| {
+        let student = student.clone();
+        async move { dropout_bundle(&tr, student, class("11:00 chem lab"), Timestamp::now()).await }
+    })
+    .await
+    .unwrap();
+
+    assert!(schedule(&db, &student).await.is_empty());
+
+    for class_name in bundle() {
+        assert_eq!(seats_left(&db, &class_name).await, 100);
+    }
+}
+
+#[tokio::test]
+async fn incomplete_bundle() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    // A class of a bundle cannot be signed up for on its own.
+    let err = db
+        .run(|tr| {
+            let student = student.clone();
+            async move { signup(&tr, student, class("10:00 chem 101"), Timestamp::now()).await }
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), INCOMPLETE_BUNDLE);
+
+    // A class that is in no bundle has no bundle to drop.
+    let err = db
+        .run(|tr| {
+            let student = student.clone();
+            async move {
+                dropout_bundle(&tr, student, class("10:00 chem 201"), Timestamp::now()).await
+            }
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), INCOMPLETE_BUNDLE);
+
+    for class_names in [
+        vec![],
+        vec![class("10:00 chem 101")],
+        vec![class("10:00 chem 201"), class("11:00 chem 201")],
+        vec![
+            class("10:00 chem 101"),
+            class("11:00 chem lab"),
+            class("12:00 chem 201"),
+        ],
+    ] {
+        let err = bundle_signup(&db, &student, class_names).await.unwrap_err();
+
+        assert_eq!(err.code(), INCOMPLETE_BUNDLE);
+    }
+
+    assert!(schedule(&db, &student).await.is_empty());
+}
+
+// When one class of the bundle cannot be taken, none of them is.
+#[tokio::test]
+async fn bundle_all_or_none() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    db.run(|tr| async move {
+        tr.set(ClassKey::new(class("11:00 chem lab")), ClassValue::new(0));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let err = bundle_signup(&db, &student, bundle()).await.unwrap_err();
+
+    assert_eq!(err.code(), NO_REMAINING_SEATS);
+
+    assert!(schedule(&db, &student).await.is_empty());
+
+    assert_eq!(seats_left(&db, &class("10:00 chem 101")).await, 100);
+
+    // Four classes and a bundle of two are more than `MAX_CLASSES`.
+    for class_name in [
+        "2:00 art 201",
+        "3:00 art 201",
+        "4:00 art 201",
+        "5:00 art 201",
+    ] {
+        db.run(|tr| {
+            let student = student.clone();
+            async move { signup(&tr, student, class(class_name), Timestamp::now()).await }
+        })
+        .await
+        .unwrap();
+    }
+
+    let err = bundle_signup(
+        &db,
+        &student,
+        vec![class("2:00 bio 301"), class("3:00 bio seminar")],
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(err.code(), TOO_MANY_CLASSES);
+
+    assert_eq!(schedule(&db, &student).await.len(), 4);
+
+    assert_eq!(seats_left(&db, &class("2:00 bio 301")).await, 100);
+}
+
+// Neither class of the bundle may be at the same time as a class the
+// student already has.
+#[tokio::test]
+async fn bundle_time_conflict() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    for class_name in ["10:00 art 201", "11:00 bio 201"] {
+        db.run(|tr| {
+            let student = student.clone();
+            async move { signup(&tr, student, class(class_name), Timestamp::now()).await }
+        })
+        .await
+        .unwrap();
+
+        let err = bundle_signup(&db, &student, bundle()).await.unwrap_err();
+
+        assert_eq!(err.code(), TIME_CONFLICT);
+
+        db.run(|tr| {
+            let student = student.clone();
+            async move { dropout(&tr, student, class(class_name), Timestamp::now()).await }
+        })
+        .await
+        .unwrap();
+    }
+
+    assert!(schedule(&db, &student).await.is_empty());
+
+    for class_name in bundle() {
+        assert_eq!(seats_left(&db, &class_name).await, 100);
+    }
+}
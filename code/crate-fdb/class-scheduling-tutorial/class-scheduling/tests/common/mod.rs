@@ -0,0 +1,32 @@
+// Helpers shared by the tests that run on `MemoryDatabase`. Each test
+// binary uses only some of them.
+#![allow(dead_code)]
+
+use class_scheduling::storage::{KvDatabase, MemoryDatabase};
+use class_scheduling::{get_seats_left, get_student_schedule, Class, Student};
+
+pub fn class(class_inner: &str) -> Class {
+    Class(class_inner.to_string())
+}
+
+pub fn student(student_inner: &str) -> Student {
+    Student(student_inner.to_string())
+}
+
+pub async fn schedule(db: &MemoryDatabase, student: &Student) -> Vec<Class> {
+    db.run(|tr| {
+        let student = student.clone();
+        async move { get_student_schedule(&tr, student).await }
+    })
+    .await
+    .unwrap()
+}
+
+pub async fn seats_left(db: &MemoryDatabase, class_name: &Class) -> u8 {
+    db.run(|tr| {
+        let class_name = class_name.clone();
+        async move { get_seats_left(&tr, class_name).await }
+    })
+    .await
+    .unwrap()
+}
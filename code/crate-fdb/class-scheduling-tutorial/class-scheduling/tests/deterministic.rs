@@ -0,0 +1,37 @@
+// These tests run the deterministic simulation, and do not need a
+// FoundationDB cluster.
+
+use class_scheduling::sim::{run_deterministic, Faults, Profile, SimConfig};
+
+fn config(seed: u64) -> SimConfig {
+    SimConfig {
+        students: 10,
+        ops_per_student: 10,
+        seed,
+        profile: Profile::named("hot-spot").unwrap(),
+        faults: "0.02,0.02,0.05".parse().unwrap(),
+        concurrency: None,
+        record: None,
+    }
+}
+
+#[test]
+fn same_seed_same_steps() {
+    let stats = run_deterministic(config(42), 0.1).unwrap();
+
+    let stats_again = run_deterministic(config(42), 0.1).unwrap();
+
+    assert_eq!(stats.to_json(), stats_again.to_json());
+}
+
+#[test]
+fn seeds_without_faults() {
+    for seed in 0..5 {
+        let config = SimConfig {
+            faults: Faults::default(),
+            ..config(seed)
+        };
+
+        run_deterministic(config, 0.0).unwrap();
+    }
+}
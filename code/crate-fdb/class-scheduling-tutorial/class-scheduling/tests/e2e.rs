@@ -0,0 +1,65 @@
+// These tests run the command line tool against a throwaway
+// `fdbserver`, one per test, which needs to be installed. Run them
+// with `cargo test -- --ignored`.
+
+use test_support::TestCluster;
+
+use std::process::Output;
+
+fn class_scheduling(cluster: &TestCluster, args: &[&str]) -> Output {
+    cluster
+        .command(env!("CARGO_BIN_EXE_class-scheduling"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{:?}", output);
+
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+#[ignore = "needs fdbserver"]
+fn signup_and_drop() {
+    let cluster = TestCluster::start().unwrap();
+
+    assert_eq!(stdout(&class_scheduling(&cluster, &["init"])), "ok\n");
+
+    assert_eq!(
+        stdout(&class_scheduling(
+            &cluster,
+            &["signup", "s1", "10:00 chem intro"]
+        )),
+        "ok\n"
+    );
+
+    // ALREADY_SIGNED_UP
+    assert_eq!(
+        class_scheduling(&cluster, &["signup", "s1", "10:00 chem intro"])
+            .status
+            .code(),
+        Some(4)
+    );
+
+    assert_eq!(
+        stdout(&class_scheduling(&cluster, &["schedule", "s1"])),
+        "10:00 chem intro\n"
+    );
+
+    assert_eq!(
+        stdout(&class_scheduling(&cluster, &["roster", "10:00 chem intro"])),
+        "s1\n"
+    );
+
+    assert_eq!(
+        stdout(&class_scheduling(
+            &cluster,
+            &["drop", "s1", "10:00 chem intro"]
+        )),
+        "ok\n"
+    );
+
+    assert_eq!(stdout(&class_scheduling(&cluster, &["schedule", "s1"])), "");
+}
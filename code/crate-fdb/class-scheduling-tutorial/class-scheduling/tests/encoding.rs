@@ -0,0 +1,179 @@
+// Checks that keys and values decode back to what they were encoded
+// from, and pins their encodings. Existing databases hold keys and
+// values in these encodings, so changing one of them needs a
+// migration, not just an updated test.
+
+use class_scheduling::{
+    AttendsKey, Class, ClassKey, ClassValue, Student, KEY_CONVERTION_ERROR, VALUE_CONVERTION_ERROR,
+};
+
+use fdb::{Key, Value};
+
+use bytes::Bytes;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use std::convert::TryFrom;
+
+const CASES: usize = 1_000;
+
+// Strings that an encoding can easily get wrong: empty ones, NUL
+// bytes, which the tuple layer escapes, and multi-byte characters.
+fn random_string(rng: &mut StdRng) -> String {
+    let len = rng.gen_range(0..=16);
+
+    (0..len)
+        .map(|_| match rng.gen_range(0..4) {
+            0 => '\0',
+            // Safety: The slice is not empty.
+            1 => *['\u{ff}', 'é', '\u{fffd}', '\u{10ffff}', '😀']
+                .choose(rng)
+                .unwrap(),
+            2 => rng.gen::<char>(),
+            _ => rng.gen_range('a'..='z'),
+        })
+        .collect()
+}
+
+fn edge_case_strings() -> Vec<String> {
+    ["", "\0", "\0\0", "\0\u{ff}", "\u{ff}", "class", "attends"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
+
+#[test]
+fn class_key_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let class_names = edge_case_strings()
+        .into_iter()
+        .chain((0..CASES).map(|_| random_string(&mut rng)));
+
+    for class_inner in class_names {
+        let class_key = ClassKey::new(Class(class_inner));
+
+        let key = Key::from(class_key.clone());
+
+        assert_eq!(ClassKey::try_from(key).unwrap(), class_key);
+    }
+}
+
+#[test]
+fn attends_key_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0);
+
+    let edge_cases = edge_case_strings();
+
+    let names = edge_cases
+        .iter()
+        .flat_map(|student| {
+            edge_cases
+                .iter()
+                .map(move |class_inner| (student.clone(), class_inner.clone()))
+        })
+        .chain((0..CASES).map(|_| (random_string(&mut rng), random_string(&mut rng))))
+        .collect::<Vec<(String, String)>>();
+
+    for (student, class_inner) in names {
+        let attends_key = AttendsKey::new(Student(student), Class(class_inner));
+
+        let key = Key::from(attends_key.clone());
+
+        assert_eq!(AttendsKey::try_from(key).unwrap(), attends_key);
+    }
+}
+
+#[test]
+fn keys_of_other_kinds() {
+    let class_key = Key::from(ClassKey::new(Class("10:00 chem intro".to_string())));
+
+    let attends_key = Key::from(AttendsKey::new(
+        Student("s0".to_string()),
+        Class("10:00 chem intro".to_string()),
+    ));
+
+    assert_eq!(
+        AttendsKey::try_from(class_key).unwrap_err().code(),
+        KEY_CONVERTION_ERROR
+    );
+
+    assert_eq!(
+        ClassKey::try_from(attends_key).unwrap_err().code(),
+        KEY_CONVERTION_ERROR
+    );
+}
+
+#[test]
+fn class_value_round_trip() {
+    for seats_left in 0..=u8::MAX {
+        let value = Value::from(ClassValue::new(seats_left));
+
+        assert_eq!(ClassValue::try_from(value).unwrap().get_val(), seats_left);
+    }
+}
+
+#[test]
+fn class_value_of_wrong_length() {
+    for value_bytes in [&b""[..], &b"\x64\x00"[..]] {
+        let value = Value::from(Bytes::from_static(value_bytes));
+
+        assert_eq!(
+            ClassValue::try_from(value).unwrap_err().code(),
+            VALUE_CONVERTION_ERROR
+        );
+    }
+}
+
+fn key_bytes(key: impl Into<Key>) -> Bytes {
+    Bytes::from(key.into())
+}
+
+#[test]
+fn class_key_bytes() {
+    // ("class", "10:00 chem intro")
+    assert_eq!(
+        key_bytes(ClassKey::new(Class("10:00 chem intro".to_string()))),
+        Bytes::from_static(b"\x02class\x00\x0210:00 chem intro\x00")
+    );
+
+    // ("class", "")
+    assert_eq!(
+        key_bytes(ClassKey::new(Class("".to_string()))),
+        Bytes::from_static(b"\x02class\x00\x02\x00")
+    );
+}
+
+#[test]
+fn attends_key_bytes() {
+    // ("attends", "s0", "10:00 chem intro")
+    assert_eq!(
+        key_bytes(AttendsKey::new(
+            Student("s0".to_string()),
+            Class("10:00 chem intro".to_string())
+        )),
+        Bytes::from_static(b"\x02attends\x00\x02s0\x00\x0210:00 chem intro\x00")
+    );
+
+    // ("attends", "s\0", "é"), where NUL is escaped as 00 ff, and "é"
+    // is UTF-8.
+    assert_eq!(
+        key_bytes(AttendsKey::new(
+            Student("s\0".to_string()),
+            Class("é".to_string())
+        )),
+        Bytes::from_static(b"\x02attends\x00\x02s\x00\xff\x00\x02\xc3\xa9\x00")
+    );
+}
+
+#[test]
+fn class_value_bytes() {
+    for (seats_left, value_bytes) in [(0, b"\x00"), (1, b"\x01"), (100, b"\x64")] {
+        assert_eq!(
+            Bytes::from(Value::from(ClassValue::new(seats_left))),
+            Bytes::from_static(value_bytes)
+        );
+    }
+}
@@ -0,0 +1,498 @@
+// These tests check and repair data on `MemoryDatabase`, and do not
+// need a FoundationDB cluster.
+
+mod common;
+
+use common::{class, seats_left, student};
+
+use class_scheduling::fsck::{fsck, repair, Finding};
+use class_scheduling::storage::{KvDatabase, KvTransaction, MemoryDatabase};
+use class_scheduling::{
+    available_classes, get_class_bundle, init, set_priority_group, set_registration_window, signup,
+    submit_preferences, AttendsKey, Bundle, Class, ClassKey, ClassValue, Phase, PriorityGroup,
+    Student, Timestamp,
+};
+
+use fdb::tuple::Tuple;
+use fdb::{Key, Value};
+
+use bytes::Bytes;
+
+async fn signup_all(db: &MemoryDatabase, signups: &[(Student, Class)]) {
+    for (student, class_name) in signups {
+        db.run(|tr| {
+            let (student, class_name) = (student.clone(), class_name.clone());
+            async move { signup(&tr, student, class_name, Timestamp::now()).await }
+        })
+        .await
+        .unwrap();
+    }
+}
+
+// More students than `fsck` reads in a transaction, and more classes,
+// so that it has to pick up where it left off.
+#[tokio::test]
+async fn consistent_data() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    // The classes of a bundle are not signed up for one at a time.
+    let class_names = db
+        .run(|tr| async move {
+            let mut class_names = Vec::new();
+
+            for class_name in available_classes(&tr).await? {
+                if get_class_bundle(&tr, class_name.clone()).await?.is_none() {
+                    class_names.push(class_name);
+                }
+            }
+
+            Ok(class_names)
+        })
+        .await
+        .unwrap();
+
+    let signups = (0..1_100)
+        .map(|i| {
+            (
+                Student(format!("s{}", i)),
+                class_names[i % class_names.len()].clone(),
+            )
+        })
+        .collect::<Vec<(Student, Class)>>();
+
+    signup_all(&db, &signups).await;
+
+    assert_eq!(fsck(&db).await.unwrap(), vec![]);
+}
+
+#[tokio::test]
+async fn inconsistent_data() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let mut signups = vec![(student("s1"), class("2:00 chem intro"))];
+
+    for time in ["2:00", "3:00", "4:00", "5:00", "6:00"] {
+        signups.push((student("s2"), class(&format!("{} bio intro", time))));
+    }
+
+    signup_all(&db, &signups).await;
+
+    db.run(|tr| async move {
+        // A class that was deleted while s1 still attends it.
+        tr.clear(ClassKey::new(class("2:00 chem intro")));
+
+        // Seats left that are off.
+        tr.set(ClassKey::new(class("3:00 chem intro")), ClassValue::new(7));
+
+        // A sixth class for s2, with the seat taken.
+        tr.set(
+            AttendsKey::new(student("s2"), class("7:00 bio intro")),
+            Bytes::new(),
+        );
+        tr.set(ClassKey::new(class("7:00 bio intro")), ClassValue::new(99));
+
+        // ("attends", 1)
+        tr.set(
+            Key::from(Bytes::from_static(b"\x02attends\x00\x15\x01")),
+            Bytes::new(),
+        );
+
+        // ("bundled", "2:00 chem 101")
+        tr.clear(Key::from({
+            let mut tup = Tuple::new();
+
+            tup.add_string("bundled".to_string());
+            tup.add_string("2:00 chem 101".to_string());
+
+            tup.pack()
+        }));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let findings = fsck(&db).await.unwrap();
+
+    let too_many_classes = Finding::TooManyClasses {
+        student: student("s2"),
+        classes: 6,
+    };
+
+    let expected = vec![
+        Finding::OrphanedAttends {
+            student: student("s1"),
+            class: class("2:00 chem intro"),
+        },
+        Finding::Undecodable {
+            key: Bytes::from_static(b"\x02attends\x00\x15\x01"),
+            value: Bytes::new(),
+        },
+        too_many_classes.clone(),
+        Finding::SeatCount {
+            class: class("3:00 chem intro"),
+            seats_left: Some(7),
+            enrolled: 0,
+        },
+        Finding::BundledIndex {
+            class: class("2:00 chem 101"),
+            bundle: Some(Bundle("2:00 chem 101 with lab".to_string())),
+            indexed: None,
+        },
+    ];
+
+    assert_eq!(findings.len(), expected.len(), "{:?}", findings);
+
+    for finding in &expected {
+        assert!(findings.contains(finding), "{:?}", finding);
+    }
+
+    let repaired = repair(&db, &findings).await.unwrap();
+
+    for (finding, repaired) in findings.iter().zip(repaired) {
+        assert_eq!(repaired, *finding != too_many_classes, "{:?}", finding);
+    }
+
+    assert_eq!(fsck(&db).await.unwrap(), vec![too_many_classes]);
+}
+
+fn bundled_key(class_inner: &str) -> Key {
+    // ("bundled", class_name)
+    Key::from({
+        let mut tup = Tuple::new();
+
+        tup.add_string("bundled".to_string());
+        tup.add_string(class_inner.to_string());
+
+        tup.pack()
+    })
+}
+
+async fn set(db: &MemoryDatabase, key: impl Into<Key>, value: impl Into<Value>) {
+    let (key, value) = (key.into(), value.into());
+
+    db.run(|tr| {
+        let (key, value) = (key.clone(), value.clone());
+
+        async move {
+            tr.set(key, value);
+
+            Ok(())
+        }
+    })
+    .await
+    .unwrap();
+}
+
+// Someone signs up for the class after the scan, so the seats left
+// are not what the scan saw, and are left alone.
+#[tokio::test]
+async fn seat_count_changed() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    set(
+        &db,
+        ClassKey::new(class("3:00 chem intro")),
+        ClassValue::new(7),
+    )
+    .await;
+
+    let findings = fsck(&db).await.unwrap();
+
+    assert_eq!(
+        findings,
+        vec![Finding::SeatCount {
+            class: class("3:00 chem intro"),
+            seats_left: Some(7),
+            enrolled: 0,
+        }]
+    );
+
+    signup_all(&db, &[(student("s1"), class("3:00 chem intro"))]).await;
+
+    assert_eq!(repair(&db, &findings).await.unwrap(), vec![false]);
+
+    assert_eq!(seats_left(&db, &class("3:00 chem intro")).await, 6);
+}
+
+// An attends key shows up after the scan, without the seats left
+// changing. The seats left are worked out from the students enrolled
+// when the repair runs, not from the scan.
+#[tokio::test]
+async fn seat_count_recounted() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    set(
+        &db,
+        ClassKey::new(class("3:00 chem intro")),
+        ClassValue::new(7),
+    )
+    .await;
+
+    let findings = fsck(&db).await.unwrap();
+
+    set(
+        &db,
+        AttendsKey::new(student("s1"), class("3:00 chem intro")),
+        Bytes::new(),
+    )
+    .await;
+
+    assert_eq!(repair(&db, &findings).await.unwrap(), vec![true]);
+
+    assert_eq!(seats_left(&db, &class("3:00 chem intro")).await, 99);
+
+    assert_eq!(fsck(&db).await.unwrap(), vec![]);
+}
+
+// A key that doesn't decode is only cleared when it still has the
+// value the scan saw.
+#[tokio::test]
+async fn undecodable_changed() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    // ("attends", 1)
+    let key = Key::from(Bytes::from_static(b"\x02attends\x00\x15\x01"));
+
+    set(&db, key.clone(), Bytes::new()).await;
+
+    let findings = fsck(&db).await.unwrap();
+
+    assert_eq!(findings.len(), 1, "{:?}", findings);
+
+    set(&db, key.clone(), Bytes::from_static(b"changed")).await;
+
+    assert_eq!(repair(&db, &findings).await.unwrap(), vec![false]);
+
+    let value = db
+        .run(|tr| {
+            let key = key.clone();
+            async move { tr.get(key).await }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(value.map(Bytes::from), Some(Bytes::from_static(b"changed")));
+}
+
+// Someone fixes the index, or changes the bundle, after the scan.
+#[tokio::test]
+async fn bundled_index_changed() {
+    for fixed in [true, false] {
+        let db = MemoryDatabase::new();
+
+        init(&db).await.unwrap();
+
+        let bundle = Bundle("2:00 chem 101 with lab".to_string());
+
+        db.run(|tr| async move {
+            tr.clear(bundled_key("2:00 chem 101"));
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        let findings = fsck(&db).await.unwrap();
+
+        assert_eq!(
+            findings,
+            vec![Finding::BundledIndex {
+                class: class("2:00 chem 101"),
+                bundle: Some(bundle.clone()),
+                indexed: None,
+            }]
+        );
+
+        // ("bundled", "2:00 chem 101") = ("something else")
+        let other_bundle = {
+            let mut tup = Tuple::new();
+
+            tup.add_string("something else".to_string());
+
+            tup.pack()
+        };
+
+        if fixed {
+            set(&db, bundled_key("2:00 chem 101"), other_bundle.clone()).await;
+        } else {
+            // ("bundle", "2:00 chem 101 with lab", "2:00 chem 101")
+            db.run(|tr| async move {
+                tr.clear(Key::from({
+                    let mut tup = Tuple::new();
+
+                    tup.add_string("bundle".to_string());
+                    tup.add_string("2:00 chem 101 with lab".to_string());
+                    tup.add_string("2:00 chem 101".to_string());
+
+                    tup.pack()
+                }));
+
+                Ok(())
+            })
+            .await
+            .unwrap();
+        }
+
+        assert_eq!(repair(&db, &findings).await.unwrap(), vec![false]);
+
+        let value = db
+            .run(|tr| async move { tr.get(bundled_key("2:00 chem 101")).await })
+            .await
+            .unwrap();
+
+        if fixed {
+            assert_eq!(value.map(Bytes::from), Some(other_bundle));
+        } else {
+            assert_eq!(value, None);
+        }
+    }
+}
+
+// A class in no bundle is indexed as part of one, and is either left
+// that way or added to the bundle after the scan.
+#[tokio::test]
+async fn bundled_index_cleared() {
+    for added in [false, true] {
+        let db = MemoryDatabase::new();
+
+        init(&db).await.unwrap();
+
+        // ("2:00 chem 101 with lab")
+        let indexed = {
+            let mut tup = Tuple::new();
+
+            tup.add_string("2:00 chem 101 with lab".to_string());
+
+            tup.pack()
+        };
+
+        set(&db, bundled_key("3:00 chem intro"), indexed.clone()).await;
+
+        let findings = fsck(&db).await.unwrap();
+
+        assert_eq!(
+            findings,
+            vec![Finding::BundledIndex {
+                class: class("3:00 chem intro"),
+                bundle: None,
+                indexed: Some(indexed.clone()),
+            }]
+        );
+
+        if added {
+            // ("bundle", "2:00 chem 101 with lab", "3:00 chem intro")
+            let bundle_key = {
+                let mut tup = Tuple::new();
+
+                tup.add_string("bundle".to_string());
+                tup.add_string("2:00 chem 101 with lab".to_string());
+                tup.add_string("3:00 chem intro".to_string());
+
+                tup.pack()
+            };
+
+            set(&db, bundle_key, Bytes::new()).await;
+        }
+
+        assert_eq!(repair(&db, &findings).await.unwrap(), vec![!added]);
+
+        let value = db
+            .run(|tr| async move { tr.get(bundled_key("3:00 chem intro")).await })
+            .await
+            .unwrap();
+
+        if added {
+            assert_eq!(value.map(Bytes::from), Some(indexed));
+        } else {
+            assert_eq!(value, None);
+        }
+    }
+}
+
+// Registration windows, priority groups and lottery requests that
+// don't decode are reported, and left for someone to fix by hand.
+#[tokio::test]
+async fn undecodable_kept() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    db.run(|tr| async move {
+        set_registration_window(&tr, Phase::Lottery, Timestamp(0), Timestamp(i64::MAX));
+
+        set_priority_group(&tr, student("s1"), PriorityGroup("seniors".to_string()));
+
+        submit_preferences(
+            &tr,
+            student("s1"),
+            vec![class("10:00 chem 201")],
+            Timestamp::now(),
+        )
+        .await?;
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let mut keys = Vec::new();
+
+    for subspace in ["registration_window", "priority_group", "lottery_request"] {
+        let mut tup = Tuple::new();
+
+        tup.add_string(subspace.to_string());
+        tup.add_string("s2".to_string());
+
+        keys.push(Key::from(tup.pack()));
+
+        // (subspace, 1)
+        let mut tup = Tuple::new();
+
+        tup.add_string(subspace.to_string());
+        tup.add_i64(1);
+
+        keys.push(Key::from(tup.pack()));
+    }
+
+    for key in &keys {
+        set(&db, key.clone(), Bytes::from_static(b"nonsense")).await;
+    }
+
+    let findings = fsck(&db).await.unwrap();
+
+    assert_eq!(findings.len(), keys.len(), "{:?}", findings);
+
+    assert!(repair(&db, &findings)
+        .await
+        .unwrap()
+        .into_iter()
+        .all(|repaired| !repaired));
+
+    for key in keys {
+        let value = db
+            .run(|tr| {
+                let key = key.clone();
+                async move { tr.get(key).await }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            value.map(Bytes::from),
+            Some(Bytes::from_static(b"nonsense"))
+        );
+    }
+
+    assert_eq!(fsck(&db).await.unwrap(), findings);
+}
@@ -0,0 +1,379 @@
+// These tests send requests to the routes of the HTTP/JSON service on
+// `MemoryDatabase`, and do not need a FoundationDB cluster or a
+// listening server.
+
+use class_scheduling::http::router;
+use class_scheduling::storage::{KvDatabase, KvTransaction, MemoryDatabase};
+use class_scheduling::{
+    init, run_lottery, set_registration_window, Class, ClassKey, Phase, Timestamp,
+    ALREADY_SIGNED_UP, INCOMPLETE_BUNDLE, NOT_SIGNED_UP, NO_SUCH_CLASS, REGISTRATION_CLOSED,
+    VALUE_CONVERTION_ERROR,
+};
+
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode};
+
+use bytes::Bytes;
+
+use http_body_util::BodyExt;
+
+use serde_json::{json, Value};
+
+use tower::ServiceExt;
+
+async fn request(
+    db: &MemoryDatabase,
+    method: Method,
+    uri: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let request = Request::builder().method(method).uri(uri);
+
+    let request = match body {
+        Some(body) => request
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = router(db.clone()).oneshot(request).await.unwrap();
+
+    let status_code = response.status();
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+
+    (status_code, serde_json::from_slice(&body).unwrap())
+}
+
+async fn init_db() -> MemoryDatabase {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    db
+}
+
+fn error_code(body: &Value) -> i64 {
+    body["error"]["code"].as_i64().unwrap()
+}
+
+#[tokio::test]
+async fn health_and_classes() {
+    let db = init_db().await;
+
+    let (status_code, body) = request(&db, Method::GET, "/health", None).await;
+
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(body, json!({ "status": "ok" }));
+
+    let (status_code, body) = request(&db, Method::GET, "/classes", None).await;
+
+    assert_eq!(status_code, StatusCode::OK);
+    assert!(body["classes"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("10:00 chem 201")));
+}
+
+#[tokio::test]
+async fn signup_and_dropout() {
+    let db = init_db().await;
+
+    let uri = "/students/s1/classes/10:00%20chem%20201";
+
+    let (status_code, body) = request(&db, Method::PUT, uri, None).await;
+
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(body, json!({ "status": "ok" }));
+
+    let (status_code, body) = request(&db, Method::PUT, uri, None).await;
+
+    assert_eq!(status_code, StatusCode::CONFLICT);
+    assert_eq!(error_code(&body), ALREADY_SIGNED_UP as i64);
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(body, json!({ "classes": ["10:00 chem 201"] }));
+
+    let (status_code, _) = request(&db, Method::DELETE, uri, None).await;
+
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(body, json!({ "classes": [] }));
+}
+
+#[tokio::test]
+async fn error_status_codes() {
+    let db = init_db().await;
+
+    let (status_code, body) = request(
+        &db,
+        Method::PUT,
+        "/students/s1/classes/10:00%20chem%20nonsense",
+        None,
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&body), NO_SUCH_CLASS as i64);
+    assert_eq!(body["error"]["message"], json!("no such class"));
+
+    let (status_code, body) = request(
+        &db,
+        Method::PUT,
+        "/students/s1/classes/10:00%20chem%20101",
+        None,
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_code(&body), INCOMPLETE_BUNDLE as i64);
+
+    // Data that doesn't decode is a server error.
+    db.run(|tr| async move {
+        tr.set(
+            ClassKey::new(Class("10:00 chem 201".to_string())),
+            Bytes::from_static(b"nonsense"),
+        );
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let (status_code, body) = request(
+        &db,
+        Method::PUT,
+        "/students/s1/classes/10:00%20chem%20201",
+        None,
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(error_code(&body), VALUE_CONVERTION_ERROR as i64);
+
+    // The server checks registration windows against the system clock,
+    // and this one is long over.
+    db.run(|tr| async move {
+        set_registration_window(&tr, Phase::Open, Timestamp(0), Timestamp(1));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let (status_code, body) = request(
+        &db,
+        Method::PUT,
+        "/students/s1/classes/11:00%20chem%20201",
+        None,
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::FORBIDDEN);
+    assert_eq!(error_code(&body), REGISTRATION_CLOSED as i64);
+}
+
+#[tokio::test]
+async fn bundle_signup() {
+    let db = init_db().await;
+
+    let (status_code, _) = request(
+        &db,
+        Method::POST,
+        "/students/s1/bundles",
+        Some(json!({ "classes": ["10:00 chem 101", "11:00 chem lab"] })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(
+        body,
+        json!({ "classes": ["10:00 chem 101", "11:00 chem lab"] })
+    );
+
+    let (status_code, body) = request(
+        &db,
+        Method::DELETE,
+        "/students/s1/classes/11:00%20chem%20lab",
+        None,
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_code(&body), INCOMPLETE_BUNDLE as i64);
+
+    let (status_code, _) = request(
+        &db,
+        Method::DELETE,
+        "/students/s1/bundles/11:00%20chem%20lab",
+        None,
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(body, json!({ "classes": [] }));
+
+    let (status_code, body) = request(
+        &db,
+        Method::POST,
+        "/students/s2/bundles",
+        Some(json!({ "classes": [] })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_code(&body), INCOMPLETE_BUNDLE as i64);
+}
+
+#[tokio::test]
+async fn switch() {
+    let db = init_db().await;
+
+    for uri in [
+        "/students/s1/classes/10:00%20chem%20201",
+        "/students/s1/classes/11:00%20chem%20201",
+    ] {
+        let (status_code, _) = request(&db, Method::PUT, uri, None).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+    }
+
+    let (status_code, body) = request(
+        &db,
+        Method::POST,
+        "/students/s1/switch",
+        Some(json!({ "old_class": "10:00 chem 201", "new_class": "11:00 chem 201" })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::CONFLICT);
+    assert_eq!(error_code(&body), ALREADY_SIGNED_UP as i64);
+
+    let (status_code, body) = request(
+        &db,
+        Method::POST,
+        "/students/s1/switch",
+        Some(json!({ "old_class": "10:00 chem 201", "new_class": "12:00 chem nonsense" })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::NOT_FOUND);
+    assert_eq!(error_code(&body), NO_SUCH_CLASS as i64);
+
+    let (status_code, body) = request(
+        &db,
+        Method::POST,
+        "/students/s1/switch",
+        Some(json!({ "old_class": "10:00 chem 201", "new_class": "12:00 chem 201" })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(body, json!({ "status": "ok" }));
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(
+        body,
+        json!({ "classes": ["11:00 chem 201", "12:00 chem 201"] })
+    );
+}
+
+#[tokio::test]
+async fn trade() {
+    let db = init_db().await;
+
+    for uri in [
+        "/students/s1/classes/10:00%20chem%20201",
+        "/students/s2/classes/11:00%20bio%20201",
+    ] {
+        let (status_code, _) = request(&db, Method::PUT, uri, None).await;
+
+        assert_eq!(status_code, StatusCode::OK);
+    }
+
+    let trade_request = json!({
+        "student_a": "s1",
+        "class_a": "10:00 chem 201",
+        "student_b": "s2",
+        "class_b": "11:00 bio 201",
+    });
+
+    let (status_code, _) = request(&db, Method::POST, "/trades", Some(trade_request.clone())).await;
+
+    assert_eq!(status_code, StatusCode::OK);
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(body, json!({ "classes": ["11:00 bio 201"] }));
+
+    // s1 no longer attends the class to trade.
+    let (status_code, body) = request(&db, Method::POST, "/trades", Some(trade_request)).await;
+
+    assert_eq!(status_code, StatusCode::CONFLICT);
+    assert_eq!(error_code(&body), NOT_SIGNED_UP as i64);
+}
+
+#[tokio::test]
+async fn preferences() {
+    let db = init_db().await;
+
+    let (status_code, _) = request(
+        &db,
+        Method::PUT,
+        "/students/s1/preferences",
+        Some(json!({ "classes": ["10:00 chem 201", "11:00 chem 201"] })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::OK);
+
+    run_lottery(&db, 42).await.unwrap();
+
+    let (_, body) = request(&db, Method::GET, "/students/s1/classes", None).await;
+
+    assert_eq!(
+        body,
+        json!({ "classes": ["10:00 chem 201", "11:00 chem 201"] })
+    );
+
+    let (status_code, body) = request(
+        &db,
+        Method::PUT,
+        "/students/s1/preferences",
+        Some(json!({ "classes": ["10:00 chem 101"] })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_code(&body), INCOMPLETE_BUNDLE as i64);
+}
+
+#[tokio::test]
+async fn build_schedule() {
+    let db = init_db().await;
+
+    let (status_code, body) = request(
+        &db,
+        Method::POST,
+        "/students/s1/schedule",
+        Some(json!({ "classes": ["10:00 chem 201", "10:00 bio 201", "11:00 chem 201"] })),
+    )
+    .await;
+
+    assert_eq!(status_code, StatusCode::OK);
+    assert_eq!(
+        body,
+        json!({ "classes": ["10:00 chem 201", "11:00 chem 201"] })
+    );
+}
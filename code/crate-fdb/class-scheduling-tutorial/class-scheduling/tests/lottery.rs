@@ -0,0 +1,298 @@
+// These tests run the lottery on `MemoryDatabase`, and do not need a
+// FoundationDB cluster.
+
+mod common;
+
+use common::{class, schedule};
+
+use class_scheduling::storage::{KvDatabase, KvTransaction, MemoryDatabase, MemoryTransaction};
+use class_scheduling::{
+    get_seats_left, init, run_lottery, signup, submit_preferences, Class, ClassKey, ClassValue,
+    Student, Timestamp, INCOMPLETE_BUNDLE, MAX_CLASSES,
+};
+
+use fdb::error::FdbResult;
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn student(i: usize) -> Student {
+    Student(format!("s{}", i))
+}
+
+async fn submit(db: &MemoryDatabase, student: Student, class_names: &[&str]) {
+    db.run(|tr| {
+        let student = student.clone();
+
+        let class_names = class_names.iter().map(|c| class(c)).collect();
+
+        async move { submit_preferences(&tr, student, class_names, Timestamp::now()).await }
+    })
+    .await
+    .unwrap();
+}
+
+// More students want the class than it has seats, and there are more
+// of them than are read or written in one transaction.
+#[tokio::test]
+async fn capacity() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    for i in 0..150 {
+        submit(&db, student(i), &["10:00 chem 201"]).await;
+    }
+
+    let results = run_lottery(&db, 42).await.unwrap();
+
+    assert_eq!(results.len(), 100);
+
+    let seats_left = db
+        .run(|tr| async move { get_seats_left(&tr, class("10:00 chem 201")).await })
+        .await
+        .unwrap();
+
+    assert_eq!(seats_left, 0);
+
+    let mut winners = 0;
+
+    for i in 0..150 {
+        match schedule(&db, &student(i)).await.as_slice() {
+            [] => {}
+            [c] if *c == class("10:00 chem 201") => winners += 1,
+            classes => panic!("{:?}", classes),
+        }
+    }
+
+    assert_eq!(winners, 100);
+
+    // The requests are gone once the lottery ran.
+    assert!(run_lottery(&db, 42).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn time_conflicts() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    db.run(
+        |tr| async move { signup(&tr, student(0), class("10:00 bio 201"), Timestamp::now()).await },
+    )
+    .await
+    .unwrap();
+
+    submit(
+        &db,
+        student(0),
+        &["10:00 chem 201", "11:00 chem 201", "11:00 art 201"],
+    )
+    .await;
+
+    run_lottery(&db, 42).await.unwrap();
+
+    // 10:00 is taken already, and so is 11:00 once the student gets
+    // their first choice at that time.
+    assert_eq!(
+        schedule(&db, &student(0)).await,
+        vec![class("10:00 bio 201"), class("11:00 chem 201")]
+    );
+}
+
+#[tokio::test]
+async fn max_classes() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    submit(
+        &db,
+        student(0),
+        &[
+            "2:00 art 201",
+            "3:00 art 201",
+            "4:00 art 201",
+            "5:00 art 201",
+            "6:00 art 201",
+            "7:00 art 201",
+        ],
+    )
+    .await;
+
+    run_lottery(&db, 42).await.unwrap();
+
+    assert_eq!(schedule(&db, &student(0)).await.len(), MAX_CLASSES);
+}
+
+// A class that filled up or a class taken at the same time after the
+// lottery read the data is not assigned.
+#[tokio::test]
+async fn changes_while_running() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    submit(&db, student(0), &["10:00 chem 201"]).await;
+
+    db.run(|tr| async move {
+        tr.set(ClassKey::new(class("10:00 chem 201")), ClassValue::new(0));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    assert!(run_lottery(&db, 42).await.unwrap().is_empty());
+}
+
+// Runs transactions on `MemoryDatabase`, filling up "10:00 chem 201"
+// right before the transaction with number `fill_at`, as if students
+// signed up for it in the meantime.
+struct FillingDatabase {
+    db: MemoryDatabase,
+    runs: AtomicUsize,
+    fill_at: usize,
+}
+
+impl KvDatabase for FillingDatabase {
+    type Transaction = MemoryTransaction;
+
+    async fn run<T, F, Fut>(&self, f: F) -> FdbResult<T>
+    where
+        T: Send,
+        F: FnMut(MemoryTransaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send,
+    {
+        if self.runs.fetch_add(1, Ordering::SeqCst) == self.fill_at {
+            self.db
+                .run(|tr| async move {
+                    tr.set(ClassKey::new(class("10:00 chem 201")), ClassValue::new(0));
+
+                    Ok(())
+                })
+                .await?;
+        }
+
+        self.db.run(f).await
+    }
+}
+
+async fn fallback_db(fill_at: usize) -> FillingDatabase {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    submit(&db, student(0), &["10:00 chem 201", "10:00 bio 201"]).await;
+
+    FillingDatabase {
+        db,
+        runs: AtomicUsize::new(0),
+        fill_at,
+    }
+}
+
+// A student whose seat is gone by the time the results are written
+// gets their next preference instead.
+#[tokio::test]
+async fn changes_while_writing() {
+    // The results are written in the last transaction of the lottery.
+    let counting_db = fallback_db(usize::MAX).await;
+
+    run_lottery(&counting_db, 42).await.unwrap();
+
+    let db = fallback_db(counting_db.runs.load(Ordering::SeqCst) - 1).await;
+
+    assert_eq!(
+        run_lottery(&db, 42).await.unwrap(),
+        vec![(student(0), class("10:00 bio 201"))]
+    );
+
+    assert_eq!(
+        schedule(&db.db, &student(0)).await,
+        vec![class("10:00 bio 201")]
+    );
+}
+
+// Two students want the same four classes, which have one seat each,
+// so they take turns for two rounds.
+async fn two_rounds(seed: u64) -> BTreeMap<Student, Vec<Class>> {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let class_names = [
+        "2:00 art 201",
+        "3:00 art 201",
+        "4:00 art 201",
+        "5:00 art 201",
+    ];
+
+    db.run(|tr| async move {
+        for class_name in class_names {
+            tr.set(ClassKey::new(class(class_name)), ClassValue::new(1));
+        }
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    for i in 0..2 {
+        submit(&db, student(i), &class_names).await;
+    }
+
+    run_lottery(&db, seed).await.unwrap().into_iter().fold(
+        BTreeMap::new(),
+        |mut won, (student, class_name)| {
+            won.entry(student).or_insert_with(Vec::new).push(class_name);
+            won
+        },
+    )
+}
+
+// The same seed gives the same result. The order of the students is
+// shuffled again for every round, so the student who picks first in
+// the first round, and gets "2:00 art 201", does not always pick first
+// in the second round and get "4:00 art 201" too.
+#[tokio::test]
+async fn seeded_rounds() {
+    assert_eq!(two_rounds(7).await, two_rounds(7).await);
+
+    let mut second_round_lost = false;
+
+    for seed in 0..20 {
+        let won = two_rounds(seed).await;
+
+        assert_eq!(won.values().map(Vec::len).collect::<Vec<_>>(), vec![2, 2]);
+
+        second_round_lost |= won.values().any(|classes| {
+            classes.contains(&class("2:00 art 201")) && classes.contains(&class("5:00 art 201"))
+        });
+    }
+
+    assert!(second_round_lost);
+}
+
+#[tokio::test]
+async fn bundles_refused() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let err = db
+        .run(|tr| async move {
+            submit_preferences(
+                &tr,
+                student(0),
+                vec![class("10:00 chem 101")],
+                Timestamp::now(),
+            )
+            .await
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), INCOMPLETE_BUNDLE);
+}
@@ -0,0 +1,335 @@
+// These tests run the scheduling logic on `MemoryDatabase`, and do not
+// need a FoundationDB cluster.
+
+mod common;
+
+use common::schedule;
+
+use class_scheduling::sim::{run_sim, Profile, SimConfig};
+use class_scheduling::storage::{
+    KvDatabase, KvTransaction, MemoryDatabase, COMMIT_UNKNOWN_RESULT, NOT_COMMITTED,
+};
+use class_scheduling::{
+    dropout, get_seats_left, get_student_schedule, init, signup, switch_classes, Class, ClassKey,
+    NewClass, OldClass, Student, Timestamp, ALREADY_SIGNED_UP, NOT_SIGNED_UP, NO_SUCH_CLASS,
+};
+
+use fdb::error::{FdbError, FdbResult};
+use fdb::Key;
+
+use bytes::Bytes;
+
+use std::future;
+
+fn class() -> Class {
+    Class("10:00 chem intro".to_string())
+}
+
+#[tokio::test]
+async fn signup_and_dropout() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    db.run(|tr| {
+        let student = student.clone();
+        async move { signup(&tr, student, class(), Timestamp::now()).await }
+    })
+    .await
+    .unwrap();
+
+    let schedule = db
+        .run(|tr| {
+            let student = student.clone();
+            async move { get_student_schedule(&tr, student).await }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(schedule, vec![class()]);
+
+    db.run(|tr| {
+        let student = student.clone();
+        async move { dropout(&tr, student, class(), Timestamp::now()).await }
+    })
+    .await
+    .unwrap();
+
+    let schedule = db
+        .run(|tr| {
+            let student = student.clone();
+            async move { get_student_schedule(&tr, student).await }
+        })
+        .await
+        .unwrap();
+
+    assert!(schedule.is_empty());
+}
+
+async fn switch(
+    db: &MemoryDatabase,
+    student: &Student,
+    old_class: &str,
+    new_class: &str,
+) -> FdbResult<()> {
+    db.run(|tr| {
+        let student = student.clone();
+
+        let (old_class, new_class) = (
+            OldClass(Class(old_class.to_string())),
+            NewClass(Class(new_class.to_string())),
+        );
+
+        async move { switch_classes(&tr, student, old_class, new_class, Timestamp::now()).await }
+    })
+    .await
+}
+
+// A switch is a dropout followed by a signup, and when the signup
+// fails, the dropout does not happen either. Running a switch again
+// after it was committed changes nothing.
+#[tokio::test]
+async fn switch_all_or_nothing() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    for class_name in ["10:00 chem intro", "11:00 chem intro"] {
+        db.run(|tr| {
+            let student = student.clone();
+            async move {
+                signup(
+                    &tr,
+                    student,
+                    Class(class_name.to_string()),
+                    Timestamp::now(),
+                )
+                .await
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    let attended = vec![
+        Class("10:00 chem intro".to_string()),
+        Class("11:00 chem intro".to_string()),
+    ];
+
+    // Both classes are attended.
+    let err = switch(&db, &student, "10:00 chem intro", "11:00 chem intro")
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), ALREADY_SIGNED_UP);
+
+    // Neither class is attended.
+    let err = switch(&db, &student, "12:00 chem intro", "13:00 chem intro")
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), NOT_SIGNED_UP);
+
+    assert_eq!(schedule(&db, &student).await, attended);
+
+    let seats_left = db
+        .run(|tr| async move { get_seats_left(&tr, class()).await })
+        .await
+        .unwrap();
+
+    assert_eq!(seats_left, 99);
+
+    // The second time, the old class is not attended, and the new one
+    // is.
+    for _ in 0..2 {
+        switch(&db, &student, "10:00 chem intro", "12:00 chem intro")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            schedule(&db, &student).await,
+            vec![
+                Class("11:00 chem intro".to_string()),
+                Class("12:00 chem intro".to_string()),
+            ]
+        );
+    }
+
+    let seats_left = db
+        .run(|tr| async move { get_seats_left(&tr, class()).await })
+        .await
+        .unwrap();
+
+    assert_eq!(seats_left, 100);
+}
+
+// Class names typed by a user reach `signup` and `switch_classes`, and a
+// class can be deleted while a student attends it.
+#[tokio::test]
+async fn no_such_class() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let student = Student("s0".to_string());
+
+    let no_such_class = Class("10:00 chem nonsense".to_string());
+
+    let err = db
+        .run(|tr| {
+            let (student, no_such_class) = (student.clone(), no_such_class.clone());
+            async move { signup(&tr, student, no_such_class, Timestamp::now()).await }
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), NO_SUCH_CLASS);
+
+    db.run(|tr| {
+        let student = student.clone();
+        async move { signup(&tr, student, class(), Timestamp::now()).await }
+    })
+    .await
+    .unwrap();
+
+    let err = db
+        .run(|tr| {
+            let (student, no_such_class) = (student.clone(), no_such_class.clone());
+            async move {
+                switch_classes(
+                    &tr,
+                    student,
+                    OldClass(class()),
+                    NewClass(no_such_class),
+                    Timestamp::now(),
+                )
+                .await
+            }
+        })
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), NO_SUCH_CLASS);
+
+    db.run(|tr| async move {
+        tr.clear(ClassKey::new(class()));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    // Nothing was changed by the failed switch.
+    let schedule = db
+        .run(|tr| {
+            let student = student.clone();
+            async move { get_student_schedule(&tr, student).await }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(schedule, vec![class()]);
+
+    // A student can still drop a class that was deleted, and no class
+    // is created for the seat given back.
+    db.run(|tr| {
+        let student = student.clone();
+        async move { dropout(&tr, student, class(), Timestamp::now()).await }
+    })
+    .await
+    .unwrap();
+
+    let (schedule, class_value) = db
+        .run(|tr| {
+            let student = student.clone();
+            async move {
+                Ok((
+                    get_student_schedule(&tr, student).await?,
+                    tr.get(ClassKey::new(class())).await?,
+                ))
+            }
+        })
+        .await
+        .unwrap();
+
+    assert!(schedule.is_empty());
+    assert_eq!(class_value, None);
+}
+
+#[tokio::test]
+async fn conflicting_commit() {
+    let db = MemoryDatabase::new();
+
+    let key = Key::from(Bytes::from_static(b"counter"));
+
+    let tr1 = db.create_transaction();
+    let tr2 = db.create_transaction();
+
+    // Both transactions read the key before either of them commits.
+    assert_eq!(tr1.get(key.clone()).await.unwrap(), None);
+    assert_eq!(tr2.get(key.clone()).await.unwrap(), None);
+
+    tr1.set(key.clone(), Bytes::from_static(b"1"));
+    tr2.set(key.clone(), Bytes::from_static(b"2"));
+
+    tr1.commit().unwrap();
+
+    assert_eq!(tr2.commit().unwrap_err().code(), NOT_COMMITTED);
+
+    let tr3 = db.create_transaction();
+
+    assert_eq!(
+        tr3.get(key).await.unwrap().map(Bytes::from),
+        Some(Bytes::from_static(b"1"))
+    );
+}
+
+// Like `FdbDatabase::run`, `run` tries a transaction again when it is
+// not known whether it was committed.
+#[tokio::test]
+async fn commit_unknown_result() {
+    let db = MemoryDatabase::new();
+
+    let mut attempts = 0;
+
+    db.run(|_| {
+        attempts += 1;
+
+        async move {
+            if attempts == 1 {
+                Err(FdbError::new(COMMIT_UNKNOWN_RESULT))
+            } else {
+                Ok(())
+            }
+        }
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(attempts, 2);
+}
+
+// Every committed transaction of this simulation is also reported as
+// `commit_unknown_result` once in a while, and run again.
+#[tokio::test]
+async fn simulation() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let config = SimConfig {
+        students: 10,
+        ops_per_student: 20,
+        seed: 42,
+        profile: Profile::default(),
+        faults: "0,0,0.2".parse().unwrap(),
+        concurrency: None,
+        record: None,
+    };
+
+    run_sim(db, config, future::pending()).await.unwrap();
+}
@@ -0,0 +1,603 @@
+// Runs random sequences of sign ups, drops and switches, of single
+// classes and of bundles, with registration windows opening and
+// closing, on `MemoryDatabase` and on a simple reference model, and
+// checks that both agree on the outcome of every operation and on the
+// final state. These tests do not need a FoundationDB cluster.
+
+use class_scheduling::storage::{KvDatabase, MemoryDatabase};
+use class_scheduling::{
+    available_classes, dropout, dropout_bundle, get_class_bundle, get_class_roster, get_seats_left,
+    get_student_schedule, init, set_priority_group, set_registration_window, signup, signup_bundle,
+    switch_classes, Class, NewClass, OldClass, Phase, PriorityGroup, Student, Timestamp,
+    ALREADY_SIGNED_UP, CLASS_CAPACITY, INCOMPLETE_BUNDLE, MAX_CLASSES, NOT_SIGNED_UP,
+    NO_REMAINING_SEATS, PRIORITY_REGISTRATION_ONLY, REGISTRATION_CLOSED, TIME_CONFLICT,
+    TOO_MANY_CLASSES,
+};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+const CASES: u64 = 32;
+
+const OPS_PER_CASE: usize = 1_000;
+
+// Operations run at, and registration windows start and end at, a
+// timestamp below this one.
+const TIMESTAMPS: i64 = 10;
+
+#[derive(Clone, Debug)]
+enum Op {
+    Signup(Student, Class, Timestamp),
+    SignupBundle(Student, Vec<Class>, Timestamp),
+    Dropout(Student, Class, Timestamp),
+    DropoutBundle(Student, Class, Timestamp),
+    Switch(Student, Class, Class, Timestamp),
+    SetRegistrationWindow(Phase, Timestamp, Timestamp),
+    SetPriorityGroup(Student, PriorityGroup),
+}
+
+// Class names are of the form "time type level".
+fn class_time(class_name: &Class) -> &str {
+    // Safety: `split` always returns at least one item.
+    class_name.0.split(' ').next().unwrap()
+}
+
+// What the scheduler is meant to do, without any keys, values or
+// transactions.
+#[derive(Clone, Debug, Default)]
+struct Model {
+    seats_left: BTreeMap<Class, u8>,
+    schedules: BTreeMap<Student, BTreeSet<Class>>,
+    // The classes of the bundle of every class that is part of one.
+    bundles: BTreeMap<Class, Vec<Class>>,
+    registration_windows: Vec<(Phase, Timestamp, Timestamp)>,
+    priority_groups: BTreeMap<Student, PriorityGroup>,
+}
+
+impl Model {
+    fn new(class_names: &[Class], bundles: BTreeMap<Class, Vec<Class>>) -> Model {
+        Model {
+            seats_left: class_names
+                .iter()
+                .map(|class_name| (class_name.clone(), CLASS_CAPACITY))
+                .collect(),
+            bundles,
+            ..Model::default()
+        }
+    }
+
+    fn attends(&self, student: &Student, class_name: &Class) -> bool {
+        self.schedules
+            .get(student)
+            .map(|classes| classes.contains(class_name))
+            .unwrap_or(false)
+    }
+
+    fn class_count(&self, student: &Student) -> usize {
+        self.schedules
+            .get(student)
+            .map(|classes| classes.len())
+            .unwrap_or(0)
+    }
+
+    // Registration is open until the first registration window is set
+    // up, and then only during the open and add/drop windows, and the
+    // window of the student's priority group.
+    fn check_registration_open(&self, student: &Student, now: Timestamp) -> Result<(), i32> {
+        if self.registration_windows.is_empty() {
+            return Ok(());
+        }
+
+        let mut priority_phase = false;
+
+        for (phase, start, end) in &self.registration_windows {
+            if !(*start <= now && now < *end) {
+                continue;
+            }
+
+            match phase {
+                Phase::Open | Phase::AddDrop => return Ok(()),
+                Phase::Lottery => {}
+                Phase::Priority(priority_group) => {
+                    priority_phase = true;
+
+                    if self.priority_groups.get(student) == Some(priority_group) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+
+        if priority_phase {
+            Err(PRIORITY_REGISTRATION_ONLY)
+        } else {
+            Err(REGISTRATION_CLOSED)
+        }
+    }
+
+    fn take_seat(&mut self, student: &Student, class_name: &Class) -> Result<(), i32> {
+        if self.attends(student, class_name) {
+            return Err(ALREADY_SIGNED_UP);
+        }
+
+        if self.seats_left[class_name] == 0 {
+            return Err(NO_REMAINING_SEATS);
+        }
+
+        let classes = self.schedules.entry(student.clone()).or_default();
+
+        if classes.len() == MAX_CLASSES {
+            return Err(TOO_MANY_CLASSES);
+        }
+
+        classes.insert(class_name.clone());
+
+        // Safety: `class_name` is one of our classes.
+        *self.seats_left.get_mut(class_name).unwrap() -= 1;
+
+        Ok(())
+    }
+
+    fn give_up_seat(&mut self, student: &Student, class_name: &Class) {
+        if self.attends(student, class_name) {
+            // Safety: `student` attends `class_name`.
+            self.schedules.get_mut(student).unwrap().remove(class_name);
+
+            // Safety: `class_name` is one of our classes.
+            *self.seats_left.get_mut(class_name).unwrap() += 1;
+        }
+    }
+
+    fn signup(&mut self, student: &Student, class_name: &Class, now: Timestamp) -> Result<(), i32> {
+        self.check_registration_open(student, now)?;
+
+        if self.bundles.contains_key(class_name) {
+            return Err(INCOMPLETE_BUNDLE);
+        }
+
+        self.take_seat(student, class_name)
+    }
+
+    // Either all classes of the bundle are signed up for, or none.
+    fn signup_bundle(
+        &mut self,
+        student: &Student,
+        class_names: &[Class],
+        now: Timestamp,
+    ) -> Result<(), i32> {
+        self.check_registration_open(student, now)?;
+
+        let bundle_classes = class_names
+            .first()
+            .and_then(|class_name| self.bundles.get(class_name))
+            .ok_or(INCOMPLETE_BUNDLE)?;
+
+        if bundle_classes.len() != class_names.len()
+            || !bundle_classes.iter().all(|c| class_names.contains(c))
+        {
+            return Err(INCOMPLETE_BUNDLE);
+        }
+
+        if self.class_count(student) + class_names.len() > MAX_CLASSES {
+            return Err(TOO_MANY_CLASSES);
+        }
+
+        for class_name in class_names {
+            let conflict = self.schedules.get(student).map(|classes| {
+                classes
+                    .iter()
+                    .any(|c| c != class_name && class_time(c) == class_time(class_name))
+            });
+
+            if conflict == Some(true) {
+                return Err(TIME_CONFLICT);
+            }
+        }
+
+        let mut model = self.clone();
+
+        for class_name in class_names {
+            model.take_seat(student, class_name)?;
+        }
+
+        *self = model;
+
+        Ok(())
+    }
+
+    fn dropout(
+        &mut self,
+        student: &Student,
+        class_name: &Class,
+        now: Timestamp,
+    ) -> Result<(), i32> {
+        self.check_registration_open(student, now)?;
+
+        if self.bundles.contains_key(class_name) {
+            return Err(INCOMPLETE_BUNDLE);
+        }
+
+        self.give_up_seat(student, class_name);
+
+        Ok(())
+    }
+
+    fn dropout_bundle(
+        &mut self,
+        student: &Student,
+        class_name: &Class,
+        now: Timestamp,
+    ) -> Result<(), i32> {
+        self.check_registration_open(student, now)?;
+
+        let bundle_classes = self
+            .bundles
+            .get(class_name)
+            .cloned()
+            .ok_or(INCOMPLETE_BUNDLE)?;
+
+        for bundle_class_name in &bundle_classes {
+            self.give_up_seat(student, bundle_class_name);
+        }
+
+        Ok(())
+    }
+
+    // Either both the dropout and the signup happen, or neither. A
+    // switch that already happened is not an error.
+    fn switch_classes(
+        &mut self,
+        student: &Student,
+        old_class: &Class,
+        new_class: &Class,
+        now: Timestamp,
+    ) -> Result<(), i32> {
+        self.check_registration_open(student, now)?;
+
+        if !self.attends(student, old_class) {
+            if self.attends(student, new_class) {
+                return Ok(());
+            }
+
+            return Err(NOT_SIGNED_UP);
+        }
+
+        let mut model = self.clone();
+
+        model.dropout(student, old_class, now)?;
+        model.signup(student, new_class, now)?;
+
+        *self = model;
+
+        Ok(())
+    }
+
+    fn apply(&mut self, op: &Op) -> Result<(), i32> {
+        match op {
+            Op::Signup(student, class_name, now) => self.signup(student, class_name, *now),
+            Op::SignupBundle(student, class_names, now) => {
+                self.signup_bundle(student, class_names, *now)
+            }
+            Op::Dropout(student, class_name, now) => self.dropout(student, class_name, *now),
+            Op::DropoutBundle(student, class_name, now) => {
+                self.dropout_bundle(student, class_name, *now)
+            }
+            Op::Switch(student, old_class, new_class, now) => {
+                self.switch_classes(student, old_class, new_class, *now)
+            }
+            Op::SetRegistrationWindow(phase, start, end) => {
+                self.registration_windows.retain(|(p, _, _)| p != phase);
+
+                self.registration_windows
+                    .push((phase.clone(), *start, *end));
+
+                Ok(())
+            }
+            Op::SetPriorityGroup(student, priority_group) => {
+                self.priority_groups
+                    .insert(student.clone(), priority_group.clone());
+
+                Ok(())
+            }
+        }
+    }
+}
+
+async fn apply(db: &MemoryDatabase, op: &Op) -> Result<(), i32> {
+    let res = match op.clone() {
+        Op::Signup(student, class_name, now) => {
+            db.run(|tr| {
+                let (student, class_name) = (student.clone(), class_name.clone());
+                async move { signup(&tr, student, class_name, now).await }
+            })
+            .await
+        }
+        Op::SignupBundle(student, class_names, now) => {
+            db.run(|tr| {
+                let (student, class_names) = (student.clone(), class_names.clone());
+                async move { signup_bundle(&tr, student, class_names, now).await }
+            })
+            .await
+        }
+        Op::Dropout(student, class_name, now) => {
+            db.run(|tr| {
+                let (student, class_name) = (student.clone(), class_name.clone());
+                async move { dropout(&tr, student, class_name, now).await }
+            })
+            .await
+        }
+        Op::DropoutBundle(student, class_name, now) => {
+            db.run(|tr| {
+                let (student, class_name) = (student.clone(), class_name.clone());
+                async move { dropout_bundle(&tr, student, class_name, now).await }
+            })
+            .await
+        }
+        Op::Switch(student, old_class, new_class, now) => {
+            db.run(|tr| {
+                let (student, old_class, new_class) =
+                    (student.clone(), old_class.clone(), new_class.clone());
+                async move {
+                    switch_classes(&tr, student, OldClass(old_class), NewClass(new_class), now)
+                        .await
+                }
+            })
+            .await
+        }
+        Op::SetRegistrationWindow(phase, start, end) => {
+            db.run(|tr| {
+                let phase = phase.clone();
+                async move {
+                    set_registration_window(&tr, phase, start, end);
+
+                    Ok(())
+                }
+            })
+            .await
+        }
+        Op::SetPriorityGroup(student, priority_group) => {
+            db.run(|tr| {
+                let (student, priority_group) = (student.clone(), priority_group.clone());
+                async move {
+                    set_priority_group(&tr, student, priority_group);
+
+                    Ok(())
+                }
+            })
+            .await
+        }
+    };
+
+    res.map_err(|err| err.code())
+}
+
+// The classes of the bundle of every class that is part of one.
+async fn read_bundles(db: &MemoryDatabase, class_names: &[Class]) -> BTreeMap<Class, Vec<Class>> {
+    let bundle_names = db
+        .run(|tr| async move {
+            let mut bundle_names = Vec::new();
+
+            for class_name in class_names {
+                if let Some(bundle) = get_class_bundle(&tr, class_name.clone()).await? {
+                    bundle_names.push((class_name.clone(), bundle.0));
+                }
+            }
+
+            Ok(bundle_names)
+        })
+        .await
+        .unwrap();
+
+    let mut bundle_classes = BTreeMap::<String, Vec<Class>>::new();
+
+    for (class_name, bundle_name) in &bundle_names {
+        bundle_classes
+            .entry(bundle_name.clone())
+            .or_default()
+            .push(class_name.clone());
+    }
+
+    bundle_names
+        .into_iter()
+        .map(|(class_name, bundle_name)| (class_name, bundle_classes[&bundle_name].clone()))
+        .collect()
+}
+
+fn random_timestamp(rng: &mut StdRng) -> Timestamp {
+    Timestamp(rng.gen_range(0..TIMESTAMPS))
+}
+
+fn random_phase(rng: &mut StdRng) -> Phase {
+    match rng.gen_range(0..5) {
+        0 => Phase::Open,
+        1 => Phase::AddDrop,
+        2 => Phase::Lottery,
+        3 => Phase::Priority(PriorityGroup("seniors".to_string())),
+        _ => Phase::Priority(PriorityGroup("juniors".to_string())),
+    }
+}
+
+// Classes at a couple of times, along with the other classes of their
+// bundles, so that some bundles conflict with the classes students
+// have, and a varying number of students, so that some cases sign
+// students up for too many classes.
+fn random_ops(
+    rng: &mut StdRng,
+    all_classes: &[Class],
+    all_bundles: &BTreeMap<Class, Vec<Class>>,
+) -> (Vec<Class>, Vec<Student>, Vec<Op>) {
+    let times = all_classes
+        .choose_multiple(rng, 2)
+        .map(|class_name| class_time(class_name).to_string())
+        .collect::<Vec<String>>();
+
+    let class_count = rng.gen_range(2..=MAX_CLASSES + 2);
+
+    let mut class_names = all_classes
+        .iter()
+        .filter(|class_name| times.iter().any(|time| time == class_time(class_name)))
+        .cloned()
+        .collect::<Vec<Class>>()
+        .choose_multiple(rng, class_count)
+        .cloned()
+        .collect::<BTreeSet<Class>>();
+
+    for class_name in class_names.clone() {
+        if let Some(bundle_classes) = all_bundles.get(&class_name) {
+            class_names.extend(bundle_classes.iter().cloned());
+        }
+    }
+
+    let class_names = class_names.into_iter().collect::<Vec<Class>>();
+
+    let students = (0..rng.gen_range(1..=150))
+        .map(|i| Student(format!("s{}", i)))
+        .collect::<Vec<Student>>();
+
+    // Half of the cases leave registration open throughout.
+    let registration_windows = rng.gen_bool(0.5);
+
+    let ops = (0..OPS_PER_CASE)
+        .map(|_| {
+            // Safety: There is at least one student and two classes.
+            let student = students.choose(rng).unwrap().clone();
+            let class_name = class_names.choose(rng).unwrap().clone();
+
+            let now = random_timestamp(rng);
+
+            match rng.gen_range(0..40) {
+                0..=11 => Op::Signup(student, class_name, now),
+                12..=17 => {
+                    let mut bundle = all_bundles
+                        .get(&class_name)
+                        .cloned()
+                        .unwrap_or_else(|| vec![class_name.clone()]);
+
+                    bundle.shuffle(rng);
+
+                    // Now and then, leave out a class of the bundle.
+                    if rng.gen_range(0..4) == 0 {
+                        bundle.pop();
+                    }
+
+                    Op::SignupBundle(student, bundle, now)
+                }
+                18..=25 => Op::Dropout(student, class_name, now),
+                26..=29 => Op::DropoutBundle(student, class_name, now),
+                30..=37 => Op::Switch(
+                    student,
+                    class_name,
+                    class_names.choose(rng).unwrap().clone(),
+                    now,
+                ),
+                38..=39 if !registration_windows => Op::Signup(student, class_name, now),
+                38 => {
+                    let start = random_timestamp(rng);
+                    let end = Timestamp(start.0 + rng.gen_range(1..=TIMESTAMPS));
+
+                    Op::SetRegistrationWindow(random_phase(rng), start, end)
+                }
+                _ => match random_phase(rng) {
+                    Phase::Priority(priority_group) => {
+                        Op::SetPriorityGroup(student, priority_group)
+                    }
+                    _ => Op::SetPriorityGroup(student, PriorityGroup("juniors".to_string())),
+                },
+            }
+        })
+        .collect();
+
+    (class_names, students, ops)
+}
+
+#[tokio::test]
+async fn scheduler_matches_model() {
+    for seed in 0..CASES {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let db = MemoryDatabase::new();
+
+        init(&db).await.unwrap();
+
+        let all_classes = db
+            .run(|tr| async move { available_classes(&tr).await })
+            .await
+            .unwrap();
+
+        let all_bundles = read_bundles(&db, &all_classes).await;
+
+        let (class_names, students, ops) = random_ops(&mut rng, &all_classes, &all_bundles);
+
+        let bundles = all_bundles
+            .into_iter()
+            .filter(|(class_name, _)| class_names.contains(class_name))
+            .collect();
+
+        let mut model = Model::new(&class_names, bundles);
+
+        for (i, op) in ops.iter().enumerate() {
+            let expected = model.apply(op);
+
+            assert_eq!(
+                apply(&db, op).await,
+                expected,
+                "seed {}, op {}: {:?}",
+                seed,
+                i,
+                op
+            );
+        }
+
+        for class_name in &class_names {
+            let seats_left = db
+                .run(|tr| async move { get_seats_left(&tr, class_name.clone()).await })
+                .await
+                .unwrap();
+
+            assert_eq!(
+                seats_left, model.seats_left[class_name],
+                "seed {}: seats left in {:?}",
+                seed, class_name
+            );
+
+            let roster = db
+                .run(|tr| async move { get_class_roster(&tr, class_name.clone()).await })
+                .await
+                .unwrap()
+                .into_iter()
+                .collect::<BTreeSet<Student>>();
+
+            let expected = students
+                .iter()
+                .filter(|student| model.attends(student, class_name))
+                .cloned()
+                .collect::<BTreeSet<Student>>();
+
+            assert_eq!(
+                roster, expected,
+                "seed {}: roster of {:?}",
+                seed, class_name
+            );
+        }
+
+        for student in &students {
+            let schedule = db
+                .run(|tr| async move { get_student_schedule(&tr, student.clone()).await })
+                .await
+                .unwrap()
+                .into_iter()
+                .collect::<BTreeSet<Class>>();
+
+            let expected = model.schedules.get(student).cloned().unwrap_or_default();
+
+            assert_eq!(
+                schedule, expected,
+                "seed {}: schedule of {:?}",
+                seed, student
+            );
+        }
+    }
+}
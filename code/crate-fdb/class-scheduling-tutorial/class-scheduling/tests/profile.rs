@@ -0,0 +1,107 @@
+// Checks how the simulation profiles pick classes, with seeded random
+// number generators. They do not need a FoundationDB cluster.
+
+use class_scheduling::sim::{ClassChoice, Profile};
+use class_scheduling::Class;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+const PICKS: usize = 10_000;
+
+fn classes() -> Vec<Class> {
+    (0..100)
+        .map(|i| Class(format!("{}:00 chem {}", i % 24, i)))
+        .collect()
+}
+
+// How many times each class is picked, in the order of `classes`.
+fn picks(class_choice: &ClassChoice, seed: u64) -> Vec<usize> {
+    let classes = classes();
+
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut picks = vec![0; classes.len()];
+
+    for _ in 0..PICKS {
+        // Safety: There are classes to pick from.
+        let class = class_choice.choose(&classes, &mut rng).unwrap();
+
+        // Safety: The class is one of `classes`.
+        picks[classes.iter().position(|c| c == class).unwrap()] += 1;
+    }
+
+    picks
+}
+
+#[test]
+fn hot_spot() {
+    let class_choice = Profile::named("hot-spot").unwrap().class_choice;
+
+    let picks = picks(&class_choice, 42);
+
+    // 90% of the picks go to the 10 hot classes, and the rest are
+    // spread over all 100 classes, so about 91% in all.
+    let hot_picks = picks[..10].iter().sum::<usize>();
+
+    assert!(
+        (8_800..9_400).contains(&hot_picks),
+        "{} of {} picks",
+        hot_picks,
+        PICKS
+    );
+
+    // Every hot class is picked more often than any cold class.
+    let coldest_hot = picks[..10].iter().min().unwrap();
+    let hottest_cold = picks[10..].iter().max().unwrap();
+
+    assert!(coldest_hot > hottest_cold, "{:?}", picks);
+}
+
+#[test]
+fn zipfian() {
+    let class_choice = Profile::named("zipfian").unwrap().class_choice;
+
+    let picks = picks(&class_choice, 42);
+
+    // The first class is picked the most, and the first ten classes
+    // together get more picks than the other ninety.
+    assert_eq!(picks.iter().max(), Some(&picks[0]), "{:?}", picks);
+
+    assert!(picks[..10].iter().sum::<usize>() > picks[10..].iter().sum::<usize>());
+}
+
+#[test]
+fn uniform() {
+    let picks = picks(&ClassChoice::Uniform, 42);
+
+    // About 100 picks each.
+    assert!(picks.iter().all(|p| (50..150).contains(p)), "{:?}", picks);
+}
+
+#[test]
+fn same_seed_same_picks() {
+    for class_choice in [
+        ClassChoice::Uniform,
+        ClassChoice::Zipfian { exponent: 1.2 },
+        ClassChoice::HotSpot {
+            classes: 10,
+            fraction: 0.9,
+        },
+    ] {
+        assert_eq!(picks(&class_choice, 7), picks(&class_choice, 7));
+    }
+}
+
+#[test]
+fn no_classes() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    assert_eq!(
+        Profile::named("hot-spot")
+            .unwrap()
+            .class_choice
+            .choose(&[], &mut rng),
+        None
+    );
+}
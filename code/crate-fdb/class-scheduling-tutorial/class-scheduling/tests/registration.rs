@@ -0,0 +1,133 @@
+// These tests set up registration windows on `MemoryDatabase`, and do
+// not need a FoundationDB cluster. The time is passed to every
+// operation, so the tests step through the phases without waiting.
+
+mod common;
+
+use common::student;
+
+use class_scheduling::storage::{KvDatabase, MemoryDatabase};
+use class_scheduling::{
+    dropout, init, set_priority_group, set_registration_window, signup, submit_preferences, Class,
+    Phase, PriorityGroup, Timestamp, LOTTERY_CLOSED, PRIORITY_REGISTRATION_ONLY,
+    REGISTRATION_CLOSED,
+};
+
+use fdb::error::FdbResult;
+
+fn class() -> Class {
+    Class("10:00 chem 201".to_string())
+}
+
+// Seniors register in [100, 200), everyone in [200, 300), add/drop is
+// in [300, 400), and the lottery in [500, 600).
+async fn init_windows() -> MemoryDatabase {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    db.run(|tr| async move {
+        let seniors = PriorityGroup("seniors".to_string());
+
+        set_registration_window(
+            &tr,
+            Phase::Priority(seniors.clone()),
+            Timestamp(100),
+            Timestamp(200),
+        );
+        set_registration_window(&tr, Phase::Open, Timestamp(200), Timestamp(300));
+        set_registration_window(&tr, Phase::AddDrop, Timestamp(300), Timestamp(400));
+        set_registration_window(&tr, Phase::Lottery, Timestamp(500), Timestamp(600));
+
+        set_priority_group(&tr, student("senior"), seniors);
+        set_priority_group(&tr, student("junior"), PriorityGroup("juniors".to_string()));
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    db
+}
+
+async fn signup_at(db: &MemoryDatabase, student_inner: &str, now: i64) -> FdbResult<()> {
+    db.run(|tr| async move { signup(&tr, student(student_inner), class(), Timestamp(now)).await })
+        .await
+}
+
+async fn dropout_at(db: &MemoryDatabase, student_inner: &str, now: i64) -> FdbResult<()> {
+    db.run(|tr| async move { dropout(&tr, student(student_inner), class(), Timestamp(now)).await })
+        .await
+}
+
+#[tokio::test]
+async fn closed() {
+    let db = init_windows().await;
+
+    for now in [0, 99, 400, 450, 600] {
+        for student_inner in ["senior", "junior", "other"] {
+            let err = signup_at(&db, student_inner, now).await.unwrap_err();
+
+            assert_eq!(err.code(), REGISTRATION_CLOSED);
+        }
+    }
+
+    let err = dropout_at(&db, "senior", 450).await.unwrap_err();
+
+    assert_eq!(err.code(), REGISTRATION_CLOSED);
+
+    // Students only submit their preferences during the lottery.
+    let err = signup_at(&db, "other", 550).await.unwrap_err();
+
+    assert_eq!(err.code(), REGISTRATION_CLOSED);
+}
+
+#[tokio::test]
+async fn priority_only() {
+    let db = init_windows().await;
+
+    signup_at(&db, "senior", 100).await.unwrap();
+
+    // Students of another priority group, or of none, wait until
+    // registration opens for everyone.
+    for student_inner in ["junior", "other"] {
+        let err = signup_at(&db, student_inner, 199).await.unwrap_err();
+
+        assert_eq!(err.code(), PRIORITY_REGISTRATION_ONLY);
+    }
+
+    dropout_at(&db, "senior", 150).await.unwrap();
+}
+
+#[tokio::test]
+async fn open() {
+    let db = init_windows().await;
+
+    for student_inner in ["senior", "junior", "other"] {
+        signup_at(&db, student_inner, 200).await.unwrap();
+    }
+
+    // Add/drop is open for everyone too.
+    dropout_at(&db, "junior", 399).await.unwrap();
+
+    signup_at(&db, "junior", 300).await.unwrap();
+}
+
+#[tokio::test]
+async fn lottery() {
+    let db = init_windows().await;
+
+    for (now, res) in [
+        (450, Err(LOTTERY_CLOSED)),
+        (500, Ok(())),
+        (600, Err(LOTTERY_CLOSED)),
+    ] {
+        let submitted = db
+            .run(|tr| async move {
+                submit_preferences(&tr, student("other"), vec![class()], Timestamp(now)).await
+            })
+            .await;
+
+        assert_eq!(submitted.map_err(|err| err.code()), res);
+    }
+}
@@ -0,0 +1,58 @@
+// These tests start `fdbserver`, which needs to be installed. Run them
+// with `cargo test -- --ignored`. Every test sets up the classes with
+// `init`, so each starts a cluster of its own.
+
+use class_scheduling::sim::{read_trace, replay, run_sim, Faults, Profile, SimConfig};
+
+use test_support::TestCluster;
+
+use std::env;
+use std::future;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::process;
+
+#[tokio::test]
+#[ignore = "needs fdbserver"]
+async fn recorded_trace() {
+    let cluster = TestCluster::start().unwrap();
+
+    let db = cluster.database();
+
+    let entries = read_trace(Path::new("tests/traces/switch_and_drop.jsonl")).unwrap();
+
+    assert_eq!(replay(db, entries).await.unwrap(), 6);
+}
+
+#[tokio::test]
+#[ignore = "needs fdbserver"]
+async fn simulation_replays() {
+    let cluster = TestCluster::start().unwrap();
+
+    let db = cluster.database();
+
+    class_scheduling::init(&db).await.unwrap();
+
+    let trace = env::temp_dir().join(format!("class-scheduling-{}.jsonl", process::id()));
+
+    // One student at a time, so that replaying the operations in the
+    // order they finished runs them exactly as they ran.
+    let config = SimConfig {
+        students: 10,
+        ops_per_student: 10,
+        seed: 42,
+        profile: Profile::named("registration-day").unwrap(),
+        faults: Faults::default(),
+        concurrency: NonZeroUsize::new(1),
+        record: Some(trace.clone()),
+    };
+
+    run_sim(db.clone(), config, future::pending())
+        .await
+        .unwrap();
+
+    let entries = read_trace(&trace).unwrap();
+
+    assert_eq!(entries.len(), 100);
+    assert_eq!(replay(db, entries).await.unwrap(), 100);
+}
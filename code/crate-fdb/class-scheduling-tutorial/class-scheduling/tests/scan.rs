@@ -0,0 +1,194 @@
+// These tests scan `MemoryDatabase`, and do not need a FoundationDB
+// cluster.
+
+use class_scheduling::scan::parallel_scan;
+use class_scheduling::storage::{
+    KeyValueStream, KvDatabase, KvTransaction, MemoryDatabase, MemoryTransaction,
+};
+
+use fdb::error::{FdbError, FdbResult};
+use fdb::range::Range;
+use fdb::tuple::Tuple;
+use fdb::{Key, Value};
+
+use bytes::Bytes;
+
+use std::future::{self, Future};
+use std::sync::Arc;
+
+fn key(i: i64) -> Key {
+    // ("scan", i)
+    let mut tup = Tuple::new();
+
+    tup.add_string("scan".to_string());
+    tup.add_i64(i);
+
+    Key::from(tup.pack())
+}
+
+fn scan_range() -> Range {
+    let mut tup = Tuple::new();
+
+    tup.add_string("scan".to_string());
+
+    tup.range(Bytes::new())
+}
+
+// About 3 MB, so that the range is split into a few shards, each of
+// them read in a few transactions.
+async fn write_keys(db: &MemoryDatabase) -> Vec<Key> {
+    let keys = (0..3_000).map(key).collect::<Vec<Key>>();
+
+    for chunk in keys.chunks(500) {
+        db.run(|tr| async move {
+            for key in chunk {
+                tr.set(key.clone(), Bytes::from(vec![0; 1_000]));
+            }
+
+            Ok(())
+        })
+        .await
+        .unwrap();
+    }
+
+    keys
+}
+
+#[tokio::test]
+async fn every_key_once_in_order() {
+    let db = MemoryDatabase::new();
+
+    let keys = write_keys(&db).await;
+
+    // Keys around the range, which are not scanned.
+    db.run(|tr| async move {
+        tr.set(Key::from(Bytes::from_static(b"\x02scal\x00")), Bytes::new());
+        tr.set(Key::from(Bytes::from_static(b"\x02scao\x00")), Bytes::new());
+
+        Ok(())
+    })
+    .await
+    .unwrap();
+
+    let shards = parallel_scan(&db, scan_range(), |shard_keys: &mut Vec<Key>, key, _| {
+        shard_keys.push(key)
+    })
+    .await
+    .unwrap();
+
+    assert!(shards.len() > 1, "{} shards", shards.len());
+
+    assert_eq!(shards.concat(), keys);
+}
+
+// Reading a range that begins at or after `fail_from` fails, and
+// reading any other range never returns, holding on to a clone of
+// `pending` until it is dropped.
+#[derive(Clone)]
+struct FailingDatabase {
+    inner: MemoryDatabase,
+    fail_from: Bytes,
+    pending: Arc<()>,
+}
+
+struct FailingTransaction {
+    inner: MemoryTransaction,
+    fail_from: Bytes,
+    pending: Arc<()>,
+}
+
+impl KvTransaction for FailingTransaction {
+    fn get(&self, key: impl Into<Key>) -> impl Future<Output = FdbResult<Option<Value>>> + Send {
+        self.inner.get(key)
+    }
+
+    fn get_range(&self, range: Range) -> KeyValueStream<'_> {
+        if Bytes::from(range.begin().clone()) >= self.fail_from {
+            Box::pin(tokio_stream::once(Err(FdbError::new(1))))
+        } else {
+            let pending = self.pending.clone();
+
+            Box::pin(futures::stream::once(async move {
+                let _pending = pending;
+
+                future::pending::<FdbResult<(Key, Value)>>().await
+            }))
+        }
+    }
+
+    fn get_read_version(&self) -> impl Future<Output = FdbResult<i64>> + Send {
+        self.inner.get_read_version()
+    }
+
+    fn get_range_split_points(
+        &self,
+        range: Range,
+        chunk_size: i64,
+    ) -> impl Future<Output = FdbResult<Vec<Key>>> + Send {
+        self.inner.get_range_split_points(range, chunk_size)
+    }
+
+    fn set(&self, key: impl Into<Key>, value: impl Into<Value>) {
+        self.inner.set(key, value)
+    }
+
+    fn clear(&self, key: impl Into<Key>) {
+        self.inner.clear(key)
+    }
+
+    fn clear_range(&self, range: Range) {
+        self.inner.clear_range(range)
+    }
+}
+
+impl KvDatabase for FailingDatabase {
+    type Transaction = FailingTransaction;
+
+    async fn run<T, F, Fut>(&self, mut f: F) -> FdbResult<T>
+    where
+        T: Send,
+        F: FnMut(FailingTransaction) -> Fut + Send,
+        Fut: Future<Output = FdbResult<T>> + Send,
+    {
+        self.inner
+            .run(|tr| {
+                f(FailingTransaction {
+                    inner: tr,
+                    fail_from: self.fail_from.clone(),
+                    pending: self.pending.clone(),
+                })
+            })
+            .await
+    }
+}
+
+// When a shard fails, the scan returns its error, and the tasks of the
+// other shards are aborted rather than left running.
+#[tokio::test]
+async fn failed_shard_aborts_others() {
+    let db = FailingDatabase {
+        inner: MemoryDatabase::new(),
+        fail_from: Bytes::from(key(1_500)),
+        pending: Arc::new(()),
+    };
+
+    write_keys(&db.inner).await;
+
+    let err = parallel_scan(&db, scan_range(), |_: &mut (), _, _| {})
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), 1);
+
+    // Aborted tasks are dropped the next time the runtime gets to
+    // them.
+    for _ in 0..100 {
+        if Arc::strong_count(&db.pending) == 1 {
+            break;
+        }
+
+        tokio::task::yield_now().await;
+    }
+
+    assert_eq!(Arc::strong_count(&db.pending), 1);
+}
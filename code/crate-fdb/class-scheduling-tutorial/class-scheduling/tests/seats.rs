@@ -0,0 +1,187 @@
+// Checks the pieces of the seat changes stream that don't need a
+// FoundationDB cluster: reading the seat change log on
+// `MemoryDatabase`, and the resume tokens.
+
+use class_scheduling::seats::{
+    decode_seats_token, encode_seats_token, read_seat_changes, SEAT_CHANGES_TRIMMED,
+};
+use class_scheduling::storage::{KvDatabase, MemoryDatabase};
+use class_scheduling::{
+    dropout, init, signup, Class, Student, Timestamp, NO_SUCH_CLASS, SEAT_CHANGES_KEPT,
+};
+
+use fdb::error::FdbResult;
+
+fn class_names() -> Vec<Class> {
+    ["10:00 chem 201", "11:00 chem 201", "12:00 chem 201"]
+        .iter()
+        .map(|class_inner| Class(class_inner.to_string()))
+        .collect()
+}
+
+async fn seat_changes(
+    db: &MemoryDatabase,
+    versions: Option<&[i64]>,
+) -> FdbResult<(Vec<(Class, u8)>, Vec<i64>)> {
+    db.run(|tr| async move { read_seat_changes(&tr, &class_names(), versions).await })
+        .await
+}
+
+async fn signup_and_dropout(db: &MemoryDatabase, class_name: &Class) {
+    db.run(|tr| {
+        let class_name = class_name.clone();
+        async move {
+            signup(
+                &tr,
+                Student("s0".to_string()),
+                class_name.clone(),
+                Timestamp::now(),
+            )
+            .await?;
+
+            dropout(&tr, Student("s0".to_string()), class_name, Timestamp::now()).await
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn first_changes() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let class_names = class_names();
+
+    let (changes, versions) = seat_changes(&db, None).await.unwrap();
+
+    assert_eq!(
+        changes,
+        vec![
+            (class_names[0].clone(), 100),
+            (class_names[1].clone(), 100),
+            (class_names[2].clone(), 100)
+        ]
+    );
+
+    // Nothing changed since.
+    assert_eq!(
+        seat_changes(&db, Some(&versions)).await.unwrap(),
+        (vec![], versions)
+    );
+}
+
+// A change that is undone is sent too, and so are the changes of a
+// class that was initialized again.
+#[tokio::test]
+async fn later_changes() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let class_names = class_names();
+
+    let (_, versions) = seat_changes(&db, None).await.unwrap();
+
+    signup_and_dropout(&db, &class_names[1]).await;
+
+    let (changes, later_versions) = seat_changes(&db, Some(&versions)).await.unwrap();
+
+    assert_eq!(
+        changes,
+        vec![(class_names[1].clone(), 99), (class_names[1].clone(), 100)]
+    );
+
+    assert_eq!(
+        later_versions,
+        vec![versions[0], versions[1] + 2, versions[2]]
+    );
+
+    init(&db).await.unwrap();
+
+    let (changes, _) = seat_changes(&db, Some(&later_versions)).await.unwrap();
+
+    assert_eq!(changes.len(), 3);
+    assert!(changes.iter().all(|(_, seats_left)| *seats_left == 100));
+}
+
+// Changes older than the last `SEAT_CHANGES_KEPT` are gone.
+#[tokio::test]
+async fn trimmed_changes() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    let class_names = class_names();
+
+    let (_, versions) = seat_changes(&db, None).await.unwrap();
+
+    for _ in 0..SEAT_CHANGES_KEPT / 2 {
+        signup_and_dropout(&db, &class_names[0]).await;
+    }
+
+    let (changes, _) = seat_changes(&db, Some(&versions)).await.unwrap();
+
+    assert_eq!(changes.len() as i64, SEAT_CHANGES_KEPT);
+
+    signup_and_dropout(&db, &class_names[0]).await;
+
+    let err = seat_changes(&db, Some(&versions)).await.unwrap_err();
+
+    assert_eq!(err.code(), SEAT_CHANGES_TRIMMED);
+
+    // A version that is not there yet.
+    let err = seat_changes(&db, Some(&[versions[0], versions[1] + 1, versions[2]]))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), SEAT_CHANGES_TRIMMED);
+}
+
+#[tokio::test]
+async fn no_such_class() {
+    let db = MemoryDatabase::new();
+
+    let err = seat_changes(&db, None).await.unwrap_err();
+
+    assert_eq!(err.code(), NO_SUCH_CLASS);
+}
+
+#[test]
+fn token_round_trip() {
+    for versions in [vec![], vec![0], vec![100, 5, 0], vec![i64::MAX, 16, 15]] {
+        let token = encode_seats_token(&versions);
+
+        assert_eq!(decode_seats_token(&token, versions.len()), Some(versions));
+    }
+
+    assert_eq!(encode_seats_token(&[100, 5, 0]), "100.5.0");
+}
+
+#[test]
+fn malformed_token() {
+    for token in [
+        "100.5.",
+        "100..0",
+        "100.zz.0",
+        "100.5é.0",
+        "100. 5.0",
+        "-100.5.0",
+        "+100.5.0",
+        "9223372036854775808.5.0",
+    ] {
+        assert_eq!(decode_seats_token(token, 3), None, "{}", token);
+    }
+}
+
+// A token from a stream of a different set of classes.
+#[test]
+fn token_length_mismatch() {
+    let token = encode_seats_token(&[100, 5, 0]);
+
+    assert_eq!(decode_seats_token(&token, 2), None);
+    assert_eq!(decode_seats_token(&token, 4), None);
+    assert_eq!(decode_seats_token("", 1), None);
+    assert_eq!(decode_seats_token("0", 0), None);
+}
@@ -0,0 +1,265 @@
+// Checks the latency percentiles and the reports of the simulation
+// stats. They run the deterministic simulation where they need stats
+// with operations in them, and do not need a FoundationDB cluster.
+
+use class_scheduling::sim::{
+    run_deterministic, Faults, LatencyHistogram, Profile, SimConfig, SimStats,
+};
+
+use std::time::Duration;
+
+const PERCENTILES: [u32; 3] = [50, 95, 99];
+
+const MOODS: [&str; 4] = ["add", "dropout", "switch", "browse"];
+
+// A latency as reported is off by less than 1/64 of it.
+fn assert_close(reported: Duration, latency: Duration) {
+    let diff = reported.as_nanos().abs_diff(latency.as_nanos());
+
+    assert!(
+        diff * 64 < latency.as_nanos().max(1),
+        "{:?} is not close to {:?}",
+        reported,
+        latency
+    );
+}
+
+#[test]
+fn empty_histogram() {
+    let latencies = LatencyHistogram::default();
+
+    assert!(latencies.is_empty());
+
+    for percentile in [0, 50, 100] {
+        assert_eq!(latencies.percentile(percentile), Duration::ZERO);
+    }
+}
+
+#[test]
+fn single_latency() {
+    for latency in [
+        Duration::ZERO,
+        Duration::from_nanos(100),
+        Duration::from_micros(1234),
+        Duration::from_secs(3600),
+    ] {
+        let mut latencies = LatencyHistogram::default();
+
+        latencies.record(latency);
+
+        assert_eq!(latencies.len(), 1);
+
+        for percentile in [0, 50, 99, 100] {
+            assert_eq!(latencies.percentile(percentile), latency);
+        }
+    }
+}
+
+#[test]
+fn percentiles() {
+    let mut latencies = LatencyHistogram::default();
+
+    // 1ms to 1000ms, in reverse so that the order they are recorded in
+    // doesn't help.
+    for ms in (1..=1000).rev() {
+        latencies.record(Duration::from_millis(ms));
+    }
+
+    assert_eq!(latencies.len(), 1000);
+
+    for percentile in [1, 50, 95, 99] {
+        assert_close(
+            latencies.percentile(percentile),
+            Duration::from_millis(u64::from(percentile) * 10),
+        );
+    }
+
+    assert_close(latencies.percentile(0), Duration::from_millis(1));
+    assert_eq!(latencies.percentile(100), Duration::from_millis(1000));
+
+    // Nanoseconds below 128 are exact.
+    let mut latencies = LatencyHistogram::default();
+
+    for nanos in 0..100 {
+        latencies.record(Duration::from_nanos(nanos));
+    }
+
+    assert_eq!(latencies.percentile(50), Duration::from_nanos(49));
+}
+
+#[test]
+fn merge() {
+    let mut all = LatencyHistogram::default();
+
+    let mut even = LatencyHistogram::default();
+
+    let mut odd = LatencyHistogram::default();
+
+    for ms in 1..=200 {
+        let latency = Duration::from_millis(ms);
+
+        all.record(latency);
+
+        if ms % 2 == 0 {
+            even.record(latency);
+        } else {
+            odd.record(latency);
+        }
+    }
+
+    let mut merged = LatencyHistogram::default();
+
+    merged.merge(&even);
+    merged.merge(&LatencyHistogram::default());
+    merged.merge(&odd);
+
+    assert_eq!(merged.len(), all.len());
+
+    for percentile in [0, 1, 50, 95, 99, 100] {
+        assert_eq!(merged.percentile(percentile), all.percentile(percentile));
+    }
+}
+
+fn sim_stats() -> SimStats {
+    let config = SimConfig {
+        students: 10,
+        ops_per_student: 10,
+        seed: 42,
+        profile: Profile::named("hot-spot").unwrap(),
+        faults: Faults::default(),
+        concurrency: None,
+        record: None,
+    };
+
+    run_deterministic(config, 0.1).unwrap()
+}
+
+#[test]
+fn empty_reports() {
+    let stats = SimStats::default();
+
+    let csv = stats.to_csv();
+
+    let rows = csv.lines().collect::<Vec<_>>();
+
+    assert_eq!(rows.len(), 1 + MOODS.len());
+
+    for (row, mood) in rows[1..].iter().zip(MOODS) {
+        assert_eq!(*row, format!("{},,0,0,0,0,0,0,0,0", mood));
+    }
+
+    let summary = stats.summary();
+
+    assert_eq!(summary.lines().count(), 1 + MOODS.len() + 1);
+    assert!(summary.contains("total         0 in 0.00s, 0.0 ops/s"));
+
+    assert_eq!(stats.to_json()["ops"], 0);
+}
+
+// The CSV has the same numbers as the JSON, one row per mood followed
+// by a row per error code of the mood.
+#[test]
+fn csv() {
+    let stats = sim_stats();
+
+    let json = stats.to_json();
+
+    let csv = stats.to_csv();
+
+    let mut rows = csv.lines();
+
+    assert_eq!(
+        rows.next(),
+        Some("mood,error_code,ops,commits,errors,retries,ops_per_sec,p50_ms,p95_ms,p99_ms")
+    );
+
+    let mut ops = 0;
+
+    for mood in MOODS {
+        let mood_json = &json["moods"][mood];
+
+        let row = rows.next().unwrap().split(',').collect::<Vec<_>>();
+
+        assert_eq!(row[..2], [mood, ""]);
+
+        for (field, name) in row[2..6]
+            .iter()
+            .zip(["ops", "commits", "errors", "retries"])
+        {
+            let value = if name == "errors" {
+                mood_json["errors"]
+                    .as_object()
+                    .unwrap()
+                    .values()
+                    .map(|count| count.as_u64().unwrap())
+                    .sum::<u64>()
+            } else {
+                mood_json[name].as_u64().unwrap()
+            };
+
+            assert_eq!(field.parse::<u64>().unwrap(), value, "{} {}", mood, name);
+        }
+
+        ops += mood_json["ops"].as_u64().unwrap();
+
+        let latencies_ms = row[7..]
+            .iter()
+            .map(|field| field.parse::<f64>().unwrap())
+            .collect::<Vec<_>>();
+
+        for (latency_ms, percentile) in latencies_ms.iter().zip(PERCENTILES) {
+            assert_eq!(
+                *latency_ms,
+                mood_json["latency_ms"][format!("p{}", percentile)]
+                    .as_f64()
+                    .unwrap()
+            );
+        }
+
+        assert!(latencies_ms.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        for (code, count) in mood_json["errors"].as_object().unwrap() {
+            assert_eq!(
+                rows.next(),
+                Some(format!("{},{},,,{},,,,,", mood, code, count).as_str())
+            );
+        }
+    }
+
+    assert_eq!(rows.next(), None);
+
+    assert_eq!(ops, 100);
+}
+
+#[test]
+fn summary() {
+    let stats = sim_stats();
+
+    let summary = stats.summary();
+
+    let mut lines = summary.lines();
+
+    assert!(lines.next().unwrap().starts_with("mood"));
+
+    for mood in MOODS {
+        let line = lines.next().unwrap();
+
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        assert_eq!(fields.len(), 9, "{}", line);
+        assert_eq!(fields[0], mood);
+        assert_eq!(
+            fields[1].parse::<u64>().unwrap(),
+            stats.to_json()["moods"][mood]["ops"].as_u64().unwrap()
+        );
+    }
+
+    let total = lines.next().unwrap().split_whitespace().collect::<Vec<_>>();
+
+    assert_eq!(total[..3], ["total", "100", "in"]);
+
+    // The rest are errors.
+    for line in lines {
+        assert!(line.contains(" error "), "{}", line);
+    }
+}
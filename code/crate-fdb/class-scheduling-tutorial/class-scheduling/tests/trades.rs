@@ -0,0 +1,207 @@
+// These tests trade seats between students on `MemoryDatabase`, and do
+// not need a FoundationDB cluster.
+
+mod common;
+
+use common::{class, schedule, seats_left, student};
+
+use class_scheduling::storage::{KvDatabase, MemoryDatabase, NOT_COMMITTED};
+use class_scheduling::{
+    dropout, init, signup, signup_bundle, trade_seats, Timestamp, ALREADY_SIGNED_UP,
+    INCOMPLETE_BUNDLE, NOT_SIGNED_UP, TIME_CONFLICT,
+};
+
+use fdb::error::FdbResult;
+
+async fn signup_for(db: &MemoryDatabase, student_inner: &str, class_name: &str) {
+    db.run(|tr| async move {
+        signup(
+            &tr,
+            student(student_inner),
+            class(class_name),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await
+    .unwrap();
+}
+
+async fn trade(
+    db: &MemoryDatabase,
+    (student_a, class_a): (&str, &str),
+    (student_b, class_b): (&str, &str),
+) -> FdbResult<()> {
+    db.run(|tr| async move {
+        trade_seats(
+            &tr,
+            student(student_a),
+            class(class_a),
+            student(student_b),
+            class(class_b),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await
+}
+
+#[tokio::test]
+async fn trade_classes() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    signup_for(&db, "s0", "10:00 chem 201").await;
+    signup_for(&db, "s1", "11:00 bio 201").await;
+
+    trade(&db, ("s0", "10:00 chem 201"), ("s1", "11:00 bio 201"))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        schedule(&db, &student("s0")).await,
+        vec![class("11:00 bio 201")]
+    );
+    assert_eq!(
+        schedule(&db, &student("s1")).await,
+        vec![class("10:00 chem 201")]
+    );
+
+    // Trading takes no seats.
+    assert_eq!(seats_left(&db, &class("10:00 chem 201")).await, 99);
+    assert_eq!(seats_left(&db, &class("11:00 bio 201")).await, 99);
+}
+
+#[tokio::test]
+async fn refused_trades() {
+    let db = MemoryDatabase::new();
+
+    init(&db).await.unwrap();
+
+    signup_for(&db, "s0", "10:00 chem 201").await;
+    signup_for(&db, "s0", "11:00 art 201").await;
+    signup_for(&db, "s1", "11:00 bio 201").await;
+    signup_for(&db, "s1", "10:00 chem 201").await;
+
+    // s1 already attends the class s0 gives up.
+    let err = trade(&db, ("s0", "10:00 chem 201"), ("s1", "11:00 bio 201"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), ALREADY_SIGNED_UP);
+
+    // s0 does not attend the class.
+    let err = trade(&db, ("s0", "12:00 chem 201"), ("s1", "11:00 bio 201"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), NOT_SIGNED_UP);
+
+    // s0 has a class at 11:00 already.
+    db.run(|tr| async move {
+        dropout(
+            &tr,
+            student("s1"),
+            class("10:00 chem 201"),
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await
+    .unwrap();
+
+    let err = trade(&db, ("s0", "10:00 chem 201"), ("s1", "11:00 bio 201"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), TIME_CONFLICT);
+
+    // A class of a bundle is not traded on its own.
+    db.run(|tr| async move {
+        signup_bundle(
+            &tr,
+            student("s1"),
+            vec![class("2:00 cs 101"), class("3:00 cs lab")],
+            Timestamp::now(),
+        )
+        .await
+    })
+    .await
+    .unwrap();
+
+    let err = trade(&db, ("s0", "10:00 chem 201"), ("s1", "2:00 cs 101"))
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.code(), INCOMPLETE_BUNDLE);
+
+    assert_eq!(
+        schedule(&db, &student("s0")).await,
+        vec![class("10:00 chem 201"), class("11:00 art 201")]
+    );
+    assert_eq!(
+        schedule(&db, &student("s1")).await,
+        vec![
+            class("11:00 bio 201"),
+            class("2:00 cs 101"),
+            class("3:00 cs lab")
+        ]
+    );
+}
+
+// A trade is only committed if neither student changed their classes
+// since the trade read them. When it runs again, the class that was
+// dropped is no longer there to trade.
+#[tokio::test]
+async fn trade_after_change() {
+    for (dropping_student, dropped_class) in [("s0", "10:00 chem 201"), ("s1", "11:00 bio 201")] {
+        let db = MemoryDatabase::new();
+
+        init(&db).await.unwrap();
+
+        signup_for(&db, "s0", "10:00 chem 201").await;
+        signup_for(&db, "s1", "11:00 bio 201").await;
+
+        let tr = db.create_transaction();
+
+        trade_seats(
+            &tr,
+            student("s0"),
+            class("10:00 chem 201"),
+            student("s1"),
+            class("11:00 bio 201"),
+            Timestamp::now(),
+        )
+        .await
+        .unwrap();
+
+        db.run(|tr| async move {
+            dropout(
+                &tr,
+                student(dropping_student),
+                class(dropped_class),
+                Timestamp::now(),
+            )
+            .await
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(tr.commit().unwrap_err().code(), NOT_COMMITTED);
+
+        let err = trade(&db, ("s0", "10:00 chem 201"), ("s1", "11:00 bio 201"))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.code(), NOT_SIGNED_UP);
+
+        // Nobody got the class that was given up.
+        assert_eq!(seats_left(&db, &class(dropped_class)).await, 100);
+
+        let other_student = if dropping_student == "s0" { "s1" } else { "s0" };
+
+        assert_eq!(schedule(&db, &student(dropping_student)).await, vec![]);
+        assert_eq!(schedule(&db, &student(other_student)).await.len(), 1);
+    }
+}
@@ -0,0 +1,16 @@
+// These tests start `fdbserver`, which needs to be installed. Run them
+// with `cargo test -- --ignored`.
+
+use test_support::cluster;
+
+#[test]
+#[ignore = "needs fdbserver"]
+fn hello_world() {
+    let output = cluster()
+        .command(env!("CARGO_BIN_EXE_hello-world"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Hello world\n");
+}
@@ -0,0 +1,17 @@
+// These tests start `fdbserver`, which needs to be installed. Run them
+// with `cargo test -- --ignored`.
+
+use test_support::cluster;
+
+// The example starts the client network, opens the database and stops
+// the network again, so it only needs to exit cleanly.
+#[test]
+#[ignore = "needs fdbserver"]
+fn starts_and_stops_network() {
+    let output = cluster()
+        .command(env!("CARGO_BIN_EXE_client-network-thread"))
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+}
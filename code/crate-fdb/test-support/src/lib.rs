@@ -0,0 +1,256 @@
+// Starts a throwaway `fdbserver` for end-to-end tests, so that they do
+// not need a cluster set up by hand, pointed to by `FDB_CLUSTER_FILE`.
+//
+// The first call to `cluster` in a test binary starts `fdbserver` on a
+// random port, with a temporary data directory and cluster file, and
+// creates a new single-process, in-memory database on it. The server
+// keeps running until the test binary exits, and then it is stopped
+// and its directory removed. Tests that use keys outside of a
+// subspace, such as the keys of the class scheduling tutorial, start a
+// cluster of their own with `TestCluster::start` instead, which is
+// stopped when it is dropped. `fdbserver` and `fdbcli` are looked up
+// on `PATH`, unless `FDBSERVER` and `FDBCLI` are set to their paths.
+
+use bytes::{BufMut, Bytes, BytesMut};
+
+use fdb::database::FdbDatabase;
+use fdb::range::Range;
+use fdb::tuple::Tuple;
+use fdb::Key;
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Once, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long `fdbserver` gets to come up with a database.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Runs `fdbserver` with the arguments it is given, until its stdin is
+// closed, which happens when the test binary exits, however it exits.
+// Then it stops the server and removes its directory.
+const SUPERVISOR: &str = r#"
+"$0" "$@" &
+server=$!
+cat > /dev/null
+kill "$server"
+wait "$server"
+rm -rf "$FDB_TEST_DIR"
+"#;
+
+static CLUSTER: OnceLock<TestCluster> = OnceLock::new();
+
+// The client network can only be started once in a process, and cannot
+// be started again after `fdb::stop_network`, so it is left running
+// until the test binary exits.
+static START_NETWORK: Once = Once::new();
+
+pub struct TestCluster {
+    cluster_file: PathBuf,
+    database: OnceLock<FdbDatabase>,
+    next_subspace: AtomicU64,
+    // Holds the write end of the supervisor's stdin.
+    _supervisor: Child,
+}
+
+// Returns the cluster of this test binary, and starts it the first
+// time it is called.
+pub fn cluster() -> &'static TestCluster {
+    CLUSTER.get_or_init(|| TestCluster::start().expect("cannot start fdbserver"))
+}
+
+impl TestCluster {
+    // Starts a cluster that only the caller uses, and that is stopped
+    // when it is dropped or the test binary exits.
+    pub fn start() -> io::Result<TestCluster> {
+        let fdbserver = env::var_os("FDBSERVER").unwrap_or_else(|| OsString::from("fdbserver"));
+
+        // Fail early, rather than wait for a server that never comes up.
+        Command::new(&fdbserver)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .status()?;
+
+        let dir = tempfile::Builder::new()
+            .prefix("fdb-test-")
+            .tempdir()?
+            .keep();
+
+        let data_dir = dir.join("data");
+        let log_dir = dir.join("logs");
+
+        fs::create_dir(&data_dir)?;
+        fs::create_dir(&log_dir)?;
+
+        // There is a small window in which another process could take
+        // the port, after we let go of it and before `fdbserver` binds
+        // it. Startup then times out.
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+        let address = format!("127.0.0.1:{}", port);
+
+        let cluster_file = dir.join("fdb.cluster");
+
+        fs::write(
+            &cluster_file,
+            format!("test:test{}@{}\n", process::id(), address),
+        )?;
+
+        let supervisor = Command::new("sh")
+            .arg("-c")
+            .arg(SUPERVISOR)
+            .arg(&fdbserver)
+            .arg("--public_address")
+            .arg(&address)
+            .arg("--listen_address")
+            .arg(&address)
+            .arg("--cluster_file")
+            .arg(&cluster_file)
+            .arg("--datadir")
+            .arg(&data_dir)
+            .arg("--logdir")
+            .arg(&log_dir)
+            .env("FDB_TEST_DIR", &dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        let cluster = TestCluster {
+            cluster_file,
+            database: OnceLock::new(),
+            next_subspace: AtomicU64::new(0),
+            _supervisor: supervisor,
+        };
+
+        // If this fails, dropping `cluster` stops the server.
+        cluster.wait_for_database()?;
+
+        Ok(cluster)
+    }
+
+    fn wait_for_database(&self) -> io::Result<()> {
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+
+        let mut configured = false;
+
+        loop {
+            // An attempt that timed out may still have created the
+            // database.
+            if !configured {
+                let output = self.fdbcli("configure new single memory")?;
+
+                configured = output.status.success()
+                    || String::from_utf8_lossy(&output.stdout).contains("Database already exists");
+            }
+
+            if configured {
+                let output = self.fdbcli("status minimal")?;
+
+                if String::from_utf8_lossy(&output.stdout).contains("The database is available") {
+                    return Ok(());
+                }
+            }
+
+            if Instant::now() > deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "database did not become available",
+                ));
+            }
+
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    fn fdbcli(&self, command: &str) -> io::Result<Output> {
+        let fdbcli = env::var_os("FDBCLI").unwrap_or_else(|| OsString::from("fdbcli"));
+
+        Command::new(fdbcli)
+            .arg("--cluster-file")
+            .arg(&self.cluster_file)
+            .arg("--timeout")
+            .arg("5")
+            .arg("--exec")
+            .arg(command)
+            .output()
+    }
+
+    pub fn cluster_file(&self) -> &Path {
+        &self.cluster_file
+    }
+
+    // Opens the database, and starts the client network of this test
+    // binary the first time it is called.
+    pub fn database(&self) -> FdbDatabase {
+        self.database
+            .get_or_init(|| {
+                START_NETWORK.call_once(|| unsafe {
+                    fdb::select_api_version(fdb::FDB_API_VERSION as i32);
+                    fdb::start_network();
+                });
+
+                fdb::open_database(&self.cluster_file).expect("cannot open database")
+            })
+            .clone()
+    }
+
+    // Returns a command that runs `program` with `FDB_CLUSTER_FILE`
+    // pointing to this cluster, for testing binaries.
+    pub fn command<S: AsRef<OsStr>>(&self, program: S) -> Command {
+        let mut command = Command::new(program);
+
+        command.env("FDB_CLUSTER_FILE", &self.cluster_file);
+
+        command
+    }
+
+    // Returns a subspace that no other test uses. `name` only makes
+    // the keys easier to tell apart, two subspaces with the same name
+    // are still distinct.
+    pub fn subspace(&self, name: &str) -> TestSubspace {
+        let id = self.next_subspace.fetch_add(1, Ordering::Relaxed);
+
+        // ("test", name, id)
+        let prefix = {
+            let mut tup = Tuple::new();
+
+            tup.add_string("test".to_string());
+            tup.add_string(name.to_string());
+            tup.add_i64(id as i64);
+
+            tup
+        };
+
+        TestSubspace { prefix }
+    }
+}
+
+// Keys under a prefix of their own, so that tests that run at the
+// same time do not see each other's keys.
+pub struct TestSubspace {
+    prefix: Tuple,
+}
+
+impl TestSubspace {
+    // Returns the key for `tup` within the subspace.
+    pub fn pack(&self, tup: &Tuple) -> Key {
+        let mut key = BytesMut::new();
+
+        key.put(self.prefix.pack());
+        key.put(tup.pack());
+
+        Key::from(key.freeze())
+    }
+
+    // Returns the range of all keys within the subspace.
+    pub fn range(&self) -> Range {
+        self.prefix.range(Bytes::new())
+    }
+}
@@ -0,0 +1,63 @@
+// These tests start `fdbserver`, which needs to be installed. Run them
+// with `cargo test -- --ignored`.
+
+use bytes::Bytes;
+
+use fdb::range::RangeOptions;
+use fdb::transaction::Transaction;
+use fdb::tuple::Tuple;
+
+use tokio_stream::StreamExt;
+
+use test_support::cluster;
+
+#[tokio::test]
+#[ignore = "needs fdbserver"]
+async fn subspaces_are_isolated() {
+    let db = cluster().database();
+
+    let subspace = cluster().subspace("isolated");
+    let other_subspace = cluster().subspace("isolated");
+
+    let key = {
+        let mut tup = Tuple::new();
+
+        tup.add_string("hello".to_string());
+
+        tup
+    };
+
+    db.run(|tr| {
+        let (key, other_key) = (subspace.pack(&key), other_subspace.pack(&key));
+
+        async move {
+            tr.set(key, Bytes::from_static(b"world"));
+            tr.set(other_key, Bytes::from_static(b"other world"));
+
+            Ok(())
+        }
+    })
+    .await
+    .unwrap();
+
+    let values = db
+        .run(|tr| {
+            let range = subspace.range();
+
+            async move {
+                let mut values = Vec::new();
+
+                let mut range_stream = range.into_stream(&tr, RangeOptions::default());
+
+                while let Some(key_value) = range_stream.next().await {
+                    values.push(Bytes::from(key_value?.into_value()));
+                }
+
+                Ok(values)
+            }
+        })
+        .await
+        .unwrap();
+
+    assert_eq!(values, vec![Bytes::from_static(b"world")]);
+}